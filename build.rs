@@ -0,0 +1,16 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    // Generates the C header into OUT_DIR; a test in src/ffi.rs checks that
+    // the checked-in include/chess_bot.h matches it
+    #[cfg(feature = "ffi")]
+    {
+        let crate_dir = std::env::var("CARGO_MANIFEST_DIR").expect("Cargo sets the manifest dir");
+        let out_dir = std::env::var("OUT_DIR").expect("Cargo sets the out dir");
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        cbindgen::generate(&crate_dir)
+            .expect("Failed to generate the C header")
+            .write_to_file(format!("{out_dir}/chess_bot.h"));
+    }
+}
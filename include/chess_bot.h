@@ -0,0 +1,135 @@
+#ifndef CHESS_BOT_H
+#define CHESS_BOT_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Outcome of a call into the engine.
+ */
+typedef enum ChessBotStatus {
+  ChessBotStatus_Ok = 0,
+  /**
+   * The search is still running; poll again later.
+   */
+  ChessBotStatus_Pending = 1,
+  /**
+   * A null handle, unreadable string, invalid FEN or illegal move.
+   */
+  ChessBotStatus_InvalidArgument = -1,
+  /**
+   * A search is running; stop it and poll for its result first.
+   */
+  ChessBotStatus_Busy = -2,
+  /**
+   * Nothing has been searched since the position was set.
+   */
+  ChessBotStatus_NoResult = -3,
+  /**
+   * The search failed, e.g. because the position has no legal moves.
+   */
+  ChessBotStatus_SearchFailed = -4,
+} ChessBotStatus;
+
+/**
+ * Engine handle for C hosts. Searches run on a thread of their own, so the
+ * host polls for the result instead of blocking.
+ */
+typedef struct ChessBotEngine ChessBotEngine;
+
+/**
+ * A finished search.
+ */
+typedef struct ChessBotResult {
+  /**
+   * Best move in UCI notation, e.g. "e2e4" or "e7e8q", NUL terminated.
+   */
+  char best_move[8];
+  /**
+   * Centipawns from the side to move's point of view. For a forced mate
+   * this is a huge number that only tells quicker mates from slower ones;
+   * read `mate` instead.
+   */
+  int64_t score;
+  /**
+   * Moves until a forced mate: positive if the side to move mates,
+   * negative if it gets mated, 0 if no mate was found.
+   */
+  int64_t mate;
+  uint64_t depth;
+  uint64_t nodes;
+} ChessBotResult;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates an engine set up with the initial position. Free it with [`chess_bot_free`].
+ */
+struct ChessBotEngine *chess_bot_new(void);
+
+/**
+ * Stops a running search and frees the engine.
+ *
+ * # Safety
+ *
+ * `engine` must be null or come from [`chess_bot_new`], and must not be used afterwards.
+ */
+void chess_bot_free(struct ChessBotEngine *engine);
+
+/**
+ * Sets up the position from a FEN, or the initial position if `fen` is null
+ * or "startpos", followed by space separated UCI moves; `moves` may be null.
+ *
+ * # Safety
+ *
+ * `engine` must be null or a live handle, and `fen` and `moves` null or
+ * NUL-terminated strings.
+ */
+enum ChessBotStatus chess_bot_set_position(struct ChessBotEngine *engine,
+                                           const char *fen,
+                                           const char *moves);
+
+/**
+ * Starts searching the position in the background. The search ends at
+ * `depth` plies or after `movetime_ms`, whichever comes first; 0 leaves a
+ * limit out, and with both left out it runs until [`chess_bot_stop`].
+ *
+ * # Safety
+ *
+ * `engine` must be null or a live handle.
+ */
+enum ChessBotStatus chess_bot_search(struct ChessBotEngine *engine,
+                                     uint32_t depth,
+                                     uint64_t movetime_ms);
+
+/**
+ * Asks a running search to finish; its result is then available through
+ * [`chess_bot_poll`] shortly after.
+ *
+ * # Safety
+ *
+ * `engine` must be null or a live handle.
+ */
+void chess_bot_stop(struct ChessBotEngine *engine);
+
+/**
+ * Writes the result of the last search to `result` once it is done,
+ * returning [`ChessBotStatus::Pending`] until then. Never blocks.
+ *
+ * # Safety
+ *
+ * `engine` must be null or a live handle, and `result` null or writable.
+ */
+enum ChessBotStatus chess_bot_poll(struct ChessBotEngine *engine, struct ChessBotResult *result);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* CHESS_BOT_H */
@@ -0,0 +1,294 @@
+use std::{
+    sync::{Arc, atomic::AtomicBool},
+    time::Duration,
+};
+
+use shakmaty::{
+    CastlingMode, Color, Move, Position,
+    fen::Fen,
+    san::SanPlus,
+    variant::{Variant, VariantPosition},
+};
+
+use crate::controller::SearchController;
+use crate::engine::{SearchFeatures, SearchLimits, iterative_deepening, mate_in_moves};
+use crate::error::Error;
+use crate::eval::{MATE_SCORE, PestoEvaluator};
+use crate::pgn::PgnGame;
+use crate::transposition::TranspositionTable;
+
+/// Scores beyond this many centipawns count as this many when judging a
+/// move, so that picking a slower mate isn't a blunder.
+const MAX_JUDGED_SCORE: i64 = 1000;
+/// Longest line of the movetext, as the PGN export format asks for.
+const MAX_LINE_LENGTH: usize = 79;
+
+/// Centipawns a move has to lose against the engine's choice to be marked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Thresholds {
+    pub inaccuracy: i64,
+    pub mistake: i64,
+    pub blunder: i64,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            inaccuracy: 50,
+            mistake: 100,
+            blunder: 300,
+        }
+    }
+}
+
+impl Thresholds {
+    fn judge(&self, loss: i64) -> Option<Judgement> {
+        if loss >= self.blunder {
+            Some(Judgement::Blunder)
+        } else if loss >= self.mistake {
+            Some(Judgement::Mistake)
+        } else if loss >= self.inaccuracy {
+            Some(Judgement::Inaccuracy)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Judgement {
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl Judgement {
+    /// Numeric annotation glyph: `$6` is "?!", `$2` "?" and `$4` "??".
+    pub fn nag(self) -> u8 {
+        match self {
+            Judgement::Inaccuracy => 6,
+            Judgement::Mistake => 2,
+            Judgement::Blunder => 4,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Judgement::Inaccuracy => "Inaccuracy",
+            Judgement::Mistake => "Mistake",
+            Judgement::Blunder => "Blunder",
+        }
+    }
+}
+
+/// What the engine thinks of one move of the game.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MoveAnalysis {
+    pub played: Move,
+    /// Score after the move from White's point of view.
+    pub score: i64,
+    /// Centipawns lost against the engine's choice, never negative.
+    pub loss: i64,
+    pub judgement: Option<Judgement>,
+    /// Line the engine prefers in the position before the move.
+    pub best_line: Vec<Move>,
+}
+
+/// Analysis of the mainline of a PGN game.
+#[derive(Debug, Clone)]
+pub struct GameAnalysis {
+    pub start: VariantPosition,
+    pub moves: Vec<MoveAnalysis>,
+}
+
+/// Searches every position of `game` for `movetime` and judges each move by
+/// how much worse it scores than the engine's choice.
+pub fn analyze(
+    game: &PgnGame,
+    movetime: Duration,
+    thresholds: &Thresholds,
+) -> Result<GameAnalysis, Error> {
+    let start = match game.tag("FEN") {
+        Some(fen) => {
+            let setup = fen.parse::<Fen>()?.into_setup();
+            VariantPosition::from_setup(Variant::Chess, setup, CastlingMode::Standard)?
+        }
+        None => VariantPosition::new(Variant::Chess),
+    };
+
+    let mut positions = vec![start.clone()];
+    let mut played = Vec::with_capacity(game.moves.len());
+    for san in &game.moves {
+        let position = positions.last().expect("Starts with the initial position");
+        let m = san
+            .parse::<SanPlus>()
+            .ok()
+            .and_then(|san_plus| san_plus.san.to_move(position).ok())
+            .ok_or_else(|| Error::IllegalMove(san.clone()))?;
+        let mut next = position.clone();
+        next.play_unchecked(m);
+        positions.push(next);
+        played.push(m);
+    }
+
+    // Scores are from the side to move's point of view
+    let searches = positions
+        .iter()
+        .map(|position| search(position, movetime))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let moves = played
+        .into_iter()
+        .enumerate()
+        .map(|(index, m)| {
+            let (best_score, best_line) = &searches[index];
+            let score = -searches[index + 1].0;
+            let loss = if best_line.first() == Some(&m) {
+                0
+            } else {
+                (clamp(*best_score) - clamp(score)).max(0)
+            };
+            MoveAnalysis {
+                played: m,
+                score: match positions[index].turn() {
+                    Color::White => score,
+                    Color::Black => -score,
+                },
+                loss,
+                judgement: thresholds.judge(loss),
+                best_line: best_line.clone(),
+            }
+        })
+        .collect();
+
+    Ok(GameAnalysis { start, moves })
+}
+
+/// Score and best line for the side to move; finished games are scored by their outcome.
+fn search(position: &VariantPosition, movetime: Duration) -> Result<(i64, Vec<Move>), Error> {
+    if position.is_game_over() {
+        let score = if position.is_checkmate() {
+            -MATE_SCORE
+        } else {
+            0
+        };
+        return Ok((score, Vec::new()));
+    }
+    let limits = SearchLimits {
+        movetime: Some(movetime),
+        ..SearchLimits::default()
+    };
+    let controller = SearchController::new(Arc::new(AtomicBool::new(true))).with_limits(&limits);
+    let result = iterative_deepening(
+        position,
+        &limits,
+        &PestoEvaluator,
+        SearchFeatures::default(),
+        &controller,
+        &mut TranspositionTable::default(),
+        &mut (),
+    )?;
+    Ok((result.score, result.pv))
+}
+
+fn clamp(score: i64) -> i64 {
+    score.clamp(-MAX_JUDGED_SCORE, MAX_JUDGED_SCORE)
+}
+
+impl GameAnalysis {
+    /// Writes `game` back out as PGN with every move's score as an `[%eval]`
+    /// comment, and the engine's line next to the moves it judged.
+    pub fn annotate(&self, game: &PgnGame) -> String {
+        let mut pgn = String::new();
+        for (name, value) in &game.tags {
+            pgn.push_str(&format!("[{name} \"{value}\"]\n"));
+        }
+        pgn.push_str("[Annotator \"chess_bot\"]\n\n");
+
+        let mut tokens = Vec::new();
+        let mut position = self.start.clone();
+        for analysis in &self.moves {
+            let number = position.fullmoves();
+            // Every move has a comment, so Black's moves need their number too
+            tokens.push(match position.turn() {
+                Color::White => format!("{number}."),
+                Color::Black => format!("{number}..."),
+            });
+            let before = position.clone();
+            tokens.push(
+                SanPlus::from_move_and_play_unchecked(&mut position, analysis.played).to_string(),
+            );
+
+            let mut comment = String::new();
+            if let Some(judgement) = analysis.judgement {
+                tokens.push(format!("${}", judgement.nag()));
+                let mut line_position = before;
+                let line: Vec<String> = analysis
+                    .best_line
+                    .iter()
+                    .map(|&m| {
+                        SanPlus::from_move_and_play_unchecked(&mut line_position, m).to_string()
+                    })
+                    .collect();
+                comment.push_str(&format!(
+                    "{}. Best was {}. ",
+                    judgement.name(),
+                    line.join(" ")
+                ));
+            }
+            comment.push_str(&format!("[%eval {}]", eval_tag(analysis.score)));
+            tokens.push("{".to_owned());
+            tokens.extend(comment.split(' ').map(str::to_owned));
+            tokens.push("}".to_owned());
+        }
+        tokens.push(game.tag("Result").unwrap_or("*").to_owned());
+
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > MAX_LINE_LENGTH {
+                pgn.push_str(&line);
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        pgn.push_str(&line);
+        pgn.push('\n');
+        pgn
+    }
+}
+
+/// A White's-view score as used by `[%eval]`: pawns, or "#N" for mates.
+fn eval_tag(score: i64) -> String {
+    match mate_in_moves(score) {
+        Some(moves) => format!("#{moves}"),
+        None => format!("{:.2}", score as f64 / 100.0),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_analyze_finds_blunder() {
+        let game = PgnGame::parse(
+            "[Event \"Scholar's mate\"]\n[Result \"1-0\"]\n\n1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0",
+        );
+        let analysis = analyze(&game, Duration::from_millis(50), &Thresholds::default()).unwrap();
+        assert_eq!(analysis.moves.len(), 7);
+        assert_eq!(analysis.moves[5].judgement, Some(Judgement::Blunder));
+        assert_eq!(analysis.moves[6].judgement, None);
+        assert_eq!(mate_in_moves(analysis.moves[6].score), Some(0));
+
+        let pgn = analysis.annotate(&game);
+        assert!(pgn.starts_with("[Event \"Scholar's mate\"]\n[Result \"1-0\"]\n"));
+        assert!(pgn.lines().all(|line| line.len() <= MAX_LINE_LENGTH));
+        let movetext = pgn.split_whitespace().collect::<Vec<_>>().join(" ");
+        assert!(movetext.contains("3... Nf6 $4 { Blunder. Best was "));
+        assert!(movetext.ends_with("4. Qxf7# { [%eval #0] } 1-0"));
+    }
+}
@@ -0,0 +1,88 @@
+use std::{
+    sync::{Arc, atomic::AtomicBool},
+    time::Duration,
+};
+
+use shakmaty::{
+    CastlingMode,
+    fen::Fen,
+    variant::{Variant, VariantPosition},
+};
+use web_time::Instant;
+
+use crate::controller::SearchController;
+use crate::engine::{SearchFeatures, SearchLimits, iterative_deepening};
+use crate::error::Error;
+use crate::eval::PestoEvaluator;
+use crate::transposition::TranspositionTable;
+
+/// Depth every bench position is searched to unless told otherwise.
+pub const BENCH_DEPTH: u64 = 6;
+
+/// Openings, middlegames and endgames, so that a change to any part of the
+/// search shows up in the node count.
+const BENCH_FENS: [&str; 8] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+    "r2q1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP1QBPPP/R4RK1 b - - 3 10",
+    "2r2rk1/1bqnbppp/p2ppn2/1p6/3NP3/1BN1BP2/PPPQ2PP/2KR3R w - - 2 14",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1",
+    "8/8/4k3/8/2p5/8/B2K4/8 b - - 0 1",
+];
+
+/// Totals over all bench positions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BenchResult {
+    pub nodes: u64,
+    pub time: Duration,
+}
+
+impl BenchResult {
+    pub fn nps(&self) -> u64 {
+        (self.nodes as u128 * 1000 / self.time.as_millis().max(1)) as u64
+    }
+}
+
+/// Searches every bench position to `depth` with a fresh table, so the node
+/// count only changes when the search does.
+pub fn run(depth: u64) -> Result<BenchResult, Error> {
+    let limits = SearchLimits {
+        depth: Some(depth),
+        ..SearchLimits::default()
+    };
+    let mut nodes = 0;
+    let start = Instant::now();
+    for fen in BENCH_FENS {
+        let setup = fen.parse::<Fen>()?.into_setup();
+        let position = VariantPosition::from_setup(Variant::Chess, setup, CastlingMode::Standard)?;
+        let controller = SearchController::new(Arc::new(AtomicBool::new(true)));
+        let result = iterative_deepening(
+            &position,
+            &limits,
+            &PestoEvaluator,
+            SearchFeatures::default(),
+            &controller,
+            &mut TranspositionTable::default(),
+            &mut (),
+        )?;
+        nodes += result.nodes;
+    }
+    Ok(BenchResult {
+        nodes,
+        time: start.elapsed(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bench_is_deterministic() {
+        let first = run(2).unwrap();
+        assert!(first.nodes > 0);
+        assert_eq!(run(2).unwrap().nodes, first.nodes);
+    }
+}
@@ -0,0 +1,182 @@
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use web_time::Instant;
+
+use crate::engine::SearchLimits;
+
+/// How many nodes pass between two looks at the clock.
+const DEFAULT_POLL_INTERVAL: u64 = 1024;
+/// How often a finished search checks whether it has been stopped yet.
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Decides when a running search has to stop: on request, at a deadline, or
+/// after a node budget. The search itself looks at the clock every few nodes,
+/// there is no timer. Clones share their state, so any clone can stop the search.
+#[derive(Debug, Clone)]
+pub struct SearchController {
+    is_thinking: Arc<AtomicBool>,
+    /// Set while searching on the opponent's time, until "ponderhit".
+    pondering: Arc<AtomicBool>,
+    /// Time and node count at "ponderhit", where a pondering search's clock starts.
+    ponderhit: Arc<Mutex<Option<(Instant, u64)>>>,
+    nodes: Arc<AtomicU64>,
+    /// Shared so that "ponderhit" can start the clock of a running search.
+    deadline: Arc<Mutex<Option<Instant>>>,
+    /// Nodes after which the search stops, `u64::MAX` for no budget. Shared
+    /// like `deadline`, for a budget that starts at "ponderhit".
+    node_budget: Arc<AtomicU64>,
+    poll_interval: u64,
+}
+
+impl SearchController {
+    /// Controls a search through `is_thinking`; clearing the flag from anywhere stops it.
+    pub fn new(is_thinking: Arc<AtomicBool>) -> Self {
+        Self {
+            is_thinking,
+            pondering: Arc::new(AtomicBool::new(false)),
+            ponderhit: Arc::default(),
+            nodes: Arc::new(AtomicU64::new(0)),
+            deadline: Arc::default(),
+            node_budget: Arc::new(AtomicU64::new(u64::MAX)),
+            poll_interval: DEFAULT_POLL_INTERVAL,
+        }
+    }
+
+    pub fn with_deadline(self, deadline: Instant) -> Self {
+        self.set_deadline(Some(deadline));
+        self
+    }
+
+    fn set_deadline(&self, deadline: Option<Instant>) {
+        *self.deadline.lock().expect("Deadline lock poisoned") = deadline;
+    }
+
+    /// Searches on the opponent's time until [`Self::ponderhit`].
+    pub fn with_pondering(self, pondering: bool) -> Self {
+        self.pondering.store(pondering, Ordering::SeqCst);
+        self
+    }
+
+    /// Turns a pondering search into a normal one that has to end by `deadline`,
+    /// or once it has searched `nodes` more nodes.
+    pub fn ponderhit(&self, deadline: Option<Instant>, nodes: Option<u64>) {
+        let nodes_so_far = self.nodes();
+        *self.ponderhit.lock().expect("Ponderhit lock poisoned") =
+            Some((Instant::now(), nodes_so_far));
+        self.set_deadline(deadline);
+        if let Some(nodes) = nodes {
+            // A "nodes" limit from the "go" command still counts from the start
+            self.node_budget
+                .fetch_min(nodes_so_far.saturating_add(nodes), Ordering::SeqCst);
+        }
+        self.pondering.store(false, Ordering::SeqCst);
+    }
+
+    pub fn with_node_budget(self, nodes: u64) -> Self {
+        self.node_budget.store(nodes, Ordering::SeqCst);
+        self
+    }
+
+    /// Applies the node budget and movetime of `limits`, with the clock starting now.
+    pub fn with_limits(mut self, limits: &SearchLimits) -> Self {
+        if let Some(nodes) = limits.nodes {
+            self = self.with_node_budget(nodes);
+        }
+        if let Some(movetime) = limits.movetime {
+            self = self.with_deadline(Instant::now() + movetime);
+        }
+        self
+    }
+
+    /// Checks the clock every `nodes` nodes; lower values stop closer to the deadline.
+    pub fn with_poll_interval(mut self, nodes: u64) -> Self {
+        self.poll_interval = nodes.max(1);
+        self
+    }
+
+    pub fn stop(&self) {
+        self.is_thinking.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        !self.is_thinking.load(Ordering::Relaxed)
+    }
+
+    pub fn is_pondering(&self) -> bool {
+        self.pondering.load(Ordering::Relaxed)
+    }
+
+    /// When "ponderhit" arrived and how many nodes had been searched by then,
+    /// `None` for a search that never pondered.
+    pub fn ponderhit_at(&self) -> Option<(Instant, u64)> {
+        *self.ponderhit.lock().expect("Ponderhit lock poisoned")
+    }
+
+    /// Blocks until someone stops the search. Only for a search that is
+    /// already over, so checking every few milliseconds is plenty.
+    pub fn wait_until_stopped(&self) {
+        while !self.is_stopped() {
+            std::thread::sleep(STOP_POLL_INTERVAL);
+        }
+    }
+
+    /// Blocks while the search is still pondering and hasn't been stopped,
+    /// like [`Self::wait_until_stopped`].
+    pub fn wait_while_pondering(&self) {
+        while self.is_pondering() && !self.is_stopped() {
+            std::thread::sleep(STOP_POLL_INTERVAL);
+        }
+    }
+
+    /// Nodes searched so far across all iterations.
+    pub fn nodes(&self) -> u64 {
+        self.nodes.load(Ordering::Relaxed)
+    }
+
+    /// Counts one node and returns whether the search has to stop.
+    pub fn count_node(&self) -> bool {
+        let nodes = self.nodes.fetch_add(1, Ordering::Relaxed) + 1;
+
+        let out_of_nodes = nodes >= self.node_budget.load(Ordering::Relaxed);
+        let out_of_time = nodes.is_multiple_of(self.poll_interval)
+            && self
+                .deadline
+                .lock()
+                .expect("Deadline lock poisoned")
+                .is_some_and(|deadline| Instant::now() >= deadline);
+        if out_of_nodes || out_of_time {
+            self.stop();
+        }
+
+        self.is_stopped()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_node_budget_stops_search() {
+        let controller = SearchController::new(Arc::new(AtomicBool::new(true))).with_node_budget(3);
+        assert!(!controller.count_node());
+        assert!(!controller.count_node());
+        assert!(controller.count_node());
+        assert!(controller.is_stopped());
+    }
+
+    #[test]
+    fn test_deadline_checked_at_poll_interval() {
+        let controller = SearchController::new(Arc::new(AtomicBool::new(true)))
+            .with_deadline(Instant::now())
+            .with_poll_interval(2);
+        assert!(!controller.count_node());
+        assert!(controller.count_node());
+    }
+}
@@ -1,17 +1,22 @@
-use std::{
-    collections::HashMap,
-    sync::{Arc, atomic::AtomicBool},
-};
+use std::time::Duration;
 
 use shakmaty::{
-    Chess, Move, Position, Role,
+    Move, MoveList, Outcome, Position, Role,
+    variant::VariantPosition,
     zobrist::{Zobrist64, ZobristHash},
 };
+use web_time::Instant;
 
-use crate::eval::{NEGATIVE_INFINITY, POSITIVE_INFINITY, evaluate};
+use crate::controller::SearchController;
+use crate::error::Error;
+use crate::eval::{Evaluator, MATE_SCORE, NEGATIVE_INFINITY, POSITIVE_INFINITY};
+use crate::history::QuietHistory;
+use crate::mcts;
+use crate::move_picker::MovePicker;
+use crate::transposition::TranspositionTable;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum TranspositionHashType {
+pub(crate) enum TranspositionHashType {
     Exact,
     Alpha,
     Beta,
@@ -25,38 +30,228 @@ pub struct TranspositionInformation {
     transposition_type: TranspositionHashType,
 }
 
-pub struct Searcher<'a> {
-    position: &'a Chess,
+impl TranspositionInformation {
+    /// Best move found the last time the position was searched, if any.
+    pub fn best_move(&self) -> Option<Move> {
+        self.best_move
+    }
+}
+
+/// Deepest ply the search keeps per-ply state for.
+const MAX_PLY: usize = 128;
+
+/// Search techniques that can be switched off, e.g. to find the one behind a regression,
+/// and the engine's attitude towards draws.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchFeatures {
+    pub null_move: bool,
+    pub late_move_reductions: bool,
+    /// Resolve captures at the leaves instead of using the static evaluation.
+    pub quiescence: bool,
+    /// Centipawns the side to move at the root would give up to avoid a draw.
+    /// Negative values make it steer towards draws instead.
+    pub contempt: i64,
+}
+
+impl Default for SearchFeatures {
+    fn default() -> Self {
+        Self {
+            null_move: true,
+            late_move_reductions: true,
+            quiescence: true,
+            contempt: 0,
+        }
+    }
+}
+
+/// Search algorithms to choose from, as selected by the "SearchMode" option.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SearchMode {
+    #[default]
+    AlphaBeta,
+    /// Monte-Carlo tree search, see [`crate::mcts`].
+    Mcts,
+}
+
+impl SearchMode {
+    pub const ALL: [SearchMode; 2] = [SearchMode::AlphaBeta, SearchMode::Mcts];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SearchMode::AlphaBeta => "AlphaBeta",
+            SearchMode::Mcts => "MCTS",
+        }
+    }
+
+    /// Looks up a search mode by name, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|mode| mode.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn algorithm(self) -> &'static dyn SearchAlgorithm {
+        match self {
+            SearchMode::AlphaBeta => &AlphaBeta,
+            SearchMode::Mcts => &mcts::Mcts,
+        }
+    }
+}
+
+/// A way of choosing a move. Every algorithm takes the same limits, reports
+/// through the same observer and may use the engine's transposition table.
+pub trait SearchAlgorithm: Send + Sync {
+    #[allow(clippy::too_many_arguments)]
+    fn search(
+        &self,
+        position: &VariantPosition,
+        limits: &SearchLimits,
+        evaluator: &dyn Evaluator,
+        features: SearchFeatures,
+        controller: &SearchController,
+        transposition_table: &mut TranspositionTable,
+        observer: &mut dyn SearchObserver,
+    ) -> Result<SearchResult, Error>;
+}
+
+/// Iterative deepening alpha-beta, see [`iterative_deepening`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AlphaBeta;
+
+impl SearchAlgorithm for AlphaBeta {
+    fn search(
+        &self,
+        position: &VariantPosition,
+        limits: &SearchLimits,
+        evaluator: &dyn Evaluator,
+        features: SearchFeatures,
+        controller: &SearchController,
+        transposition_table: &mut TranspositionTable,
+        mut observer: &mut dyn SearchObserver,
+    ) -> Result<SearchResult, Error> {
+        iterative_deepening(
+            position,
+            limits,
+            evaluator,
+            features,
+            controller,
+            transposition_table,
+            &mut observer,
+        )
+    }
+}
+
+pub struct Searcher<'a, E: Evaluator + ?Sized> {
+    position: &'a VariantPosition,
+    evaluator: &'a E,
     target_depth: u64,
-    is_thinking: &'a Arc<AtomicBool>,
+    controller: &'a SearchController,
     last_best_move: Option<&'a Move>,
-    transposition_table: &'a mut HashMap<Zobrist64, TranspositionInformation>,
+    transposition_table: &'a mut TranspositionTable,
+    history: &'a mut QuietHistory,
+    search_moves: &'a [Move],
+    features: SearchFeatures,
     searched_nodes: u64,
+    /// Root moves searched to the end, rather than cut short by a stop.
+    finished_root_moves: usize,
+    /// Deepest ply this iteration reached, including quiescence.
+    seldepth: u64,
+    best_score: i64,
+    score_bound: ScoreBound,
+    /// Static evaluation at each ply of the current line, `None` when in check.
+    static_evals: [Option<i64>; MAX_PLY],
 }
 
-impl<'a> Searcher<'a> {
+impl<'a, E: Evaluator + ?Sized> Searcher<'a, E> {
     pub fn new(
-        position: &'a Chess,
+        position: &'a VariantPosition,
+        evaluator: &'a E,
         target_depth: u64,
-        is_thinking: &'a Arc<AtomicBool>,
+        controller: &'a SearchController,
         last_best_move: Option<&'a Move>,
-        transposition_table: &'a mut HashMap<Zobrist64, TranspositionInformation>,
+        transposition_table: &'a mut TranspositionTable,
+        history: &'a mut QuietHistory,
     ) -> Self {
         Self {
             position,
+            evaluator,
             target_depth,
-            is_thinking,
+            controller,
             last_best_move,
             transposition_table,
+            history,
+            search_moves: &[],
+            features: SearchFeatures::default(),
             searched_nodes: 0,
+            finished_root_moves: 0,
+            seldepth: 0,
+            best_score: NEGATIVE_INFINITY,
+            score_bound: ScoreBound::Exact,
+            static_evals: [None; MAX_PLY],
         }
     }
 
+    /// Only considers these root moves; an empty slice allows all of them.
+    pub fn with_search_moves(mut self, search_moves: &'a [Move]) -> Self {
+        self.search_moves = search_moves;
+        self
+    }
+
+    pub fn with_features(mut self, features: SearchFeatures) -> Self {
+        self.features = features;
+        self
+    }
+
+    /// Score of the move returned by the last call to [`Searcher::next_move`].
+    pub fn best_score(&self) -> i64 {
+        self.best_score
+    }
+
+    /// Number of nodes this iteration visited, including quiescence nodes.
+    pub fn searched_nodes(&self) -> u64 {
+        self.searched_nodes
+    }
+
+    /// Whether [`Searcher::best_score`] is exact or only a bound.
+    pub fn score_bound(&self) -> ScoreBound {
+        self.score_bound
+    }
+
+    /// Root moves searched to the end; zero if the iteration was stopped
+    /// during the first one, whose score then can't be trusted either.
+    pub fn finished_root_moves(&self) -> usize {
+        self.finished_root_moves
+    }
+
+    /// Deepest ply this iteration reached, including quiescence.
+    pub fn seldepth(&self) -> u64 {
+        self.seldepth
+    }
+
     /// Entry point for the chess engine to search for the best move.
-    pub fn next_move(&mut self) -> Move {
+    pub fn next_move(&mut self) -> Result<Move, Error> {
+        self.next_move_with(|_, _| {})
+    }
+
+    /// [`Searcher::next_move`], calling `on_root_move` with each root move and
+    /// its number, counting from 1, before searching it.
+    pub fn next_move_with(
+        &mut self,
+        mut on_root_move: impl FnMut(Move, usize),
+    ) -> Result<Move, Error> {
         let mut legal_moves = self.position.legal_moves();
-        legal_moves.sort_by_key(|move_to_score| {
-            quick_score_move_for_sort(move_to_score, self.position, self.last_best_move)
+        if !self.search_moves.is_empty() {
+            legal_moves.retain(|m| self.search_moves.contains(m));
+        }
+        let turn = self.position.turn();
+        let legal_moves = MovePicker::new(legal_moves, |m| {
+            quick_score_move(
+                m,
+                self.position,
+                self.last_best_move,
+                None,
+                self.history.score(turn, m),
+            )
         });
 
         // Find the move that maximizes the evaluation (piece count)
@@ -64,34 +259,60 @@ impl<'a> Searcher<'a> {
         let mut alpha = NEGATIVE_INFINITY;
         let beta = POSITIVE_INFINITY;
 
-        for legal_move in &legal_moves {
+        for (index, legal_move) in legal_moves.enumerate() {
+            on_root_move(legal_move, index + 1);
             let mut new_position = self.position.clone();
-            new_position.play_unchecked(*legal_move);
-            let score = -self.negamax(&new_position, self.target_depth - 1, -beta, -alpha);
+            new_position.play_unchecked(legal_move);
+            let score = -self.negamax(&new_position, self.target_depth - 1, 1, -beta, -alpha);
+            // A move cut short has a truncated score, which is only better
+            // than nothing if no move was finished
+            let stopped = self.controller.is_stopped();
+            if stopped && best_move.is_some() {
+                break;
+            }
             if score > alpha {
                 alpha = score;
-                best_move = Some(*legal_move);
+                best_move = Some(legal_move);
             }
-            if !self.is_thinking.load(std::sync::atomic::Ordering::SeqCst) {
+            if stopped {
                 break;
             }
+            self.finished_root_moves += 1;
         }
 
-        println!(
-            "info depth {} score cp {alpha} nodes {}",
-            self.target_depth, self.searched_nodes
-        );
-        best_move.expect("No legal moves found")
+        self.best_score = alpha;
+        // Moves left unsearched could only have raised the score
+        self.score_bound = if self.controller.is_stopped() {
+            ScoreBound::Lower
+        } else {
+            ScoreBound::Exact
+        };
+        best_move.ok_or(Error::NoLegalMoves)
     }
 
-    fn negamax(&mut self, position: &Chess, depth: u64, mut alpha: i64, beta: i64) -> i64 {
+    fn negamax(
+        &mut self,
+        position: &VariantPosition,
+        depth: u64,
+        ply: usize,
+        mut alpha: i64,
+        beta: i64,
+    ) -> i64 {
         let mut transposition_type = TranspositionHashType::Alpha;
         let zobrist_hash = position.zobrist_hash::<Zobrist64>(shakmaty::EnPassantMode::Legal);
         let mut best_cached_move = None;
 
-        match probe_hash(self.transposition_table, zobrist_hash, depth, alpha, beta) {
+        // Mate scores are stored relative to the node, see `score_to_table`
+        let (table_alpha, table_beta) = (score_to_table(alpha, ply), score_to_table(beta, ply));
+        match probe_hash(
+            self.transposition_table,
+            zobrist_hash,
+            depth,
+            table_alpha,
+            table_beta,
+        ) {
             HashProbeOption::Some(val) => {
-                return val;
+                return score_from_table(val, ply);
             }
             HashProbeOption::Move(mv) => {
                 best_cached_move = Some(mv);
@@ -100,95 +321,179 @@ impl<'a> Searcher<'a> {
         }
 
         self.searched_nodes += 1;
+        self.seldepth = self.seldepth.max(ply as u64);
+        let must_stop = self.controller.count_node();
 
-        if depth == 0
-            || position.is_game_over()
-            || !self.is_thinking.load(std::sync::atomic::Ordering::SeqCst)
-        {
-            let val = self.quiesce(position, alpha, beta);
-            record_hash(
-                self.transposition_table,
-                zobrist_hash,
-                depth,
-                val,
-                TranspositionHashType::Exact,
-                None,
-            );
+        if depth == 0 || position.is_game_over() || must_stop {
+            let val = if is_draw(position) {
+                self.draw_score(ply)
+            } else if self.features.quiescence {
+                self.quiesce(position, ply, alpha, beta)
+            } else {
+                mate_distance_from_root(self.evaluator.evaluate(position), ply)
+            };
+            // A node cut short is worth far less than its depth claims, and the
+            // table outlives this search
+            if !self.controller.is_stopped() {
+                record_hash(
+                    self.transposition_table,
+                    zobrist_hash,
+                    depth,
+                    score_to_table(val, ply),
+                    TranspositionHashType::Exact,
+                    None,
+                );
+            }
             return val;
         }
 
-        if depth >= 3
-            && !position.checkers().any()
+        // Whether the side to move is doing better than on its previous turn.
+        // Nodes that aren't are more likely to fail low and get reduced harder.
+        let in_check = position.checkers().any();
+        let static_eval = (!in_check).then(|| self.evaluator.evaluate(position));
+        if let Some(slot) = self.static_evals.get_mut(ply) {
+            *slot = static_eval;
+        }
+        let improving = match (static_eval, ply.checked_sub(2)) {
+            (Some(eval), Some(earlier_ply)) => {
+                let earlier = self.static_evals.get(earlier_ply).copied().flatten();
+                earlier.is_none_or(|earlier| eval > earlier)
+            }
+            _ => false,
+        };
+
+        let mut threat = None;
+        if self.features.null_move
+            && depth >= 3
+            && !in_check
             && let Ok(null_pos) = position.clone().swap_turn()
         {
             // Search with reduced depth (typically depth - 3)
-            let null_score = -self.negamax(&null_pos, depth - 3, -beta, -beta + 1);
+            let null_score = -self.negamax(&null_pos, depth - 3, ply + 1, -beta, -beta + 1);
 
             // If even doing nothing beats beta, we can prune
             if null_score >= beta {
                 return beta;
             }
+
+            // Otherwise the opponent's best reply to passing shows what they threaten
+            let null_hash = null_pos.zobrist_hash::<Zobrist64>(shakmaty::EnPassantMode::Legal);
+            threat = self
+                .transposition_table
+                .get(&null_hash)
+                .and_then(|info| info.best_move)
+                .filter(|m| m.capture().is_some());
         }
 
-        let mut legal_moves = position.legal_moves();
-        legal_moves.sort_by_key(|move_to_score| {
-            quick_score_move_for_sort(move_to_score, position, best_cached_move.as_ref())
+        let turn = position.turn();
+        let legal_moves = MovePicker::new(position.legal_moves(), |m| {
+            quick_score_move(
+                m,
+                position,
+                best_cached_move.as_ref(),
+                threat.as_ref(),
+                self.history.score(turn, m),
+            )
         });
         let mut best_move = None;
+        // Quiet moves that were searched without causing a cutoff
+        let mut failed_quiets = MoveList::new();
 
-        for (move_index, m) in legal_moves.iter().enumerate() {
+        for (move_index, m) in legal_moves.enumerate() {
             let mut new_pos = position.clone();
-            new_pos.play_unchecked(*m);
+            new_pos.play_unchecked(m);
 
             let mut score;
 
             // Late Move Reduction
-            if move_index >= 4 && depth >= 3 && m.capture().is_none() && !new_pos.checkers().any() {
-                // Search with reduced depth first
-                score = -self.negamax(&new_pos, depth - 2, -beta, -alpha);
+            if self.features.late_move_reductions
+                && move_index >= 4
+                && depth >= 3
+                && m.capture().is_none()
+                && !new_pos.checkers().any()
+            {
+                // Search with reduced depth first, one ply less if not improving
+                let reduction = if !improving && depth >= 4 { 3 } else { 2 };
+                score = -self.negamax(&new_pos, depth - reduction, ply + 1, -beta, -alpha);
 
                 // If it looks promising, re-search with full depth
                 if score > alpha {
-                    score = -self.negamax(&new_pos, depth - 1, -beta, -alpha);
+                    score = -self.negamax(&new_pos, depth - 1, ply + 1, -beta, -alpha);
                 }
             } else {
                 // Normal full-depth search
-                score = -self.negamax(&new_pos, depth - 1, -beta, -alpha);
+                score = -self.negamax(&new_pos, depth - 1, ply + 1, -beta, -alpha);
             }
 
+            let is_quiet = m.capture().is_none() && m.promotion().is_none();
             if score >= beta {
-                record_hash(
-                    self.transposition_table,
-                    zobrist_hash,
-                    depth,
-                    beta,
-                    TranspositionHashType::Beta,
-                    Some(*m),
-                );
+                if is_quiet {
+                    // Reward the cutoff and hold the quiets tried before it against them
+                    let bonus = (depth * depth).min(400) as i32;
+                    self.history.update(turn, &m, bonus);
+                    for failed in &failed_quiets {
+                        self.history.update(turn, failed, -bonus);
+                    }
+                }
+                if !self.controller.is_stopped() {
+                    record_hash(
+                        self.transposition_table,
+                        zobrist_hash,
+                        depth,
+                        score_to_table(beta, ply),
+                        TranspositionHashType::Beta,
+                        Some(m),
+                    );
+                }
                 return beta;
             }
+            if is_quiet {
+                failed_quiets.push(m);
+            }
             if score > alpha {
                 transposition_type = TranspositionHashType::Exact;
                 alpha = score;
-                best_move = Some(*m);
+                best_move = Some(m);
             }
         }
 
-        record_hash(
-            self.transposition_table,
-            zobrist_hash,
-            depth,
-            alpha,
-            transposition_type,
-            best_move,
-        );
+        // Children searched after the stop return truncated scores
+        if !self.controller.is_stopped() {
+            record_hash(
+                self.transposition_table,
+                zobrist_hash,
+                depth,
+                score_to_table(alpha, ply),
+                transposition_type,
+                best_move,
+            );
+        }
         alpha
     }
 
-    fn quiesce(&mut self, position: &Chess, mut alpha: i64, beta: i64) -> i64 {
+    /// Score of a drawn position `ply` plies below the root, seen from its side to move.
+    fn draw_score(&self, ply: usize) -> i64 {
+        // The root's side is to move at even plies
+        if ply.is_multiple_of(2) {
+            -self.features.contempt
+        } else {
+            self.features.contempt
+        }
+    }
+
+    fn quiesce(
+        &mut self,
+        position: &VariantPosition,
+        ply: usize,
+        mut alpha: i64,
+        beta: i64,
+    ) -> i64 {
         self.searched_nodes += 1;
+        self.seldepth = self.seldepth.max(ply as u64);
+        // Quiescence always runs to the end, only the node count matters here
+        self.controller.count_node();
 
-        let static_eval = evaluate(position);
+        let static_eval = mate_distance_from_root(self.evaluator.evaluate(position), ply);
 
         // Stand Pat
         let mut best_value = static_eval;
@@ -199,24 +504,17 @@ impl<'a> Searcher<'a> {
             alpha = best_value;
         }
 
-        // Only consider capture moves for quiescence
-        let mut capture_moves: Vec<Move> = position
-            .legal_moves()
-            .into_iter()
-            .filter(|m| m.capture().is_some())
-            .collect();
-
-        // Optionally, sort captures by MVV-LVA or similar
-        capture_moves.sort_by_key(|m| {
+        // Only consider capture moves for quiescence; MoveList lives on the stack
+        let capture_moves = MovePicker::new(position.capture_moves(), |m| {
             // Most Valuable Victim - Least Valuable Attacker
-            -piece_capture_score(m.capture().unwrap()) + piece_capture_score(m.role())
+            piece_capture_score(m.capture().unwrap()) - piece_capture_score(m.role())
         });
 
         for m in capture_moves {
             let mut new_pos = position.clone();
             new_pos.play_unchecked(m);
 
-            let score = -self.quiesce(&new_pos, -beta, -alpha);
+            let score = -self.quiesce(&new_pos, ply + 1, -beta, -alpha);
 
             if score >= beta {
                 return score;
@@ -233,7 +531,66 @@ impl<'a> Searcher<'a> {
     }
 }
 
-fn piece_capture_score(piece: Role) -> i64 {
+/// Scores beyond this are forced mates, found this many plies from the root
+/// at most and scored [`MATE_SCORE`] minus the plies to the mate.
+pub const MATE_THRESHOLD: i64 = MATE_SCORE - 10_000;
+
+/// Moves until mate for a mate score: positive if the side to move mates,
+/// negative if it gets mated. `None` for scores that aren't mates.
+pub fn mate_in_moves(score: i64) -> Option<i64> {
+    if score >= MATE_THRESHOLD {
+        Some((MATE_SCORE - score + 1) / 2)
+    } else if score <= -MATE_THRESHOLD {
+        Some(-(MATE_SCORE + score + 1) / 2)
+    } else {
+        None
+    }
+}
+
+/// Takes the distance from the root off a mate score from the evaluator, so
+/// that quicker mates score higher and slower defeats lower.
+fn mate_distance_from_root(score: i64, ply: usize) -> i64 {
+    let ply = (ply as i64).min(MATE_SCORE - MATE_THRESHOLD);
+    if score >= MATE_SCORE {
+        MATE_SCORE - ply
+    } else if score <= -MATE_SCORE {
+        -MATE_SCORE + ply
+    } else {
+        score
+    }
+}
+
+/// The table keeps mate scores as distances from the stored node rather than
+/// from the root, so that they stay right when reached on another path.
+fn score_to_table(score: i64, ply: usize) -> i64 {
+    let ply = ply as i64;
+    if score >= MATE_THRESHOLD {
+        score + ply
+    } else if score <= -MATE_THRESHOLD {
+        score - ply
+    } else {
+        score
+    }
+}
+
+fn score_from_table(score: i64, ply: usize) -> i64 {
+    let ply = ply as i64;
+    if score >= MATE_THRESHOLD {
+        score - ply
+    } else if score <= -MATE_THRESHOLD {
+        score + ply
+    } else {
+        score
+    }
+}
+
+/// Whether the game is over without a winner, e.g. by stalemate.
+fn is_draw(position: &VariantPosition) -> bool {
+    position.is_game_over() && !matches!(position.outcome(), Some(Outcome::Decisive { .. }))
+}
+
+/// Rough piece values used for move ordering and exchanges.
+pub(crate) fn piece_capture_score(piece: Role) -> i64 {
     match piece {
         Role::Pawn => 100,
         Role::Knight => 320,
@@ -245,14 +602,14 @@ fn piece_capture_score(piece: Role) -> i64 {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum HashProbeOption {
+pub(crate) enum HashProbeOption {
     Some(i64),
     Move(Move),
     None,
 }
 
-fn probe_hash(
-    transposition_table: &HashMap<Zobrist64, TranspositionInformation>,
+pub(crate) fn probe_hash(
+    transposition_table: &TranspositionTable,
     zobrist_hash: Zobrist64,
     depth: u64,
     alpha: i64,
@@ -261,6 +618,12 @@ fn probe_hash(
     let info_option = transposition_table.get(&zobrist_hash);
 
     if let Some(info) = info_option {
+        tracing::trace!(
+            stored_depth = info.depth,
+            depth,
+            value = info.value,
+            "tt hit"
+        );
         if info.depth >= depth {
             if info.transposition_type == TranspositionHashType::Exact {
                 return HashProbeOption::Some(info.value);
@@ -281,14 +644,15 @@ fn probe_hash(
     HashProbeOption::None
 }
 
-fn record_hash(
-    transposition_table: &mut HashMap<Zobrist64, TranspositionInformation>,
+pub(crate) fn record_hash(
+    transposition_table: &mut TranspositionTable,
     zobrist_hash: Zobrist64,
     depth: u64,
     value: i64,
     transposition_type: TranspositionHashType,
     best_move: Option<Move>,
 ) {
+    tracing::trace!(depth, value, ?transposition_type, "tt store");
     transposition_table.insert(
         zobrist_hash,
         TranspositionInformation {
@@ -300,11 +664,273 @@ fn record_hash(
     );
 }
 
+/// Summary of one search iteration, reported while deepening.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IterationInfo {
+    pub depth: u64,
+    /// Deepest ply reached, including extensions and quiescence.
+    pub seldepth: u64,
+    pub score: i64,
+    pub bound: ScoreBound,
+    /// Nodes searched since the search started.
+    pub nodes: u64,
+    /// Permille of the transposition table in use, `None` for searches
+    /// without one.
+    pub hashfull: Option<u64>,
+    pub best_move: Move,
+    /// Expected line of play starting with `best_move`.
+    pub pv: Vec<Move>,
+}
+
+/// How far an iteration's score can be trusted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ScoreBound {
+    #[default]
+    Exact,
+    /// The true score is at least this high, e.g. when the iteration was cut short.
+    Lower,
+    /// The true score is at most this high.
+    Upper,
+}
+
+/// What the GUI allows a search to use, as given by "go".
+/// Fields left at `None` don't limit the search.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchLimits {
+    /// Maximum number of plies to deepen to.
+    pub depth: Option<u64>,
+    pub nodes: Option<u64>,
+    /// Exact time to search for.
+    pub movetime: Option<Duration>,
+    pub wtime: Option<Duration>,
+    pub btime: Option<Duration>,
+    pub winc: Option<Duration>,
+    pub binc: Option<Duration>,
+    pub movestogo: Option<u64>,
+    /// Nodes that count as one millisecond of the clock ("go nodestime"), so
+    /// clock games play out the same on any machine.
+    pub nodestime: Option<u64>,
+    /// Look for a mate in this many moves.
+    pub mate: Option<u64>,
+    /// Restricts the root to these moves; empty means all legal moves.
+    pub search_moves: Vec<Move>,
+    /// Search until "stop", and hold back the result until then even if the
+    /// search ends by itself.
+    pub infinite: bool,
+    /// Search the expected position on the opponent's time. Like `infinite`
+    /// until "ponderhit", after which the clock limits apply.
+    pub ponder: bool,
+}
+
+impl SearchLimits {
+    /// Whether the GUI gave a clock or a fixed time per move.
+    pub fn is_timed(&self) -> bool {
+        self.movetime.is_some() || self.wtime.is_some() || self.btime.is_some()
+    }
+
+    /// Whether nothing would end the search, as after a bare "go".
+    pub fn is_unbounded(&self) -> bool {
+        !self.is_timed()
+            && !self.infinite
+            && self.depth.is_none()
+            && self.nodes.is_none()
+            && self.mate.is_none()
+    }
+
+    /// Depth after which deepening ends, taking a mate search into account.
+    pub fn max_depth(&self) -> Option<u64> {
+        // A mate in n moves needs 2n - 1 plies
+        let mate_depth = self.mate.map(|moves| (2 * moves).saturating_sub(1).max(1));
+        match (self.depth, mate_depth) {
+            (Some(depth), Some(mate_depth)) => Some(depth.min(mate_depth)),
+            (depth, mate_depth) => depth.or(mate_depth),
+        }
+    }
+}
+
+/// Outcome of a finished search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchResult {
+    pub best_move: Move,
+    /// Expected reply to `best_move`, if the principal variation has one.
+    pub ponder: Option<Move>,
+    pub score: i64,
+    /// Whether `score` is exact, e.g. not from an iteration that was cut short.
+    pub bound: ScoreBound,
+    /// Expected line of play starting with `best_move`.
+    pub pv: Vec<Move>,
+    /// Last iteration that was searched.
+    pub depth: u64,
+    /// Deepest ply reached, including extensions and quiescence.
+    pub seldepth: u64,
+    pub nodes: u64,
+    pub time: Duration,
+}
+
+impl SearchResult {
+    fn from_iteration(info: IterationInfo, time: Duration) -> Self {
+        Self {
+            best_move: info.best_move,
+            ponder: info.pv.get(1).copied(),
+            score: info.score,
+            bound: info.bound,
+            depth: info.depth,
+            seldepth: info.seldepth,
+            nodes: info.nodes,
+            pv: info.pv,
+            time,
+        }
+    }
+}
+
+/// Receives progress updates from [`iterative_deepening`]; every method defaults to doing nothing.
+pub trait SearchObserver {
+    /// Called after every iteration, including the final one that may have been cut short.
+    fn on_iteration(&mut self, _info: &IterationInfo) {}
+
+    /// Called when an iteration settles on a different move than the one before it.
+    fn on_new_best_move(&mut self, _info: &IterationInfo) {}
+
+    /// Called as an iteration at `depth` starts on root move `number`, counting from 1.
+    fn on_root_move(&mut self, _depth: u64, _root_move: Move, _number: usize) {}
+
+    /// Called once when the search is over, with the result that will be played.
+    fn on_search_finished(&mut self, _result: &SearchResult) {}
+}
+
+/// Ignores all events.
+impl SearchObserver for () {}
+
+impl<O: SearchObserver + ?Sized> SearchObserver for &mut O {
+    fn on_iteration(&mut self, info: &IterationInfo) {
+        (**self).on_iteration(info);
+    }
+
+    fn on_new_best_move(&mut self, info: &IterationInfo) {
+        (**self).on_new_best_move(info);
+    }
+
+    fn on_root_move(&mut self, depth: u64, root_move: Move, number: usize) {
+        (**self).on_root_move(depth, root_move, number);
+    }
+
+    fn on_search_finished(&mut self, result: &SearchResult) {
+        (**self).on_search_finished(result);
+    }
+}
+
+/// Deepens the search one ply at a time until `limits` are reached or `controller`
+/// stops it, keeping `observer` informed. Node and time limits are enforced by
+/// `controller`, the rest of `limits` here.
+pub fn iterative_deepening<E: Evaluator + ?Sized>(
+    position: &VariantPosition,
+    limits: &SearchLimits,
+    evaluator: &E,
+    features: SearchFeatures,
+    controller: &SearchController,
+    transposition_table: &mut TranspositionTable,
+    observer: &mut impl SearchObserver,
+) -> Result<SearchResult, Error> {
+    let start_time = Instant::now();
+    let max_depth = limits.max_depth();
+    let mut last_iteration: Option<IterationInfo> = None;
+    let mut depth: u64 = 0;
+    let mut history = QuietHistory::new();
+
+    // Depth 1 always runs so there is a move to play even if time is already up
+    while depth == 0 || (!controller.is_stopped() && max_depth.is_none_or(|max| depth < max)) {
+        depth += 1;
+        let _span = tracing::debug_span!("iteration", depth).entered();
+        let previous_best_move = last_iteration.as_ref().map(|info| info.best_move);
+        let mut searcher = Searcher::new(
+            position,
+            evaluator,
+            depth,
+            controller,
+            previous_best_move.as_ref(),
+            transposition_table,
+            &mut history,
+        )
+        .with_search_moves(&limits.search_moves)
+        .with_features(features);
+        let best_move = searcher
+            .next_move_with(|root_move, number| observer.on_root_move(depth, root_move, number))?;
+        // The last iteration knows more than one stopped before finishing a move
+        if searcher.finished_root_moves() == 0 && last_iteration.is_some() {
+            break;
+        }
+        let score = searcher.best_score();
+        let seldepth = searcher.seldepth();
+        let bound = searcher.score_bound();
+        let nodes = controller.nodes();
+
+        let info = IterationInfo {
+            depth,
+            seldepth,
+            score,
+            bound,
+            nodes,
+            hashfull: Some(transposition_table.hashfull()),
+            best_move,
+            pv: principal_variation(position, best_move, transposition_table),
+        };
+        tracing::debug!(score, nodes, best_move = %best_move, "iteration finished");
+        observer.on_iteration(&info);
+        if previous_best_move != Some(best_move) {
+            observer.on_new_best_move(&info);
+        }
+        last_iteration = Some(info);
+
+        if limits.mate.is_some() && mate_in_moves(score).is_some_and(|moves| moves > 0) {
+            break;
+        }
+    }
+
+    let last_iteration = last_iteration.expect("At least one iteration is always searched");
+    let result = SearchResult {
+        // Nodes spent on an iteration that was cut short count too
+        nodes: controller.nodes(),
+        ..SearchResult::from_iteration(last_iteration, start_time.elapsed())
+    };
+    observer.on_search_finished(&result);
+    Ok(result)
+}
+
+/// Follows the best moves stored in the transposition table, starting with `best_move`.
+pub fn principal_variation(
+    position: &VariantPosition,
+    best_move: Move,
+    transposition_table: &TranspositionTable,
+) -> Vec<Move> {
+    let mut pv = vec![best_move];
+    let mut current_position = position.clone();
+    current_position.play_unchecked(best_move);
+
+    // Cap the length so repetitions stored in the table can't loop forever
+    while pv.len() < 64 {
+        let zobrist_hash =
+            current_position.zobrist_hash::<Zobrist64>(shakmaty::EnPassantMode::Legal);
+        let Some(next_move) = transposition_table
+            .get(&zobrist_hash)
+            .and_then(|info| info.best_move)
+            .filter(|m| current_position.is_legal(*m))
+        else {
+            break;
+        };
+        pv.push(next_move);
+        current_position.play_unchecked(next_move);
+    }
+
+    pv
+}
+
 /// Higher result is a better move
-fn quick_score_move_for_sort(
+fn quick_score_move(
     move_to_score: &Move,
-    position: &Chess,
+    position: &VariantPosition,
     last_best_move: Option<&Move>,
+    threat: Option<&Move>,
+    history_score: i32,
 ) -> i64 {
     let mut score = 0;
 
@@ -325,6 +951,20 @@ fn quick_score_move_for_sort(
         score += piece_capture_score(new_piece);
     }
 
+    // Moves that save the piece the opponent threatens to take, or take the attacker
+    if let Some(threat) = threat {
+        if move_to_score.from() == Some(threat.to()) {
+            score += 300;
+        } else if Some(move_to_score.to()) == threat.from() {
+            score += 200;
+        }
+    }
+
+    // Order quiet moves by how they did elsewhere in the tree, below good captures
+    if move_to_score.capture().is_none() && move_to_score.promotion().is_none() {
+        score += i64::from(history_score / 32);
+    }
+
     if position
         .board()
         .attacks_to(
@@ -337,17 +977,16 @@ fn quick_score_move_for_sort(
         score -= piece_capture_score(move_to_score.role());
     }
 
-    // Reverse order since rust sorts moves from lowest score to highest score
-    -score
+    score
 }
 
 #[cfg(test)]
 mod test {
-    use crate::eval::evaluate;
+    use crate::eval::{PestoEvaluator, evaluate};
 
     use super::*;
     // use rand::prelude::*;
-    use shakmaty::{CastlingMode, fen};
+    use shakmaty::{CastlingMode, Chess, fen};
 
     #[test]
     fn test_evaluate() {
@@ -375,6 +1014,256 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_stop_ignores_unfinished_root_move() {
+        use std::sync::{Arc, atomic::AtomicBool};
+
+        // Qxd5 drops the queen to exd5, which a search stopped right after it never sees
+        let position: VariantPosition = "4k3/8/4p3/3p4/8/8/8/3QK3 w - - 0 1"
+            .parse::<fen::Fen>()
+            .unwrap()
+            .into_position::<Chess>(CastlingMode::Standard)
+            .unwrap()
+            .into();
+        let to_move = |uci: &str| {
+            uci.parse::<shakmaty::uci::UciMove>()
+                .unwrap()
+                .to_move(&position)
+                .unwrap()
+        };
+        let (quiet, capture) = (to_move("d1d2"), to_move("d1d5"));
+        let search_moves = [quiet, capture];
+        let features = SearchFeatures {
+            quiescence: false,
+            ..SearchFeatures::default()
+        };
+        let search = |controller: &SearchController, on_root_move: &mut dyn FnMut(Move, usize)| {
+            let mut transposition_table = TranspositionTable::default();
+            let mut history = QuietHistory::new();
+            let mut searcher = Searcher::new(
+                &position,
+                &PestoEvaluator,
+                2,
+                controller,
+                Some(&quiet),
+                &mut transposition_table,
+                &mut history,
+            )
+            .with_search_moves(&search_moves)
+            .with_features(features);
+            let best_move = searcher.next_move_with(on_root_move).unwrap();
+            (best_move, searcher.finished_root_moves())
+        };
+
+        let controller = SearchController::new(Arc::new(AtomicBool::new(true)));
+        let mut capture_start = 0;
+        let mut record_start = |root_move, _| {
+            if root_move == capture {
+                capture_start = controller.nodes();
+            }
+        };
+        assert_eq!(search(&controller, &mut record_start), (quiet, 2));
+
+        // Stops on the first node after the capture
+        let controller = SearchController::new(Arc::new(AtomicBool::new(true)))
+            .with_node_budget(capture_start + 1);
+        assert_eq!(search(&controller, &mut |_, _| {}), (quiet, 1));
+    }
+
+    #[test]
+    fn test_observer_sees_every_iteration() {
+        use std::sync::{Arc, atomic::AtomicBool};
+
+        struct Recorder {
+            controller: SearchController,
+            depths: Vec<u64>,
+            /// Numbers of the root moves started at depth 1.
+            root_moves: Vec<usize>,
+            finished: Option<SearchResult>,
+        }
+
+        impl SearchObserver for Recorder {
+            fn on_iteration(&mut self, info: &IterationInfo) {
+                self.depths.push(info.depth);
+                if info.depth == 3 {
+                    self.controller.stop();
+                }
+            }
+
+            fn on_root_move(&mut self, depth: u64, _root_move: Move, number: usize) {
+                if depth == 1 {
+                    self.root_moves.push(number);
+                }
+            }
+
+            fn on_search_finished(&mut self, result: &SearchResult) {
+                self.finished = Some(result.clone());
+            }
+        }
+
+        let controller = SearchController::new(Arc::new(AtomicBool::new(true)));
+        let mut recorder = Recorder {
+            controller: controller.clone(),
+            depths: Vec::new(),
+            root_moves: Vec::new(),
+            finished: None,
+        };
+        let mut transposition_table = TranspositionTable::default();
+        let result = iterative_deepening(
+            &VariantPosition::default(),
+            &SearchLimits::default(),
+            &PestoEvaluator,
+            SearchFeatures::default(),
+            &controller,
+            &mut transposition_table,
+            &mut recorder,
+        )
+        .unwrap();
+
+        assert_eq!(recorder.depths, vec![1, 2, 3]);
+        assert_eq!(recorder.root_moves, (1..=20).collect::<Vec<_>>());
+        assert_eq!(recorder.finished, Some(result.clone()));
+        assert_eq!(result.pv.first(), Some(&result.best_move));
+        assert_eq!(result.ponder, result.pv.get(1).copied());
+        // Quiescence searches past the nominal depth
+        assert!(result.seldepth >= result.depth);
+    }
+
+    #[test]
+    fn test_search_respects_limits() {
+        use std::sync::{Arc, atomic::AtomicBool};
+
+        let position = VariantPosition::default();
+        let only_move = "a2a3"
+            .parse::<shakmaty::uci::UciMove>()
+            .unwrap()
+            .to_move(&position)
+            .unwrap();
+        let limits = SearchLimits {
+            depth: Some(2),
+            search_moves: vec![only_move],
+            ..SearchLimits::default()
+        };
+
+        let controller = SearchController::new(Arc::new(AtomicBool::new(true)));
+        let result = iterative_deepening(
+            &position,
+            &limits,
+            &PestoEvaluator,
+            SearchFeatures::default(),
+            &controller,
+            &mut TranspositionTable::default(),
+            &mut (),
+        )
+        .unwrap();
+
+        assert_eq!(result.depth, 2);
+        assert_eq!(result.bound, ScoreBound::Exact);
+        assert_eq!(result.best_move, only_move);
+    }
+
+    #[test]
+    fn test_every_algorithm_finds_mate() {
+        use std::sync::{Arc, atomic::AtomicBool};
+
+        let position: Chess = "6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1"
+            .parse::<fen::Fen>()
+            .unwrap()
+            .into_position(CastlingMode::Standard)
+            .unwrap();
+        let position = VariantPosition::Chess(position);
+        let limits = SearchLimits {
+            depth: Some(2),
+            ..SearchLimits::default()
+        };
+
+        for mode in SearchMode::ALL {
+            let controller =
+                SearchController::new(Arc::new(AtomicBool::new(true))).with_node_budget(20_000);
+            let result = mode
+                .algorithm()
+                .search(
+                    &position,
+                    &limits,
+                    &PestoEvaluator,
+                    SearchFeatures::default(),
+                    &controller,
+                    &mut TranspositionTable::default(),
+                    &mut (),
+                )
+                .unwrap();
+            assert_eq!(
+                result.best_move.to_uci(CastlingMode::Standard).to_string(),
+                "a1a8",
+                "{}",
+                mode.name()
+            );
+        }
+    }
+
+    #[test]
+    fn test_stopped_search_leaves_table_alone() {
+        use std::sync::{Arc, atomic::AtomicBool};
+
+        // Every node is searched after the budget ran out, so none may be stored
+        let controller = SearchController::new(Arc::new(AtomicBool::new(true))).with_node_budget(1);
+        let mut transposition_table = TranspositionTable::default();
+        iterative_deepening(
+            &VariantPosition::default(),
+            &SearchLimits::default(),
+            &PestoEvaluator,
+            SearchFeatures::default(),
+            &controller,
+            &mut transposition_table,
+            &mut (),
+        )
+        .unwrap();
+        assert_eq!(transposition_table.len(), 0);
+    }
+
+    #[test]
+    fn test_mate_limit_depth() {
+        let limits = SearchLimits {
+            depth: Some(10),
+            mate: Some(2),
+            ..SearchLimits::default()
+        };
+        assert_eq!(limits.max_depth(), Some(3));
+        assert_eq!(SearchLimits::default().max_depth(), None);
+    }
+
+    #[test]
+    fn test_mate_search_reports_distance() {
+        use std::sync::{Arc, atomic::AtomicBool};
+
+        let position: Chess = "6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1"
+            .parse::<fen::Fen>()
+            .unwrap()
+            .into_position(CastlingMode::Standard)
+            .unwrap();
+        let position = VariantPosition::Chess(position);
+        let limits = SearchLimits {
+            mate: Some(2),
+            ..SearchLimits::default()
+        };
+
+        let controller = SearchController::new(Arc::new(AtomicBool::new(true)));
+        let result = iterative_deepening(
+            &position,
+            &limits,
+            &PestoEvaluator,
+            SearchFeatures::default(),
+            &controller,
+            &mut TranspositionTable::default(),
+            &mut (),
+        )
+        .unwrap();
+
+        assert_eq!(result.best_move.to_string(), "Ra1-a8");
+        assert_eq!(mate_in_moves(result.score), Some(1));
+        assert_eq!(result.depth, 1);
+    }
+
     // #[test]
     // fn test_evaluations_are_equivalent() {
     //     // 1. Initialize dependencies
@@ -0,0 +1,245 @@
+use std::{
+    sync::{Arc, atomic::AtomicBool},
+    time::Duration,
+};
+
+use shakmaty::{
+    CastlingMode, Move,
+    fen::Fen,
+    san::SanPlus,
+    variant::{Variant, VariantPosition},
+};
+
+use crate::controller::SearchController;
+use crate::engine::{SearchFeatures, SearchLimits, SearchResult, iterative_deepening};
+use crate::error::Error;
+use crate::eval::PestoEvaluator;
+use crate::transposition::TranspositionTable;
+
+/// One position of a test suite like WAC or STS.
+#[derive(Debug, Clone)]
+pub struct EpdEntry {
+    pub position: VariantPosition,
+    /// The `id` operation, if there is one.
+    pub id: Option<String>,
+    /// Moves that solve the position (`bm`).
+    pub best_moves: Vec<Move>,
+    /// Moves that fail it (`am`).
+    pub avoid_moves: Vec<Move>,
+    /// Points per move as in STS's `c0 "Nd5=10, Rb8=3"`.
+    pub points: Vec<(Move, u32)>,
+}
+
+impl EpdEntry {
+    /// Reads one EPD line: the first four FEN fields followed by operations
+    /// separated by `;`. Operations other than `id`, `bm`, `am` and `c0` are ignored.
+    pub fn parse(line: &str) -> Result<Self, Error> {
+        // Fields may be separated by any run of whitespace
+        let mut rest = line.trim();
+        let mut fen_fields = Vec::with_capacity(4);
+        for _ in 0..4 {
+            let (field, after) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            fen_fields.push(field);
+            rest = after.trim_start();
+        }
+        let fen = fen_fields.join(" ");
+        let setup = fen.parse::<Fen>()?.into_setup();
+        let position = VariantPosition::from_setup(Variant::Chess, setup, CastlingMode::Standard)?;
+        let mut entry = Self {
+            position,
+            id: None,
+            best_moves: Vec::new(),
+            avoid_moves: Vec::new(),
+            points: Vec::new(),
+        };
+
+        for operation in split_operations(rest) {
+            let (opcode, operands) = operation
+                .split_once(char::is_whitespace)
+                .unwrap_or((operation, ""));
+            let operands = operands.trim();
+            match opcode {
+                "id" => entry.id = Some(operands.trim_matches('"').to_owned()),
+                "bm" => entry.best_moves = entry.parse_moves(operands)?,
+                "am" => entry.avoid_moves = entry.parse_moves(operands)?,
+                "c0" => {
+                    // Other suites use c0 as a free-form comment, so anything
+                    // that isn't a list of move points is left alone
+                    let points: Option<Vec<_>> = operands
+                        .trim_matches('"')
+                        .split(',')
+                        .map(|pair| {
+                            let (san, points) = pair.trim().split_once('=')?;
+                            Some((entry.parse_move(san).ok()?, points.parse().ok()?))
+                        })
+                        .collect();
+                    entry.points = points.unwrap_or_default();
+                }
+                _ => {}
+            }
+        }
+
+        Ok(entry)
+    }
+
+    fn parse_moves(&self, operands: &str) -> Result<Vec<Move>, Error> {
+        operands
+            .split_whitespace()
+            .map(|san| self.parse_move(san))
+            .collect()
+    }
+
+    fn parse_move(&self, san: &str) -> Result<Move, Error> {
+        san.parse::<SanPlus>()
+            .ok()
+            .and_then(|parsed| parsed.san.to_move(&self.position).ok())
+            .ok_or_else(|| Error::IllegalMove(san.to_owned()))
+    }
+
+    /// Whether `chosen` is one of the best moves and none of the ones to avoid.
+    /// Without either list, whether it earns the most points.
+    pub fn is_solved(&self, chosen: Move) -> bool {
+        if self.best_moves.is_empty() && self.avoid_moves.is_empty() {
+            return !self.points.is_empty() && self.score(chosen) == self.max_score();
+        }
+        (self.best_moves.is_empty() || self.best_moves.contains(&chosen))
+            && !self.avoid_moves.contains(&chosen)
+    }
+
+    /// Points earned by `chosen`, or 1 for a solved position if the suite
+    /// doesn't award points.
+    pub fn score(&self, chosen: Move) -> u32 {
+        if self.points.is_empty() {
+            return u32::from(self.is_solved(chosen));
+        }
+        self.points
+            .iter()
+            .find(|(point_move, _)| *point_move == chosen)
+            .map_or(0, |(_, points)| *points)
+    }
+
+    /// Most points any move earns.
+    pub fn max_score(&self) -> u32 {
+        self.points
+            .iter()
+            .map(|(_, points)| *points)
+            .max()
+            .unwrap_or(1)
+    }
+}
+
+/// Splits at the `;` ending each operation, keeping quoted strings whole.
+fn split_operations(text: &str) -> Vec<&str> {
+    let mut operations = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    for (index, c) in text.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                operations.push(text[start..index].trim());
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    operations.push(text[start..].trim());
+    operations.retain(|operation| !operation.is_empty());
+    operations
+}
+
+/// Reads a suite with one EPD per line, skipping blank lines and `#` comments.
+/// Lines that can't be read are skipped too, after passing their line number,
+/// starting at 1, and the error to `on_error`.
+pub fn parse_suite(text: &str, mut on_error: impl FnMut(usize, Error)) -> Vec<EpdEntry> {
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|(number, line)| {
+            EpdEntry::parse(line)
+                .map_err(|error| on_error(number, error))
+                .ok()
+        })
+        .collect()
+}
+
+/// Totals over a suite.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SuiteResult {
+    pub positions: u32,
+    pub solved: u32,
+    pub score: u32,
+    pub max_score: u32,
+}
+
+/// Searches every entry for `movetime` with a fresh table, calling
+/// `on_position` with each entry and its search result as it finishes.
+pub fn run_suite(
+    entries: &[EpdEntry],
+    movetime: Duration,
+    mut on_position: impl FnMut(&EpdEntry, &SearchResult),
+) -> Result<SuiteResult, Error> {
+    let limits = SearchLimits {
+        movetime: Some(movetime),
+        ..SearchLimits::default()
+    };
+    let mut total = SuiteResult::default();
+    for entry in entries {
+        let controller =
+            SearchController::new(Arc::new(AtomicBool::new(true))).with_limits(&limits);
+        let result = iterative_deepening(
+            &entry.position,
+            &limits,
+            &PestoEvaluator,
+            SearchFeatures::default(),
+            &controller,
+            &mut TranspositionTable::default(),
+            &mut (),
+        )?;
+        total.positions += 1;
+        total.solved += u32::from(entry.is_solved(result.best_move));
+        total.score += entry.score(result.best_move);
+        total.max_score += entry.max_score();
+        on_position(entry, &result);
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_suite() {
+        let mut skipped = Vec::new();
+        let suite = parse_suite(
+            concat!(
+                "# mate in one\n",
+                "6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - bm Ra8#; id \"back rank; mate\";\n",
+                "\n",
+                "6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - bm Qh7#;\n",
+                "6k1/5ppp/8/8/8/8/5PPP/R5K1  w -\t-   am Ra2; c0 \"Ra8=10, Rb1=2\";\n",
+            ),
+            |number, error| skipped.push((number, error)),
+        );
+        assert_eq!(suite.len(), 2);
+        assert_eq!(suite[0].id.as_deref(), Some("back rank; mate"));
+        assert_eq!(suite[1].avoid_moves.len(), 1);
+        assert_eq!(suite[1].points.len(), 2);
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].0, 4);
+        assert!(matches!(skipped[0].1, Error::IllegalMove(_)));
+
+        let result = run_suite(&suite, Duration::from_millis(50), |_, _| {}).unwrap();
+        assert_eq!(
+            result,
+            SuiteResult {
+                positions: 2,
+                solved: 2,
+                score: 11,
+                max_score: 11,
+            }
+        );
+    }
+}
@@ -0,0 +1,38 @@
+use shakmaty::{PositionError, fen::ParseFenError, variant::VariantPosition};
+use thiserror::Error;
+
+/// Everything that can go wrong when driving the engine.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("invalid FEN: {0}")]
+    InvalidFen(#[from] ParseFenError),
+    // Boxed because the error carries the whole rejected position
+    #[error("invalid position: {0}")]
+    InvalidPosition(#[source] Box<PositionError<VariantPosition>>),
+    #[error("illegal move {0}")]
+    IllegalMove(String),
+    #[error("unknown option {0}")]
+    UnknownOption(String),
+    #[error("invalid value {value:?} for option {name}: {reason}")]
+    InvalidOptionValue {
+        name: String,
+        value: String,
+        reason: String,
+    },
+    #[error("expected \"name = value\" in config, got {0:?}")]
+    InvalidConfigLine(String),
+    #[error("invalid eval file: {0}")]
+    InvalidEvalFile(String),
+    #[error("no legal moves in this position")]
+    NoLegalMoves,
+    #[error("search was aborted by a crash in the search thread")]
+    SearchAborted,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl From<PositionError<VariantPosition>> for Error {
+    fn from(error: PositionError<VariantPosition>) -> Self {
+        Error::InvalidPosition(Box::new(error))
+    }
+}
@@ -1,6 +1,13 @@
-use std::sync::OnceLock;
+use std::sync::{Arc, OnceLock};
 
-use shakmaty::{Chess, Color, Outcome, Position, Role, Square};
+use shakmaty::{Board, Color, Outcome, Position, Role, Square, variant::VariantPosition};
+
+mod draw;
+mod pawns;
+mod weights;
+mod xray;
+
+pub use weights::EvalWeights;
 
 // Values taken from: https://www.chessprogramming.org/PeSTO%27s_Evaluation_Function
 const PIECE_VALUES_MG: [i64; 6] = [
@@ -183,102 +190,257 @@ pub fn get_piece_eg_increase(role: Role) -> i64 {
     }
 }
 
+/// Game phase of a position with all pieces still on the board.
+pub const MAX_GAME_PHASE: i64 = 24;
+
+/// How much of the middlegame is left, from 0 with only kings and pawns up to
+/// [`MAX_GAME_PHASE`]. Promotions can't push it any higher.
+pub fn game_phase(board: &Board) -> i64 {
+    board.iter().map(|(_, piece)| get_piece_eg_increase(piece.role)).sum::<i64>().min(MAX_GAME_PHASE)
+}
+
 // Color[PieceType[Square]]
 type PieceSquareTableType = [[[i64; 64]; 6]; 2];
 
 pub fn mg_table() -> &'static PieceSquareTableType {
     static MG_TABLE: OnceLock<PieceSquareTableType> = OnceLock::new();
     MG_TABLE.get_or_init(|| {
-        let mut m = [[[0; 64]; 6]; 2];
-
-        for square in Square::ALL {
-            for (piece_idx, _) in PIECE_VALUES_MG.iter().enumerate() {
-                let mg_value = match piece_idx {
-                    0 => MG_PAWN_TABLE[square as usize],
-                    1 => MG_KNIGHT_TABLE[square as usize],
-                    2 => MG_BISHOP_TABLE[square as usize],
-                    3 => MG_ROOK_TABLE[square as usize],
-                    4 => MG_QUEEN_TABLE[square as usize],
-                    5 => MG_KING_TABLE[square as usize],
-                    _ => unreachable!(),
-                } + PIECE_VALUES_MG[piece_idx];
-
-                m[Color::White as usize][piece_idx][square.flip_vertical() as usize] = mg_value;
-                m[Color::Black as usize][piece_idx][square as usize] = mg_value;
-            }
-        } 
-
-        m
+        build_table(&PIECE_VALUES_MG, [&MG_PAWN_TABLE, &MG_KNIGHT_TABLE, &MG_BISHOP_TABLE, &MG_ROOK_TABLE, &MG_QUEEN_TABLE, &MG_KING_TABLE])
     })
 }
 
 pub fn eg_table() -> &'static PieceSquareTableType {
     static EG_TABLE: OnceLock<PieceSquareTableType> = OnceLock::new();
     EG_TABLE.get_or_init(|| {
-        let mut m = [[[0; 64]; 6]; 2];
-
-        for square in Square::ALL {
-            for (piece_idx, _) in PIECE_VALUES_EG.iter().enumerate() {
-                let eg_value = match piece_idx {
-                    0 => EG_PAWN_TABLE[square as usize],
-                    1 => EG_KNIGHT_TABLE[square as usize],
-                    2 => EG_BISHOP_TABLE[square as usize],
-                    3 => EG_ROOK_TABLE[square as usize],
-                    4 => EG_QUEEN_TABLE[square as usize],
-                    5 => EG_KING_TABLE[square as usize],
-                    _ => unreachable!(),
-                } + PIECE_VALUES_EG[piece_idx];
-
-                m[Color::White as usize][piece_idx][square.flip_vertical() as usize] = eg_value;
-                m[Color::Black as usize][piece_idx][square as usize] = eg_value;
-            }
-        } 
-
-        m
+        build_table(&PIECE_VALUES_EG, [&EG_PAWN_TABLE, &EG_KNIGHT_TABLE, &EG_BISHOP_TABLE, &EG_ROOK_TABLE, &EG_QUEEN_TABLE, &EG_KING_TABLE])
     })
 }
 
-/// Calculates a chess position's score from the players's perspective.
-/// A positive score means the player is ahead; a negative score means the opponent is ahead.
-pub fn evaluate(position: &Chess) -> i64 {
-    let current_player_color = position.turn();
+/// Adds piece values to the per-piece tables, which are laid out from White's
+/// side with a8 first, and mirrors them for both colors.
+fn build_table(values: &[i64; 6], tables: [&[i64; 64]; 6]) -> PieceSquareTableType {
+    let mut m = [[[0; 64]; 6]; 2];
+
+    for square in Square::ALL {
+        for (piece_idx, table) in tables.iter().enumerate() {
+            let value = table[square as usize] + values[piece_idx];
+
+            m[Color::White as usize][piece_idx][square.flip_vertical() as usize] = value;
+            m[Color::Black as usize][piece_idx][square as usize] = value;
+        }
+    }
+
+    m
+}
+
+/// Scores positions for the search, from the side to move's perspective.
+/// Implementations are shared with the search thread, hence `Send + Sync`.
+///
+/// Game ends follow the rules of the position's variant, but the built-in
+/// evaluators otherwise score every variant like standard chess.
+pub trait Evaluator: Send + Sync {
+    /// A positive score means the side to move is ahead.
+    fn evaluate(&self, position: &VariantPosition) -> i64;
+}
+
+/// Tapered PeSTO evaluation, blending middlegame and endgame tables by game phase.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PestoEvaluator;
+
+impl Evaluator for PestoEvaluator {
+    fn evaluate(&self, position: &VariantPosition) -> i64 {
+        evaluate(position)
+    }
+}
+
+/// Classical piece-square evaluation using only the middlegame tables.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ClassicalEvaluator;
+
+impl Evaluator for ClassicalEvaluator {
+    fn evaluate(&self, position: &VariantPosition) -> i64 {
+        if let Some(score) = game_over_score(position) {
+            return score;
+        }
+
+        let mut evals = [0i64; 2];
+        for (square, piece) in position.board() {
+            evals[piece.color as usize] += mg_table()[piece.color as usize][piece.role as usize - 1][square as usize];
+        }
+
+        let current_player_color = position.turn();
+        evals[current_player_color as usize] - evals[current_player_color.other() as usize]
+    }
+}
+
+/// Counts material only, which makes search behavior easy to follow when debugging.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MaterialEvaluator;
+
+impl Evaluator for MaterialEvaluator {
+    fn evaluate(&self, position: &VariantPosition) -> i64 {
+        if let Some(score) = game_over_score(position) {
+            return score;
+        }
+
+        let mut evals = [0i64; 2];
+        for (_, piece) in position.board() {
+            evals[piece.color as usize] += PIECE_VALUES_MG[piece.role as usize - 1];
+        }
+
+        let current_player_color = position.turn();
+        evals[current_player_color as usize] - evals[current_player_color.other() as usize]
+    }
+}
+
+/// The built-in evaluators, as selected by the "Evaluator" option.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EvaluatorKind {
+    #[default]
+    Pesto,
+    Classical,
+    Material,
+}
+
+impl EvaluatorKind {
+    pub const ALL: [EvaluatorKind; 3] = [EvaluatorKind::Pesto, EvaluatorKind::Classical, EvaluatorKind::Material];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EvaluatorKind::Pesto => "PeSTO",
+            EvaluatorKind::Classical => "Classical",
+            EvaluatorKind::Material => "Material",
+        }
+    }
+
+    /// Looks up an evaluator by name, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn evaluator(self) -> Arc<dyn Evaluator> {
+        match self {
+            EvaluatorKind::Pesto => Arc::new(PestoEvaluator),
+            EvaluatorKind::Classical => Arc::new(ClassicalEvaluator),
+            EvaluatorKind::Material => Arc::new(MaterialEvaluator),
+        }
+    }
+}
+
+/// Score of a finished game, or `None` while it is still going.
+fn game_over_score(position: &impl Position) -> Option<i64> {
+    if !position.is_game_over() {
+        return None;
+    }
 
-    if position.is_game_over() {
-        return match position.outcome() {
-            Some(Outcome::Decisive { winner }) => {
-                if winner == current_player_color {
-                    MATE_SCORE
-                } else {
-                    -MATE_SCORE // Being checkmated is the worst outcome
-                }
+    Some(match position.outcome() {
+        Some(Outcome::Decisive { winner }) => {
+            if winner == position.turn() {
+                MATE_SCORE
+            } else {
+                -MATE_SCORE // Being checkmated is the worst outcome
             }
-            _ => 0, // Any other outcome (stalemate, etc.) is neutral
-        };
+        }
+        _ => 0, // Any other outcome (stalemate, etc.) is neutral
+    })
+}
+
+/// Names of the terms returned by [`evaluate_terms`], in order.
+pub const EVAL_TERMS: [&str; 4] = ["material and placement", "x-rays", "pawn structure", "unstoppable passers"];
+
+/// The parts [`evaluate`] adds up, for the side to move and blended by game phase,
+/// before fortress-like positions are scaled towards a draw.
+pub fn evaluate_terms(position: &impl Position) -> [i64; 4] {
+    evaluate_terms_with(position, mg_table(), eg_table())
+}
+
+/// [`evaluate_terms`] with other middlegame and endgame piece-square tables.
+fn evaluate_terms_with(position: &impl Position, mg_table: &PieceSquareTableType, eg_table: &PieceSquareTableType) -> [i64; 4] {
+    let trace = eval_trace_with(position, mg_table, eg_table);
+    trace.terms.map(|term| term.blend(position.turn(), trace.phase))
+}
+
+/// One of the [`EVAL_TERMS`] for each color, indexed by `Color`, before blending by game phase.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TermTrace {
+    pub mg: [i64; 2],
+    pub eg: [i64; 2],
+}
+
+impl TermTrace {
+    /// What the term is worth to `color` over the other color at game phase `phase`.
+    pub fn blend(&self, color: Color, phase: i64) -> i64 {
+        let mg_score = self.mg[color as usize] - self.mg[color.other() as usize];
+        let eg_score = self.eg[color as usize] - self.eg[color.other() as usize];
+        (mg_score * phase + eg_score * (MAX_GAME_PHASE - phase)) / MAX_GAME_PHASE
     }
+}
+
+/// What [`evaluate_terms`] is made of, for showing how a score comes about.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EvalTrace {
+    /// In the order of [`EVAL_TERMS`].
+    pub terms: [TermTrace; 4],
+    /// As given by [`game_phase`].
+    pub phase: i64,
+}
 
-    let mut mg_evals = [0i64; 2];
-    let mut eg_evals = [0i64; 2];
-    let mut game_phase = 0;
+pub fn eval_trace(position: &impl Position) -> EvalTrace {
+    eval_trace_with(position, mg_table(), eg_table())
+}
+
+fn eval_trace_with(position: &impl Position, mg_table: &PieceSquareTableType, eg_table: &PieceSquareTableType) -> EvalTrace {
+    let current_player_color = position.turn();
     let board = position.board();
+    let mut terms = [TermTrace::default(); 4];
 
     for (square, piece) in board {
         // piece.color is 0 for Black and 1 for White
         // piece.role is 1-indexed (1 for Pawn, 2 for Knight, etc.)
-        mg_evals[piece.color as usize] += mg_table()[piece.color as usize][piece.role as usize - 1][square as usize];
-        eg_evals[piece.color as usize] += eg_table()[piece.color as usize][piece.role as usize - 1][square as usize];
-        game_phase += get_piece_eg_increase(piece.role);
+        terms[0].mg[piece.color as usize] += mg_table[piece.color as usize][piece.role as usize - 1][square as usize];
+        terms[0].eg[piece.color as usize] += eg_table[piece.color as usize][piece.role as usize - 1][square as usize];
     }
 
-    let mg_score = mg_evals[current_player_color as usize] - mg_evals[current_player_color.other() as usize];
-    let eg_score = eg_evals[current_player_color as usize] - eg_evals[current_player_color.other() as usize];
-    let mg_phase = game_phase.min(24);
-    let eg_phase = 24 - mg_phase;
+    for color in Color::ALL {
+        let tapered = [xray::xray_pressure(board, color), pawns::pawn_structure(board, color)];
+        for (term, (mg, eg)) in tapered.into_iter().enumerate() {
+            terms[term + 1].mg[color as usize] += mg;
+            terms[term + 1].eg[color as usize] += eg;
+        }
+
+        // Worth the same in every phase
+        if pawns::has_unstoppable_passer(board, color, current_player_color) {
+            terms[3].mg[color as usize] += pawns::UNSTOPPABLE_PASSER;
+            terms[3].eg[color as usize] += pawns::UNSTOPPABLE_PASSER;
+        }
+    }
+
+    EvalTrace { terms, phase: game_phase(board) }
+}
+
+/// Calculates a chess position's score from the players's perspective.
+/// A positive score means the player is ahead; a negative score means the opponent is ahead.
+pub fn evaluate(position: &impl Position) -> i64 {
+    evaluate_with(position, mg_table(), eg_table())
+}
+
+/// [`evaluate`] with other middlegame and endgame piece-square tables.
+fn evaluate_with(position: &impl Position, mg_table: &PieceSquareTableType, eg_table: &PieceSquareTableType) -> i64 {
+    let current_player_color = position.turn();
+
+    if let Some(score) = game_over_score(position) {
+        return score;
+    }
 
-    (mg_score * mg_phase + eg_score * eg_phase) / 24
+    let score: i64 = evaluate_terms_with(position, mg_table, eg_table).iter().sum();
+    let strong = if score > 0 { current_player_color } else { current_player_color.other() };
+    score * draw::draw_scale(position.board(), strong) / draw::SCALE_NORMAL
 }
 
 #[cfg(test)]
 mod test {
+    use shakmaty::Chess;
+
     use super::*;
 
     #[test]
@@ -287,4 +449,74 @@ mod test {
         let evaluation = evaluate(&position);
         assert_eq!(evaluation, 0);
     }
+
+    #[test]
+    fn test_evaluators_agree_on_sign() {
+        let position: VariantPosition = "3k4/8/8/8/8/8/8/QQQKQQQQ w - - 0 1"
+            .parse::<shakmaty::fen::Fen>()
+            .unwrap()
+            .into_position::<Chess>(shakmaty::CastlingMode::Standard)
+            .unwrap()
+            .into();
+        for kind in EvaluatorKind::ALL {
+            assert_eq!(kind.evaluator().evaluate(&VariantPosition::default()), 0);
+            assert!(kind.evaluator().evaluate(&position) > 0, "{}", kind.name());
+            assert_eq!(EvaluatorKind::from_name(&kind.name().to_lowercase()), Some(kind));
+        }
+    }
+
+    #[test]
+    fn test_xray_through_blocker() {
+        // The rook on e1 sees through the knight on e4 to the queen on e7
+        let board: shakmaty::Board = "4k3/4q3/8/8/4N3/8/8/K3R3".parse().unwrap();
+        let xrays = xray::xray_attacks(Square::E1, Role::Rook, board.occupied());
+        assert!(xrays.contains(Square::E7));
+        assert!(!xrays.contains(Square::E4));
+        assert!(xray::xray_pressure(&board, Color::White).0 > 0);
+        assert_eq!(xray::xray_pressure(&board, Color::Black), (0, 0));
+    }
+
+    #[test]
+    fn test_outside_passer() {
+        // Both sides have a passer, but only White's is far from the enemy king
+        let board: shakmaty::Board = "8/5k2/5p2/8/8/P7/5K2/8".parse().unwrap();
+        assert_eq!(pawns::passed_pawns(&board, Color::White).count(), 1);
+        let white = pawns::pawn_structure(&board, Color::White);
+        let black = pawns::pawn_structure(&board, Color::Black);
+        assert!(white.1 > black.1);
+    }
+
+    #[test]
+    fn test_rule_of_the_square() {
+        // The king on e5 is just outside the square of the a5 pawn
+        let board: shakmaty::Board = "8/8/8/P3k3/8/8/8/7K".parse().unwrap();
+        assert!(pawns::has_unstoppable_passer(&board, Color::White, Color::White));
+        assert!(!pawns::has_unstoppable_passer(&board, Color::White, Color::Black));
+        // From its starting square the pawn may step two squares
+        let board: shakmaty::Board = "8/8/8/6k1/8/8/P7/7K".parse().unwrap();
+        assert!(pawns::has_unstoppable_passer(&board, Color::White, Color::White));
+        // Black's h-pawn queens two moves before White's a-pawn
+        let board: shakmaty::Board = "8/8/8/P3k3/8/7p/8/K7".parse().unwrap();
+        assert!(!pawns::has_unstoppable_passer(&board, Color::White, Color::White));
+    }
+
+    #[test]
+    fn test_fortresses_scale_towards_draw() {
+        let score = |fen: &str| {
+            let position: Chess = fen
+                .parse::<shakmaty::fen::Fen>()
+                .unwrap()
+                .into_position(shakmaty::CastlingMode::Standard)
+                .unwrap();
+            evaluate(&position)
+        };
+        // A rook against a bishop, and an extra bishop behind locked pawns
+        assert!(score("8/8/3k4/8/5b2/8/2K5/5R2 w - - 0 1").abs() < 20);
+        assert!(score("8/8/1k6/p1p1p3/P1P1P3/8/3B4/3K4 w - - 0 1").abs() < 50);
+        // With an open file the rook can still win
+        assert!(score("8/8/3k4/2p5/8/8/2K5/5R2 w - - 0 1") > 200);
+        // A queen against two knights or two bishops is a win, not a fortress
+        assert!(score("1n2k1n1/8/8/8/8/8/8/3QK3 w - - 0 1") > 200);
+        assert!(score("2b1kb2/8/8/8/8/8/8/3QK3 w - - 0 1") > 200);
+    }
 }
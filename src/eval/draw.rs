@@ -0,0 +1,83 @@
+use shakmaty::{Bitboard, Board, Color, Role, attacks};
+
+/// Weight of an evaluation that needs no scaling.
+pub(super) const SCALE_NORMAL: i64 = 64;
+
+/// Rough piece values in pawns, for judging what the pieces can still do.
+fn piece_units(role: Role) -> u32 {
+    match role {
+        Role::Knight | Role::Bishop => 3,
+        Role::Rook => 5,
+        Role::Queen => 9,
+        Role::Pawn | Role::King => 0,
+    }
+}
+
+fn side(board: &Board, color: Color, role: Role) -> Bitboard {
+    board.by_role(role) & board.by_color(color)
+}
+
+/// Material other than pawns, in pawn units.
+fn piece_material(board: &Board, color: Color) -> u32 {
+    Role::ALL
+        .into_iter()
+        .map(|role| side(board, color, role).count() as u32 * piece_units(role))
+        .sum()
+}
+
+/// True if no pawn of `color` can move or capture, so `color` can't open the position.
+fn no_pawn_breaks(board: &Board, color: Color) -> bool {
+    let pawns = side(board, color, Role::Pawn);
+    let enemy_pawns = side(board, !color, Role::Pawn);
+    let ahead = match color {
+        Color::White => pawns.shift(8),
+        Color::Black => pawns.shift(-8),
+    };
+    // Every pawn is rammed by an enemy pawn...
+    if ahead & enemy_pawns != ahead {
+        return false;
+    }
+    // ...and none can capture one
+    pawns
+        .into_iter()
+        .all(|square| (attacks::pawn_attacks(color, square) & enemy_pawns).is_empty())
+}
+
+/// Only a bishop each, on squares of different colors, and pawns.
+fn opposite_bishops_only(board: &Board) -> bool {
+    let bishops = board.bishops();
+    let others = board.knights() | board.rooks() | board.queens();
+    let light = bishops & Bitboard::LIGHT_SQUARES;
+    let dark = bishops & Bitboard::DARK_SQUARES;
+    others.is_empty()
+        && light.count() == 1
+        && dark.count() == 1
+        && (light & board.white()).is_empty() != (dark & board.white()).is_empty()
+}
+
+/// How much of an advantage for `strong` should count, out of
+/// [`SCALE_NORMAL`]. Fortress-like positions, where the side that is ahead
+/// has no way to make progress, are scaled towards a draw.
+pub(super) fn draw_scale(board: &Board, strong: Color) -> i64 {
+    let strong_pieces = piece_material(board, strong);
+    let has_pawns = side(board, strong, Role::Pawn).any();
+    // A queen beats minor pieces alone, however many units they add up to
+    let weak_heavy = (board.rooks() | board.queens()) & board.by_color(!strong);
+    let queen_against_minors = side(board, strong, Role::Queen).any() && weak_heavy.is_empty();
+
+    // At most a minor piece up and nothing to promote: no mate can be forced
+    if !has_pawns
+        && !queen_against_minors
+        && strong_pieces <= piece_material(board, !strong) + 3
+    {
+        return SCALE_NORMAL / 16;
+    }
+    // Locked pawns and too little left to break through with
+    if has_pawns && strong_pieces <= 3 && no_pawn_breaks(board, strong) {
+        return SCALE_NORMAL / 8;
+    }
+    if opposite_bishops_only(board) {
+        return SCALE_NORMAL / 2;
+    }
+    SCALE_NORMAL
+}
@@ -0,0 +1,107 @@
+use shakmaty::{Bitboard, Board, Color, Rank, Square};
+
+/// Endgame bonus for having more pawns than the opponent on one wing.
+const MAJORITY_EG: i64 = 12;
+/// Endgame bonus per file a passed pawn is away from the enemy king, beyond
+/// the first two.
+const OUTSIDE_PASSER_EG: i64 = 10;
+/// Bonus for a passed pawn the enemy king can't catch, close to the value of
+/// the queen it becomes.
+pub(super) const UNSTOPPABLE_PASSER: i64 = 700;
+
+const QUEENSIDE: Bitboard = Bitboard(0x0f0f_0f0f_0f0f_0f0f);
+const KINGSIDE: Bitboard = Bitboard(0xf0f0_f0f0_f0f0_f0f0);
+
+fn file_mask(file: u32) -> Bitboard {
+    Bitboard(0x0101_0101_0101_0101 << file)
+}
+
+/// The pawn's file and its neighbors, on the ranks in front of it.
+fn front_span(square: Square, color: Color) -> Bitboard {
+    let file = square.file() as u32;
+    let mut files = file_mask(file);
+    if file > 0 {
+        files |= file_mask(file - 1);
+    }
+    if file < 7 {
+        files |= file_mask(file + 1);
+    }
+    files & ranks_ahead(square, color)
+}
+
+/// Every square on the ranks in front of `square`, seen from `color`.
+fn ranks_ahead(square: Square, color: Color) -> Bitboard {
+    let rank = square.rank() as u32;
+    match color {
+        Color::White => Bitboard(u64::MAX.checked_shl(8 * (rank + 1)).unwrap_or(0)),
+        Color::Black => Bitboard((1 << (8 * rank)) - 1),
+    }
+}
+
+/// Pawns of `color` with no enemy pawn in front of them on their own or a
+/// neighboring file.
+pub(super) fn passed_pawns(board: &Board, color: Color) -> Bitboard {
+    let enemy_pawns = board.pawns() & board.by_color(!color);
+    (board.pawns() & board.by_color(color))
+        .into_iter()
+        .filter(|&square| (front_span(square, color) & enemy_pawns).is_empty())
+        .collect()
+}
+
+/// Middlegame and endgame bonus for the pawn majorities and outside passed
+/// pawns of `color`. A passer far from the enemy king draws it away from the
+/// other wing, which decides many pawn endings.
+pub(super) fn pawn_structure(board: &Board, color: Color) -> (i64, i64) {
+    let pawns = board.pawns() & board.by_color(color);
+    let enemy_pawns = board.pawns() & board.by_color(!color);
+
+    let mut eg = 0;
+    for wing in [QUEENSIDE, KINGSIDE] {
+        if (pawns & wing).count() > (enemy_pawns & wing).count() {
+            eg += MAJORITY_EG;
+        }
+    }
+
+    if let Some(king) = board.king_of(!color) {
+        for square in passed_pawns(board, color) {
+            let file_distance = (square.file() as i64 - king.file() as i64).abs();
+            eg += OUTSIDE_PASSER_EG * (file_distance - 2).max(0);
+        }
+    }
+
+    (0, eg)
+}
+
+/// Whether `color` has a passed pawn that promotes before the enemy king can
+/// catch it, by the rule of the square. `turn` is the side to move, which
+/// gains a tempo in the race. Only counts when the enemy has nothing but its
+/// king, as any piece could stop the pawn and a pawn of its own might
+/// promote first.
+pub(super) fn has_unstoppable_passer(board: &Board, color: Color, turn: Color) -> bool {
+    let defenders = board.by_color(!color);
+    if (defenders & !board.kings()).any() {
+        return false;
+    }
+    let Some(king) = board.king_of(!color) else {
+        return false;
+    };
+
+    passed_pawns(board, color).into_iter().any(|square| {
+        let path = file_mask(square.file() as u32) & ranks_ahead(square, color);
+        if (path & board.occupied()).any() {
+            return false;
+        }
+        let (relative_rank, promotion_rank) = match color {
+            Color::White => (square.rank() as u32, Rank::Eighth),
+            Color::Black => (7 - square.rank() as u32, Rank::First),
+        };
+        // A pawn on its starting rank can step two squares at once
+        let pawn_moves = if relative_rank == 1 {
+            5
+        } else {
+            7 - relative_rank
+        };
+        let king_moves = king.distance(Square::from_coords(square.file(), promotion_rank));
+        pawn_moves + u32::from(turn != color) < king_moves
+    })
+}
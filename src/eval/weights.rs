@@ -0,0 +1,165 @@
+use std::{fs, path::Path};
+
+use shakmaty::{Position, variant::VariantPosition};
+
+use super::{
+    EvalTrace, Evaluator, PieceSquareTableType, build_table, eg_table, eval_trace_with,
+    evaluate_with, mg_table,
+};
+use crate::error::Error;
+
+/// Numbers per game phase in an eval file: six piece values, then a table of
+/// 64 squares for each piece.
+const PHASE_WEIGHTS: usize = 6 + 6 * 64;
+/// Largest weight accepted, which keeps every score far from mate scores.
+const MAX_WEIGHT: i64 = 10_000;
+
+/// Piece values and piece-square tables for the PeSTO evaluation, loaded from
+/// an eval file instead of the ones built in.
+///
+/// The file holds whitespace separated integers, with `#` starting a comment:
+/// the middlegame weights, then the endgame weights. Pieces go from pawn to
+/// king and squares from a8 to h1, as seen by White.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvalWeights {
+    mg: PieceSquareTableType,
+    eg: PieceSquareTableType,
+}
+
+impl EvalWeights {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let weights = text
+            .lines()
+            .flat_map(|line| line.split('#').next().unwrap_or_default().split_whitespace())
+            .map(|word| {
+                word.parse::<i64>()
+                    .ok()
+                    .filter(|weight| weight.abs() <= MAX_WEIGHT)
+                    .ok_or_else(|| Error::InvalidEvalFile(format!("bad weight {word:?}")))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if weights.len() != 2 * PHASE_WEIGHTS {
+            return Err(Error::InvalidEvalFile(format!(
+                "expected {} weights, found {}",
+                2 * PHASE_WEIGHTS,
+                weights.len()
+            )));
+        }
+
+        let (mg, eg) = weights.split_at(PHASE_WEIGHTS);
+        Ok(Self {
+            mg: phase_table(mg),
+            eg: phase_table(eg),
+        })
+    }
+
+    /// Like [`super::eval_trace`], with these weights.
+    pub fn trace(&self, position: &impl Position) -> EvalTrace {
+        eval_trace_with(position, &self.mg, &self.eg)
+    }
+}
+
+impl Default for EvalWeights {
+    /// The built-in weights.
+    fn default() -> Self {
+        Self {
+            mg: *mg_table(),
+            eg: *eg_table(),
+        }
+    }
+}
+
+impl Evaluator for EvalWeights {
+    fn evaluate(&self, position: &VariantPosition) -> i64 {
+        evaluate_with(position, &self.mg, &self.eg)
+    }
+}
+
+/// Tables for one game phase from its [`PHASE_WEIGHTS`] numbers.
+fn phase_table(weights: &[i64]) -> PieceSquareTableType {
+    let (values, tables) = weights.split_at(6);
+    let tables: Vec<[i64; 64]> = tables
+        .chunks_exact(64)
+        .map(|table| table.try_into().expect("64 squares"))
+        .collect();
+    build_table(
+        values.try_into().expect("6 pieces"),
+        std::array::from_fn(|piece| &tables[piece]),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use shakmaty::{CastlingMode, fen::Fen};
+
+    use super::super::*;
+    use super::*;
+
+    fn format_phase(values: &[i64; 6], tables: [&[i64; 64]; 6]) -> String {
+        let mut text = String::from("# piece values\n");
+        text += &values.map(|value| value.to_string()).join(" ");
+        for table in tables {
+            text += "\n# table\n";
+            text += &table.map(|value| value.to_string()).join(" ");
+        }
+        text + "\n"
+    }
+
+    #[test]
+    fn test_eval_file() {
+        let text = format_phase(
+            &PIECE_VALUES_MG,
+            [
+                &MG_PAWN_TABLE,
+                &MG_KNIGHT_TABLE,
+                &MG_BISHOP_TABLE,
+                &MG_ROOK_TABLE,
+                &MG_QUEEN_TABLE,
+                &MG_KING_TABLE,
+            ],
+        ) + &format_phase(
+            &PIECE_VALUES_EG,
+            [
+                &EG_PAWN_TABLE,
+                &EG_KNIGHT_TABLE,
+                &EG_BISHOP_TABLE,
+                &EG_ROOK_TABLE,
+                &EG_QUEEN_TABLE,
+                &EG_KING_TABLE,
+            ],
+        );
+        let weights = EvalWeights::parse(&text).unwrap();
+        assert_eq!(weights, EvalWeights::default());
+
+        let position = |fen: &str| {
+            let fen: Fen = fen.parse().unwrap();
+            VariantPosition::Chess(fen.into_position(CastlingMode::Standard).unwrap())
+        };
+        let italian =
+            position("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4");
+        assert_eq!(weights.evaluate(&italian), evaluate(&italian));
+
+        // A heavier knight in the file makes being a knight up worth more
+        let heavy_knight =
+            EvalWeights::parse(&text.replacen("82 337 365", "82 1337 365", 1)).unwrap();
+        let up_a_knight = position("4k3/pppp4/8/8/8/8/PPPP4/3NK3 w - - 0 1");
+        assert!(heavy_knight.evaluate(&up_a_knight) > weights.evaluate(&up_a_knight));
+
+        assert!(matches!(
+            EvalWeights::parse("1 2 3"),
+            Err(Error::InvalidEvalFile(_))
+        ));
+        assert!(matches!(
+            EvalWeights::parse(&text.replace("82", "x")),
+            Err(Error::InvalidEvalFile(_))
+        ));
+        assert!(matches!(
+            EvalWeights::load("/nonexistent/eval.txt"),
+            Err(Error::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,44 @@
+use shakmaty::{Bitboard, Board, Color, Role, Square, attacks};
+
+/// Middlegame and endgame bonus for a slider lined up with an enemy piece
+/// behind exactly one blocker, by the role of the target.
+fn xray_bonus(slider: Role, target: Role) -> (i64, i64) {
+    match (slider, target) {
+        (_, Role::King) => (12, 4),
+        (Role::Bishop | Role::Rook, Role::Queen) => (14, 10),
+        (Role::Bishop, Role::Rook) => (8, 6),
+        _ => (0, 0),
+    }
+}
+
+/// Squares a slider attacks once the first piece on each of its lines is
+/// taken away, not counting those it attacks directly.
+pub(super) fn xray_attacks(square: Square, role: Role, occupied: Bitboard) -> Bitboard {
+    let slider_attacks = |occupied| match role {
+        Role::Bishop => attacks::bishop_attacks(square, occupied),
+        Role::Rook => attacks::rook_attacks(square, occupied),
+        _ => attacks::queen_attacks(square, occupied),
+    };
+    let direct = slider_attacks(occupied);
+    slider_attacks(occupied ^ (direct & occupied)) & !direct
+}
+
+/// Pressure from the sliders of `color` through one blocking piece, such as a
+/// rook behind a knight on the enemy queen's file. The blocker may belong to
+/// either side: it is pinned or ready to uncover an attack.
+pub(super) fn xray_pressure(board: &Board, color: Color) -> (i64, i64) {
+    let sliders = board.by_color(color) & (board.bishops() | board.rooks() | board.queens());
+    let targets = board.by_color(!color) & (board.kings() | board.queens() | board.rooks());
+
+    let mut score = (0, 0);
+    for square in sliders {
+        let role = board.role_at(square).expect("sliders are occupied");
+        for target in xray_attacks(square, role, board.occupied()) & targets {
+            let target_role = board.role_at(target).expect("targets are occupied");
+            let (mg, eg) = xray_bonus(role, target_role);
+            score.0 += mg;
+            score.1 += eg;
+        }
+    }
+    score
+}
@@ -0,0 +1,167 @@
+use std::sync::{Arc, atomic::AtomicBool};
+
+use shakmaty::{
+    EnPassantMode, Move, Position,
+    variant::VariantPosition,
+    zobrist::{Zobrist64, ZobristHash},
+};
+
+use crate::controller::SearchController;
+use crate::engine::{
+    SearchFeatures, SearchResult, Searcher, TranspositionInformation, principal_variation,
+};
+use crate::error::Error;
+use crate::eval::{EVAL_TERMS, Evaluator, evaluate_terms};
+use crate::history::QuietHistory;
+use crate::transposition::TranspositionTable;
+
+/// Deepest search used to compare the root moves with each other.
+const MAX_COMPARISON_DEPTH: u64 = 6;
+
+/// A root move and what a search thinks of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootMove {
+    pub root_move: Move,
+    /// For the side to move at the root.
+    pub score: i64,
+    pub pv: Vec<Move>,
+    /// How much better each evaluation term, named as in [`EVAL_TERMS`], rates
+    /// the position right after the chosen move than the one after this move.
+    pub term_deltas: Vec<(&'static str, i64)>,
+}
+
+/// Why the engine chose its move, for people learning from it or wondering
+/// about a surprising decision.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    /// The chosen move first, then the best alternatives.
+    pub moves: Vec<RootMove>,
+    /// Searched to compare the moves, which may be shallower than the search
+    /// that chose the move.
+    pub depth: u64,
+    /// The transposition table suggested the chosen move before the search began.
+    pub from_table: bool,
+}
+
+/// The move the transposition table holds for `position`, to be taken before
+/// a search and passed to [`explain`] afterwards.
+pub fn table_move(
+    position: &VariantPosition,
+    transposition_table: &TranspositionTable,
+) -> Option<Move> {
+    let hash = position.zobrist_hash::<Zobrist64>(EnPassantMode::Legal);
+    transposition_table
+        .get(&hash)
+        .and_then(TranspositionInformation::best_move)
+}
+
+/// Searches every root move on its own to line up the chosen move of `result`
+/// against up to `alternatives` others, reusing the table of the search.
+pub fn explain<E: Evaluator + ?Sized>(
+    position: &VariantPosition,
+    result: &SearchResult,
+    evaluator: &E,
+    features: SearchFeatures,
+    transposition_table: &mut TranspositionTable,
+    table_move_before: Option<Move>,
+    alternatives: usize,
+) -> Result<Explanation, Error> {
+    let depth = result.depth.clamp(1, MAX_COMPARISON_DEPTH);
+    let mut history = QuietHistory::new();
+
+    let mut moves = Vec::new();
+    for root_move in position.legal_moves() {
+        let controller = SearchController::new(Arc::new(AtomicBool::new(true)));
+        let search_moves = [root_move];
+        let mut searcher = Searcher::new(
+            position,
+            evaluator,
+            depth,
+            &controller,
+            None,
+            transposition_table,
+            &mut history,
+        )
+        .with_search_moves(&search_moves)
+        .with_features(features);
+        searcher.next_move()?;
+        let score = searcher.best_score();
+        moves.push(RootMove {
+            root_move,
+            score,
+            pv: principal_variation(position, root_move, transposition_table),
+            term_deltas: Vec::new(),
+        });
+    }
+
+    // The chosen move leads even if the shallower search disagrees
+    moves.sort_by_key(|root_move| (root_move.root_move != result.best_move, -root_move.score));
+    moves.truncate(alternatives + 1);
+
+    let chosen_terms = terms_after(position, result.best_move);
+    for root_move in moves.iter_mut().skip(1) {
+        let terms = terms_after(position, root_move.root_move);
+        root_move.term_deltas = EVAL_TERMS
+            .iter()
+            .zip(chosen_terms.iter().zip(terms))
+            .map(|(&name, (chosen, other))| (name, chosen - other))
+            .collect();
+    }
+
+    Ok(Explanation {
+        moves,
+        depth,
+        from_table: table_move_before == Some(result.best_move),
+    })
+}
+
+/// Evaluation terms after `m`, for the side that played it.
+fn terms_after(position: &VariantPosition, m: Move) -> Vec<i64> {
+    let mut after = position.clone();
+    after.play_unchecked(m);
+    evaluate_terms(&after).iter().map(|term| -term).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::engine::ScoreBound;
+    use crate::eval::PestoEvaluator;
+
+    #[test]
+    fn test_chosen_move_comes_first() {
+        let position = VariantPosition::default();
+        let mut table = TranspositionTable::default();
+        let m = position.legal_moves()[3];
+        let result = SearchResult {
+            best_move: m,
+            ponder: None,
+            score: 0,
+            bound: ScoreBound::Exact,
+            pv: vec![m],
+            depth: 2,
+            seldepth: 2,
+            nodes: 0,
+            time: Duration::ZERO,
+        };
+
+        let explanation = explain(
+            &position,
+            &result,
+            &PestoEvaluator,
+            SearchFeatures::default(),
+            &mut table,
+            None,
+            3,
+        )
+        .unwrap();
+        assert_eq!(explanation.moves.len(), 4);
+        assert_eq!(explanation.moves[0].root_move, m);
+        assert!(explanation.moves[0].term_deltas.is_empty());
+        assert_eq!(explanation.moves[1].term_deltas.len(), EVAL_TERMS.len());
+        assert!(explanation.moves[1].score >= explanation.moves[2].score);
+        assert!(!explanation.from_table);
+    }
+}
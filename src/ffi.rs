@@ -0,0 +1,368 @@
+use std::{
+    ffi::{CStr, c_char},
+    sync::{Arc, atomic::AtomicBool},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use shakmaty::{
+    CastlingMode, Position,
+    fen::Fen,
+    uci::UciMove,
+    variant::{Variant, VariantPosition},
+};
+
+use crate::controller::SearchController;
+use crate::engine::{
+    SearchFeatures, SearchLimits, SearchResult, iterative_deepening, mate_in_moves,
+};
+use crate::error::Error;
+use crate::eval::PestoEvaluator;
+use crate::transposition::TranspositionTable;
+
+/// Outcome of a call into the engine.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChessBotStatus {
+    Ok = 0,
+    /// The search is still running; poll again later.
+    Pending = 1,
+    /// A null handle, unreadable string, invalid FEN or illegal move.
+    InvalidArgument = -1,
+    /// A search is running; stop it and poll for its result first.
+    Busy = -2,
+    /// Nothing has been searched since the position was set.
+    NoResult = -3,
+    /// The search failed, e.g. because the position has no legal moves.
+    SearchFailed = -4,
+}
+
+/// A finished search.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ChessBotResult {
+    /// Best move in UCI notation, e.g. "e2e4" or "e7e8q", NUL terminated.
+    pub best_move: [c_char; 8],
+    /// Centipawns from the side to move's point of view. For a forced mate
+    /// this is a huge number that only tells quicker mates from slower ones;
+    /// read `mate` instead.
+    pub score: i64,
+    /// Moves until a forced mate: positive if the side to move mates,
+    /// negative if it gets mated, 0 if no mate was found.
+    pub mate: i64,
+    pub depth: u64,
+    pub nodes: u64,
+}
+
+/// Engine handle for C hosts. Searches run on a thread of their own, so the
+/// host polls for the result instead of blocking.
+pub struct ChessBotEngine {
+    position: VariantPosition,
+    /// `None` while a search has it.
+    table: Option<TranspositionTable>,
+    search: Option<RunningSearch>,
+    result: Option<Result<SearchResult, Error>>,
+}
+
+struct RunningSearch {
+    controller: SearchController,
+    handle: JoinHandle<(TranspositionTable, Result<SearchResult, Error>)>,
+}
+
+impl ChessBotEngine {
+    fn new() -> Self {
+        Self {
+            position: VariantPosition::new(Variant::Chess),
+            table: Some(TranspositionTable::default()),
+            search: None,
+            result: None,
+        }
+    }
+
+    /// Collects the result of a finished search; `false` if it is still running.
+    fn collect(&mut self) -> bool {
+        let Some(search) = self.search.take_if(|search| search.handle.is_finished()) else {
+            return self.search.is_none();
+        };
+        let (table, result) = search
+            .handle
+            .join()
+            .unwrap_or_else(|_| (TranspositionTable::default(), Err(Error::SearchAborted)));
+        self.table = Some(table);
+        self.result = Some(result);
+        true
+    }
+}
+
+impl Drop for ChessBotEngine {
+    fn drop(&mut self) {
+        if let Some(search) = self.search.take() {
+            search.controller.stop();
+            let _ = search.handle.join();
+        }
+    }
+}
+
+/// Creates an engine set up with the initial position. Free it with [`chess_bot_free`].
+#[unsafe(no_mangle)]
+pub extern "C" fn chess_bot_new() -> *mut ChessBotEngine {
+    Box::into_raw(Box::new(ChessBotEngine::new()))
+}
+
+/// Stops a running search and frees the engine.
+///
+/// # Safety
+///
+/// `engine` must be null or come from [`chess_bot_new`], and must not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn chess_bot_free(engine: *mut ChessBotEngine) {
+    if !engine.is_null() {
+        // SAFETY: the caller hands back ownership of a handle from chess_bot_new
+        drop(unsafe { Box::from_raw(engine) });
+    }
+}
+
+/// Sets up the position from a FEN, or the initial position if `fen` is null
+/// or "startpos", followed by space separated UCI moves; `moves` may be null.
+///
+/// # Safety
+///
+/// `engine` must be null or a live handle, and `fen` and `moves` null or
+/// NUL-terminated strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn chess_bot_set_position(
+    engine: *mut ChessBotEngine,
+    fen: *const c_char,
+    moves: *const c_char,
+) -> ChessBotStatus {
+    // SAFETY: guaranteed by the caller
+    let Some(engine) = (unsafe { engine.as_mut() }) else {
+        return ChessBotStatus::InvalidArgument;
+    };
+    if !engine.collect() {
+        return ChessBotStatus::Busy;
+    }
+    // SAFETY: guaranteed by the caller
+    let (Some(fen), Some(moves)) = (unsafe { read_str(fen) }, unsafe { read_str(moves) }) else {
+        return ChessBotStatus::InvalidArgument;
+    };
+    match parse_position(fen, moves) {
+        Ok(position) => {
+            engine.position = position;
+            engine.result = None;
+            ChessBotStatus::Ok
+        }
+        Err(_) => ChessBotStatus::InvalidArgument,
+    }
+}
+
+/// Starts searching the position in the background. The search ends at
+/// `depth` plies or after `movetime_ms`, whichever comes first; 0 leaves a
+/// limit out, and with both left out it runs until [`chess_bot_stop`].
+///
+/// # Safety
+///
+/// `engine` must be null or a live handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn chess_bot_search(
+    engine: *mut ChessBotEngine,
+    depth: u32,
+    movetime_ms: u64,
+) -> ChessBotStatus {
+    // SAFETY: guaranteed by the caller
+    let Some(engine) = (unsafe { engine.as_mut() }) else {
+        return ChessBotStatus::InvalidArgument;
+    };
+    if !engine.collect() {
+        return ChessBotStatus::Busy;
+    }
+
+    let limits = SearchLimits {
+        depth: (depth > 0).then_some(u64::from(depth)),
+        movetime: (movetime_ms > 0).then(|| Duration::from_millis(movetime_ms)),
+        ..SearchLimits::default()
+    };
+    let controller = SearchController::new(Arc::new(AtomicBool::new(true))).with_limits(&limits);
+    let position = engine.position.clone();
+    let mut table = engine.table.take().expect("Idle engines own their table");
+    let search_controller = controller.clone();
+    let handle = thread::spawn(move || {
+        let result = iterative_deepening(
+            &position,
+            &limits,
+            &PestoEvaluator,
+            SearchFeatures::default(),
+            &search_controller,
+            &mut table,
+            &mut (),
+        );
+        (table, result)
+    });
+    engine.search = Some(RunningSearch { controller, handle });
+    engine.result = None;
+    ChessBotStatus::Ok
+}
+
+/// Asks a running search to finish; its result is then available through
+/// [`chess_bot_poll`] shortly after.
+///
+/// # Safety
+///
+/// `engine` must be null or a live handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn chess_bot_stop(engine: *mut ChessBotEngine) {
+    // SAFETY: guaranteed by the caller
+    if let Some(search) = unsafe { engine.as_ref() }.and_then(|engine| engine.search.as_ref()) {
+        search.controller.stop();
+    }
+}
+
+/// Writes the result of the last search to `result` once it is done,
+/// returning [`ChessBotStatus::Pending`] until then. Never blocks.
+///
+/// # Safety
+///
+/// `engine` must be null or a live handle, and `result` null or writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn chess_bot_poll(
+    engine: *mut ChessBotEngine,
+    result: *mut ChessBotResult,
+) -> ChessBotStatus {
+    // SAFETY: guaranteed by the caller
+    let (Some(engine), Some(out)) = (unsafe { engine.as_mut() }, unsafe { result.as_mut() }) else {
+        return ChessBotStatus::InvalidArgument;
+    };
+    if !engine.collect() {
+        return ChessBotStatus::Pending;
+    }
+    match &engine.result {
+        None => ChessBotStatus::NoResult,
+        Some(Err(_)) => ChessBotStatus::SearchFailed,
+        Some(Ok(search)) => {
+            let uci = search.best_move.to_uci(CastlingMode::Standard).to_string();
+            let mut best_move = [0; 8];
+            for (slot, byte) in best_move.iter_mut().zip(uci.bytes()) {
+                *slot = byte as c_char;
+            }
+            *out = ChessBotResult {
+                best_move,
+                score: search.score,
+                mate: mate_in_moves(search.score).unwrap_or(0),
+                depth: search.depth,
+                nodes: search.nodes,
+            };
+            ChessBotStatus::Ok
+        }
+    }
+}
+
+/// Reads a C string, with null standing for the empty string; `None` if it isn't UTF-8.
+///
+/// # Safety
+///
+/// `text` must be null or NUL-terminated.
+unsafe fn read_str<'a>(text: *const c_char) -> Option<&'a str> {
+    if text.is_null() {
+        return Some("");
+    }
+    // SAFETY: guaranteed by the caller
+    unsafe { CStr::from_ptr(text) }.to_str().ok()
+}
+
+fn parse_position(fen: &str, moves: &str) -> Result<VariantPosition, Error> {
+    let mut position = match fen.trim() {
+        "" | "startpos" => VariantPosition::new(Variant::Chess),
+        fen => {
+            let setup = fen.parse::<Fen>()?.into_setup();
+            VariantPosition::from_setup(Variant::Chess, setup, CastlingMode::Standard)?
+        }
+    };
+    for uci in moves.split_whitespace() {
+        let m = uci
+            .parse::<UciMove>()
+            .ok()
+            .and_then(|uci_move| uci_move.to_move(&position).ok())
+            .ok_or_else(|| Error::IllegalMove(uci.to_owned()))?;
+        position.play_unchecked(m);
+    }
+    Ok(position)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{ffi::CString, ptr};
+
+    use super::*;
+
+    #[test]
+    fn test_search_through_c_interface() {
+        let engine = chess_bot_new();
+        let mut result = ChessBotResult {
+            best_move: [0; 8],
+            score: 0,
+            mate: 0,
+            depth: 0,
+            nodes: 0,
+        };
+        let moves = CString::new("e2e4 e7e5").unwrap();
+        let illegal = CString::new("e2e5").unwrap();
+        unsafe {
+            assert_eq!(
+                chess_bot_poll(engine, &mut result),
+                ChessBotStatus::NoResult
+            );
+            assert_eq!(
+                chess_bot_set_position(engine, ptr::null(), illegal.as_ptr()),
+                ChessBotStatus::InvalidArgument
+            );
+            assert_eq!(
+                chess_bot_set_position(engine, ptr::null(), moves.as_ptr()),
+                ChessBotStatus::Ok
+            );
+
+            // An unbounded search only ends when stopped
+            assert_eq!(chess_bot_search(engine, 0, 0), ChessBotStatus::Ok);
+            assert_eq!(chess_bot_search(engine, 3, 0), ChessBotStatus::Busy);
+            assert_eq!(chess_bot_poll(engine, &mut result), ChessBotStatus::Pending);
+            chess_bot_stop(engine);
+            while chess_bot_poll(engine, &mut result) == ChessBotStatus::Pending {
+                thread::sleep(Duration::from_millis(1));
+            }
+
+            assert_eq!(chess_bot_search(engine, 3, 0), ChessBotStatus::Ok);
+            while chess_bot_poll(engine, &mut result) == ChessBotStatus::Pending {
+                thread::sleep(Duration::from_millis(1));
+            }
+            assert_eq!(result.depth, 3);
+            assert_eq!(result.mate, 0);
+            let best_move = CStr::from_ptr(result.best_move.as_ptr()).to_str().unwrap();
+            assert!(parse_position("startpos", &format!("e2e4 e7e5 {best_move}")).is_ok());
+
+            // The queen mates on b8
+            let fen = CString::new("7k/8/6K1/8/8/8/8/1Q6 w - - 0 1").unwrap();
+            assert_eq!(
+                chess_bot_set_position(engine, fen.as_ptr(), ptr::null()),
+                ChessBotStatus::Ok
+            );
+            assert_eq!(chess_bot_search(engine, 3, 0), ChessBotStatus::Ok);
+            while chess_bot_poll(engine, &mut result) == ChessBotStatus::Pending {
+                thread::sleep(Duration::from_millis(1));
+            }
+            assert_eq!(result.mate, 1);
+
+            chess_bot_free(engine);
+            chess_bot_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_header_is_up_to_date() {
+        // After changing the interface, copy the generated header from
+        // target/*/build/chess_bot-*/out/chess_bot.h to include/
+        assert_eq!(
+            include_str!("../include/chess_bot.h"),
+            include_str!(concat!(env!("OUT_DIR"), "/chess_bot.h")),
+            "include/chess_bot.h is out of date with src/ffi.rs"
+        );
+    }
+}
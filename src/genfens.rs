@@ -0,0 +1,117 @@
+use shakmaty::{CastlingMode, Chess, Position, fen::Fen};
+
+use crate::eval::evaluate;
+use crate::see;
+
+/// Random plies played from the start position, or from a book exit.
+const RANDOM_PLIES: usize = 8;
+const RANDOM_PLIES_AFTER_BOOK: usize = 2;
+/// Largest static evaluation, in centipawns, an opening may start with.
+const MAX_IMBALANCE: i64 = 100;
+/// Tries per requested opening before giving up on finding balanced ones.
+const ATTEMPTS_PER_OPENING: usize = 1000;
+
+/// SplitMix64, so that a seed gives the same openings on every platform.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number from `0` to `n - 1`.
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+/// Reads a book with one FEN or EPD per line. Lines that aren't legal
+/// positions are skipped.
+pub fn parse_book(text: &str) -> Vec<Chess> {
+    text.lines()
+        .filter_map(|line| {
+            // EPD has operations instead of the move counters
+            let fields: Vec<&str> = line.split_whitespace().take(4).collect();
+            fields.join(" ").parse::<Fen>().ok()
+        })
+        .filter_map(|fen| fen.into_position(CastlingMode::Standard).ok())
+        .collect()
+}
+
+/// Generates `count` openings by playing random moves from the start
+/// position, or from a random position of `book` if it isn't empty. Only
+/// roughly balanced, quiet positions are kept, so fewer than `count` come
+/// back if hardly any random line stays balanced.
+pub fn generate(count: usize, seed: u64, book: &[Chess]) -> Vec<Chess> {
+    let mut rng = Rng(seed);
+    let mut openings = Vec::with_capacity(count);
+
+    for _ in 0..count * ATTEMPTS_PER_OPENING {
+        if openings.len() == count {
+            break;
+        }
+        let (mut position, plies) = if book.is_empty() {
+            (Chess::default(), RANDOM_PLIES)
+        } else {
+            (book[rng.below(book.len())].clone(), RANDOM_PLIES_AFTER_BOOK)
+        };
+        // Either side may end up to move
+        let plies = plies + rng.below(2);
+
+        for _ in 0..plies {
+            let moves = position.legal_moves();
+            if moves.is_empty() {
+                break;
+            }
+            position.play_unchecked(moves[rng.below(moves.len())]);
+        }
+        if is_balanced(&position) {
+            openings.push(position);
+        }
+    }
+
+    openings
+}
+
+/// Still going, neither side clearly better, and nothing hanging for the
+/// side to move.
+fn is_balanced(position: &Chess) -> bool {
+    !position.is_game_over()
+        && !position.is_check()
+        && evaluate(position).abs() <= MAX_IMBALANCE
+        && position
+            .capture_moves()
+            .iter()
+            .all(|m| see::static_exchange(position, m).gain <= 0)
+}
+
+#[cfg(test)]
+mod test {
+    use shakmaty::EnPassantMode;
+
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_openings() {
+        let fens = |openings: Vec<Chess>| -> Vec<String> {
+            openings
+                .iter()
+                .map(|position| Fen::from_position(position, EnPassantMode::Legal).to_string())
+                .collect()
+        };
+        let openings = fens(generate(5, 42, &[]));
+        assert_eq!(openings.len(), 5);
+        assert_eq!(openings, fens(generate(5, 42, &[])));
+        assert_ne!(openings, fens(generate(5, 43, &[])));
+
+        let book = parse_book(
+            "not a fen\nrnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - id \"e4\";\n",
+        );
+        assert_eq!(book.len(), 1);
+        assert_eq!(generate(3, 1, &book).len(), 3);
+    }
+}
@@ -0,0 +1,69 @@
+use shakmaty::{Color, Move};
+
+/// Scores saturate at this magnitude.
+const MAX_HISTORY: i32 = 16_384;
+
+/// How well quiet moves have done in the search so far, by side and by the
+/// squares they move between. Kept across iterations to help move ordering.
+#[derive(Debug, Clone)]
+pub struct QuietHistory {
+    scores: Box<[[[i32; 64]; 64]; 2]>,
+}
+
+impl QuietHistory {
+    pub fn new() -> Self {
+        Self {
+            scores: Box::new([[[0; 64]; 64]; 2]),
+        }
+    }
+
+    pub fn score(&self, color: Color, m: &Move) -> i32 {
+        let (from, to) = squares(m);
+        self.scores[color as usize][from][to]
+    }
+
+    /// Adds `bonus` (negative for a malus), shrinking it as the score nears
+    /// its limit so that recent results still count.
+    pub fn update(&mut self, color: Color, m: &Move, bonus: i32) {
+        let (from, to) = squares(m);
+        let bonus = bonus.clamp(-MAX_HISTORY, MAX_HISTORY);
+        let entry = &mut self.scores[color as usize][from][to];
+        *entry += bonus - *entry * bonus.abs() / MAX_HISTORY;
+    }
+}
+
+impl Default for QuietHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Drops have no origin square; they are filed under their target square.
+fn squares(m: &Move) -> (usize, usize) {
+    let to = m.to();
+    (m.from().unwrap_or(to) as usize, to as usize)
+}
+
+#[cfg(test)]
+mod test {
+    use shakmaty::{Chess, Position};
+
+    use super::*;
+
+    #[test]
+    fn test_scores_saturate() {
+        let position = Chess::default();
+        let m = position.legal_moves()[0];
+        let mut history = QuietHistory::new();
+
+        for _ in 0..1000 {
+            history.update(Color::White, &m, 400);
+        }
+        let score = history.score(Color::White, &m);
+        assert!(score > 0 && score <= MAX_HISTORY);
+        assert_eq!(history.score(Color::Black, &m), 0);
+
+        history.update(Color::White, &m, -400);
+        assert!(history.score(Color::White, &m) < score);
+    }
+}
@@ -0,0 +1,58 @@
+pub mod analysis;
+pub mod bench;
+pub mod controller;
+pub mod engine;
+pub mod epd;
+pub mod error;
+
+#[rustfmt::skip]
+pub mod eval;
+
+pub mod explain;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod genfens;
+pub mod history;
+
+#[cfg(feature = "uci")]
+pub mod logging;
+
+pub mod mcts;
+pub mod move_picker;
+pub mod pgn;
+
+#[cfg(feature = "play")]
+pub mod play;
+pub mod see;
+pub mod selftest;
+
+#[cfg(feature = "uci")]
+pub mod protocol;
+
+#[cfg(feature = "replay")]
+pub mod replay;
+
+#[cfg(feature = "selfplay")]
+pub mod selfplay;
+
+#[cfg(feature = "server")]
+pub mod server;
+
+pub mod strength;
+pub mod time_manager;
+pub mod transposition;
+
+#[cfg(feature = "uci")]
+pub mod uci;
+
+pub mod wdl;
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use controller::SearchController;
+pub use engine::Searcher;
+pub use error::Error;
+pub use eval::{Evaluator, evaluate};
+#[cfg(feature = "uci")]
+pub use uci::{Engine, EngineBuilder, EngineOptions};
@@ -0,0 +1,104 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use tracing_subscriber::{
+    EnvFilter, Registry, fmt::MakeWriter, layer::SubscriberExt, reload, util::SubscriberInitExt,
+};
+
+/// Environment variable holding the initial filter, e.g. `CHESS_BOT_LOG=chess_bot::engine=debug`.
+pub const LOG_ENV_VAR: &str = "CHESS_BOT_LOG";
+
+/// Runtime controls for the global subscriber installed by [`init`].
+pub struct LogHandle {
+    filter: reload::Handle<EnvFilter, Registry>,
+    file: Arc<Mutex<Option<File>>>,
+}
+
+impl LogHandle {
+    /// Replaces the active filter, accepting anything `EnvFilter` understands ("debug", "off", ...).
+    pub fn set_filter(&self, directives: &str) -> Result<(), String> {
+        let filter = EnvFilter::try_new(directives).map_err(|e| e.to_string())?;
+        self.filter.reload(filter).map_err(|e| e.to_string())
+    }
+
+    /// Appends logs to `path`, or goes back to stderr if `path` is empty.
+    pub fn set_file(&self, path: &str) -> io::Result<()> {
+        let file = if path.is_empty() {
+            None
+        } else {
+            Some(File::options().create(true).append(true).open(path)?)
+        };
+        *self.file.lock().expect("Log file lock poisoned") = file;
+        Ok(())
+    }
+}
+
+static LOG_HANDLE: OnceLock<LogHandle> = OnceLock::new();
+
+/// Installs the global tracing subscriber. Logs never go to stdout, which belongs to UCI.
+pub fn init() -> &'static LogHandle {
+    LOG_HANDLE.get_or_init(|| {
+        let initial_filter =
+            EnvFilter::try_from_env(LOG_ENV_VAR).unwrap_or_else(|_| EnvFilter::new("off"));
+        let (filter, filter_handle) = reload::Layer::new(initial_filter);
+        let file = Arc::new(Mutex::new(None));
+
+        tracing_subscriber::registry()
+            .with(filter)
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .with_ansi(false)
+                    .with_writer(LogTarget {
+                        file: Arc::clone(&file),
+                    }),
+            )
+            .init();
+
+        LogHandle {
+            filter: filter_handle,
+            file,
+        }
+    })
+}
+
+/// The subscriber installed by [`init`], if the host application called it.
+pub fn handle() -> Option<&'static LogHandle> {
+    LOG_HANDLE.get()
+}
+
+struct LogTarget {
+    file: Arc<Mutex<Option<File>>>,
+}
+
+impl<'a> MakeWriter<'a> for LogTarget {
+    type Writer = LogWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        LogWriter {
+            file: Arc::clone(&self.file),
+        }
+    }
+}
+
+struct LogWriter {
+    file: Arc<Mutex<Option<File>>>,
+}
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.file.lock().expect("Log file lock poisoned").as_mut() {
+            Some(file) => file.write(buf),
+            None => io::stderr().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.file.lock().expect("Log file lock poisoned").as_mut() {
+            Some(file) => file.flush(),
+            None => io::stderr().flush(),
+        }
+    }
+}
@@ -1,296 +1,440 @@
-use shakmaty::uci::UciMove;
-use shakmaty::zobrist::Zobrist64;
-use shakmaty::{Chess, Color, Position};
-use std::collections::HashMap;
-use std::io::{self, BufRead, Write};
-use std::sync::{
-    Arc,
-    atomic::{AtomicBool, Ordering},
-};
-use std::thread;
-use std::time::{Duration, Instant};
-
-mod engine;
-use engine::Searcher;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+#[cfg(feature = "replay")]
+use std::{fs::File, io::BufReader};
+
+use chess_bot::protocol::{self, Stdio};
+use chess_bot::{Engine, EngineBuilder, EngineOptions};
+use clap::{Parser, Subcommand};
+
+/// A UCI chess engine. Without a subcommand it speaks UCI over stdin and stdout.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
 
-use crate::engine::TranspositionInformation;
+#[derive(Subcommand)]
+enum Command {
+    /// Speak UCI over stdin and stdout
+    Uci,
+    /// Search a fixed set of positions and print nodes and nps for OpenBench
+    Bench {
+        /// Depth to search every position to
+        depth: Option<u64>,
+    },
+    /// Count the leaf nodes below a position, per root move and in total
+    Perft {
+        /// Position as FEN, or "startpos"
+        fen: String,
+        depth: u32,
+    },
+    /// Print the static evaluation of a position term by term
+    Eval {
+        /// Position as FEN, or "startpos"
+        fen: String,
+    },
+    /// Search every position of an EPD test suite and count the solved ones
+    Epd {
+        suite: PathBuf,
+        /// Time per position in milliseconds
+        #[arg(long, value_name = "MS", default_value_t = 1000)]
+        movetime: u64,
+    },
+    /// Mark the inaccuracies, mistakes and blunders of a PGN game
+    Analyze {
+        pgn: PathBuf,
+        /// Time per position in milliseconds
+        #[arg(long, value_name = "MS", default_value_t = 1000)]
+        movetime: u64,
+        /// Where to write the annotated game instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Centipawns lost for an inaccuracy
+        #[arg(long, value_name = "CP")]
+        inaccuracy: Option<i64>,
+        /// Centipawns lost for a mistake
+        #[arg(long, value_name = "CP")]
+        mistake: Option<i64>,
+        /// Centipawns lost for a blunder
+        #[arg(long, value_name = "CP")]
+        blunder: Option<i64>,
+    },
+    /// Replay a recorded GUI session
+    #[cfg(feature = "replay")]
+    Replay {
+        log: PathBuf,
+        /// Wait between commands as long as the GUI did
+        #[arg(long)]
+        realtime: bool,
+    },
+    /// Play a match between two option sets
+    #[cfg(feature = "selfplay")]
+    #[command(alias = "ab")]
+    Match(MatchArgs),
+    /// Play against the engine in the terminal
+    #[cfg(feature = "play")]
+    Play {
+        /// Play Black instead of White
+        #[arg(long)]
+        black: bool,
+        /// Engine time per move in milliseconds
+        #[arg(long, value_name = "MS")]
+        movetime: Option<u64>,
+    },
+    /// Serve UCI over TCP, or analysis over HTTP
+    Serve(ServeArgs),
+}
 
-#[rustfmt::skip]
-mod eval;
+#[derive(clap::Args)]
+#[command(group(clap::ArgGroup::new("address").required(true)))]
+struct ServeArgs {
+    /// Speak UCI with one client at a time on this address, e.g. 0.0.0.0:4000
+    #[arg(long, group = "address")]
+    tcp: Option<String>,
+    /// Serve analysis over HTTP on this address, e.g. 127.0.0.1:8080
+    #[cfg(feature = "server")]
+    #[arg(long, group = "address")]
+    http: Option<String>,
+    #[cfg(feature = "server")]
+    #[arg(long, default_value_t = 4)]
+    workers: usize,
+}
 
-/// Holds the engine's state, primarily the current board position.
-struct EngineState {
-    pos: Chess,
-    is_thinking: Arc<AtomicBool>,
-    thinking_thread: Option<thread::JoinHandle<()>>,
-    nickname: String,
-    uci_chess960: bool,
+#[cfg(feature = "selfplay")]
+#[derive(clap::Args)]
+struct MatchArgs {
+    first: PathBuf,
+    second: PathBuf,
+    #[arg(long)]
+    games: Option<u32>,
+    /// Time per move in milliseconds
+    #[arg(long, value_name = "MS", conflicts_with = "tc")]
+    movetime: Option<u64>,
+    /// Clock per side as seconds+increment, e.g. 10+0.1
+    #[arg(long, value_name = "SECONDS+INC", value_parser = parse_time_control)]
+    tc: Option<chess_bot::selfplay::TimeControl>,
+    /// FEN or EPD file with the positions to start games from
+    #[arg(long)]
+    book: Option<PathBuf>,
+    /// Stop once a sequential probability ratio test between the two Elo
+    /// differences is decided, e.g. 0,5
+    #[arg(long, value_name = "ELO0,ELO1", value_parser = parse_elo_bounds)]
+    sprt: Option<(f64, f64)>,
+    /// Chance of accepting ELO1 when ELO0 is true
+    #[arg(long, default_value_t = 0.05, requires = "sprt")]
+    alpha: f64,
+    /// Chance of accepting ELO0 when ELO1 is true
+    #[arg(long, default_value_t = 0.05, requires = "sprt")]
+    beta: f64,
 }
 
-impl EngineState {
-    fn new() -> Self {
-        Self {
-            pos: Chess::default(),
-            is_thinking: Arc::new(AtomicBool::new(false)),
-            thinking_thread: None,
-            nickname: "AllRustBot".to_owned(),
-            uci_chess960: false,
+fn main() {
+    chess_bot::logging::init();
+
+    // Testing frameworks pass commands as arguments, e.g. "genfens 10 seed 1 book none" "quit"
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg.starts_with("genfens ")) {
+        let mut engine = build_engine(Engine::builder());
+        for command in &args {
+            engine.handle_command(command);
         }
+        return;
     }
 
-    /// Parses a line from the GUI and acts on it.
-    fn handle_command(&mut self, line: &str) {
-        let tokens: Vec<&str> = line.split_whitespace().collect();
-        if let Some(&command) = tokens.first() {
-            match command {
-                "position" => self.handle_position(&tokens[1..]),
-                "go" => self.handle_go(&tokens[1..]),
-                "isready" => self.handle_isready(),
-                "uci" => self.handle_uci(),
-                "quit" => self.handle_quit(),
-                "stop" => self.handle_stop(),
-                "ucinewgame" => self.handle_ucinewgame(),
-                "setoption" => self.handle_setoption(&tokens[1..]),
-                // The spec says to ignore unknown commands.
-                _ => {}
-            }
+    match Cli::parse().command.unwrap_or(Command::Uci) {
+        Command::Uci => run_uci(),
+        Command::Bench { depth } => run_bench(depth),
+        Command::Perft { fen, depth } => on_position(&fen).perft(depth),
+        Command::Eval { fen } => on_position(&fen).eval(),
+        Command::Epd { suite, movetime } => run_epd(&suite, movetime),
+        Command::Analyze {
+            pgn,
+            movetime,
+            output,
+            inaccuracy,
+            mistake,
+            blunder,
+        } => {
+            let defaults = chess_bot::analysis::Thresholds::default();
+            let thresholds = chess_bot::analysis::Thresholds {
+                inaccuracy: inaccuracy.unwrap_or(defaults.inaccuracy),
+                mistake: mistake.unwrap_or(defaults.mistake),
+                blunder: blunder.unwrap_or(defaults.blunder),
+            };
+            run_analyze(&pgn, movetime, output.as_deref(), &thresholds);
         }
+        #[cfg(feature = "replay")]
+        Command::Replay { log, realtime } => run_replay(&log, realtime),
+        #[cfg(feature = "selfplay")]
+        Command::Match(args) => run_match(args),
+        #[cfg(feature = "play")]
+        Command::Play { black, movetime } => run_play(black, movetime),
+        Command::Serve(args) => run_serve(args),
     }
+}
 
-    /// Handles the "setoption" command to change engine parameters.
-    fn handle_setoption(&mut self, tokens: &[&str]) {
-        // tokens slice starts after "setoption", e.g., ["name", "nick", "value", "new_name"]
-        if tokens.first() != Some(&"name") {
-            return; // Invalid format
-        }
+/// Speaks UCI over stdin/stdout until the GUI sends "quit" or closes the pipe.
+fn run_uci() {
+    // Someone typing commands by hand gets a readable table instead of info lines
+    let mut engine = build_engine(Engine::builder().with_pretty_output(io::stdout().is_terminal()));
 
-        let value_pos = tokens.iter().position(|&s| s == "value");
+    // On SIGINT/SIGTERM finish the pending search so the GUI still gets its bestmove
+    let shutdown = engine.shutdown_handle();
+    ctrlc::set_handler(move || {
+        let _ = shutdown.stop_and_wait();
+        let _ = io::stdout().flush();
+        std::process::exit(0);
+    })
+    .expect("Failed to install signal handler");
 
-        if let Some(value_idx) = value_pos {
-            // Option with a value
-            if value_idx > 1 {
-                // Ensure there is a name between "name" and "value"
-                let option_name = tokens[1..value_idx].join(" ");
-                let option_value = tokens[value_idx + 1..].join(" ");
+    protocol::run(&mut engine, &mut Stdio).expect("Failed to read from stdin");
+}
 
-                if option_name.eq_ignore_ascii_case("nick") {
-                    self.nickname = option_value;
-                } else if option_name.eq_ignore_ascii_case("UCI_Chess960") {
-                    // Accept "true"/"false" (case-insensitive)
-                    self.uci_chess960 = option_value.eq_ignore_ascii_case("true");
-                }
-                // Handle other options with values here
-            }
-        }
-        // No "else" branch needed for button types yet, as we only have "nick".
-    }
+/// Creates the engine, or reports why it can't be and exits.
+fn build_engine(builder: EngineBuilder) -> Engine {
+    builder.build().unwrap_or_else(|error| {
+        eprintln!("{error}");
+        std::process::exit(2);
+    })
+}
 
-    /// Responds to the "uci" command by identifying the engine and sending supported options.
-    fn handle_uci(&self) {
-        println!("id name {}", self.nickname);
-        println!("id author All");
-        println!("option name nick type string default {}", self.nickname);
-        println!(
-            "option name UCI_Chess960 type check default {}",
-            self.uci_chess960
-        );
-        println!("uciok");
+/// An engine set up with the position given as FEN or "startpos".
+fn on_position(fen: &str) -> Engine {
+    let mut engine = build_engine(Engine::builder());
+    let fen = (fen != "startpos").then_some(fen);
+    if let Err(error) = engine.set_position(fen, &[]) {
+        eprintln!("{error}");
+        std::process::exit(2);
     }
+    engine
+}
 
-    /// Responds to "isready" to synchronize with the GUI.
-    fn handle_isready(&mut self) {
-        // If a thinking thread is finished, join it to clean up resources.
-        if let Some(handle) = self.thinking_thread.take() {
-            if handle.is_finished() {
-                handle.join().expect("Failed to join thinking thread");
-            } else {
-                // If not finished, put it back.
-                self.thinking_thread = Some(handle);
-            }
+/// Prints the bench totals the way OpenBench reads them.
+fn run_bench(depth: Option<u64>) {
+    match chess_bot::bench::run(depth.unwrap_or(chess_bot::bench::BENCH_DEPTH)) {
+        Ok(result) => println!("{} nodes {} nps", result.nodes, result.nps()),
+        Err(error) => {
+            eprintln!("{error}");
+            std::process::exit(2);
         }
-        println!("readyok");
     }
+}
 
-    /// Sets up the board based on a FEN string or startpos, and a series of moves.
-    fn handle_position(&mut self, tokens: &[&str]) {
-        let mut current_pos: Chess;
-        let moves_start_index;
-
-        if tokens.first() == Some(&"startpos") {
-            current_pos = Chess::default();
-            moves_start_index = tokens.iter().position(|&r| r == "moves");
-        } else if tokens.first() == Some(&"fen") {
-            moves_start_index = tokens.iter().position(|&r| r == "moves");
-            let fen_tokens = if let Some(msi) = moves_start_index {
-                &tokens[1..msi]
-            } else {
-                &tokens[1..]
-            };
-            let fen_str = fen_tokens.join(" ");
-            let fen: shakmaty::fen::Fen = fen_str.parse().expect("Failed to parse FEN");
-
-            let castle_type = if self.uci_chess960 {
-                shakmaty::CastlingMode::Chess960
-            } else {
-                shakmaty::CastlingMode::Standard
-            };
-
-            current_pos = fen.into_position(castle_type).expect("Invalid FEN");
+fn run_epd(suite: &Path, movetime: u64) {
+    use shakmaty::san::San;
+
+    let text = std::fs::read_to_string(suite).unwrap_or_else(|error| {
+        eprintln!("{}: {error}", suite.display());
+        std::process::exit(2);
+    });
+    let entries = chess_bot::epd::parse_suite(&text, |number, error| {
+        eprintln!("{}:{number}: {error}, skipped", suite.display());
+    });
+    let movetime = std::time::Duration::from_millis(movetime);
+    let mut number = 0;
+    let result = chess_bot::epd::run_suite(&entries, movetime, |entry, result| {
+        number += 1;
+        let name = entry.id.clone().unwrap_or_else(|| format!("#{number}"));
+        let played = San::from_move(&entry.position, result.best_move);
+        let verdict = if entry.is_solved(result.best_move) {
+            "solved"
         } else {
-            // Invalid position command
-            return;
-        }
+            "failed"
+        };
+        println!(
+            "{name}: {played} ({verdict}, {}/{} points, depth {})",
+            entry.score(result.best_move),
+            entry.max_score(),
+            result.depth
+        );
+    })
+    .unwrap_or_else(|error| {
+        eprintln!("{error}");
+        std::process::exit(2);
+    });
+
+    println!(
+        "Solved {}/{}, score {}/{}",
+        result.solved, result.positions, result.score, result.max_score
+    );
+}
+
+fn run_analyze(
+    pgn: &Path,
+    movetime: u64,
+    output: Option<&Path>,
+    thresholds: &chess_bot::analysis::Thresholds,
+) {
+    let text = std::fs::read_to_string(pgn).unwrap_or_else(|error| {
+        eprintln!("{}: {error}", pgn.display());
+        std::process::exit(2);
+    });
+    let game = chess_bot::pgn::PgnGame::parse(&text);
+    let movetime = std::time::Duration::from_millis(movetime);
+    let analysis =
+        chess_bot::analysis::analyze(&game, movetime, thresholds).unwrap_or_else(|error| {
+            eprintln!("{error}");
+            std::process::exit(2);
+        });
 
-        if let Some(msi) = moves_start_index {
-            for move_str in &tokens[msi + 1..] {
-                let uci_move: UciMove = move_str.parse().expect("Invalid UCI move");
-                if let Ok(m) = uci_move.to_move(&current_pos) {
-                    current_pos.play_unchecked(m);
-                }
+    let annotated = analysis.annotate(&game);
+    match output {
+        Some(path) => {
+            if let Err(error) = std::fs::write(path, annotated) {
+                eprintln!("{}: {error}", path.display());
+                std::process::exit(2);
             }
         }
+        None => print!("{annotated}"),
+    }
+}
 
-        self.pos = current_pos;
+#[cfg(feature = "replay")]
+fn run_replay(log: &Path, realtime: bool) {
+    let file = File::open(log).unwrap_or_else(|error| {
+        eprintln!("{}: {error}", log.display());
+        std::process::exit(2);
+    });
+    let mut engine = build_engine(Engine::builder());
+    if let Err(error) = chess_bot::replay::replay(BufReader::new(file), &mut engine, realtime) {
+        eprintln!("{error}");
+        std::process::exit(2);
     }
+}
 
-    /// Starts calculating the best move for the current position.
-    fn handle_go(&mut self, tokens: &[&str]) {
-        if self.is_thinking.load(Ordering::SeqCst) {
-            // Ignore 'go' if already thinking, as per UCI spec.
-            return;
-        }
-        self.is_thinking.store(true, Ordering::SeqCst);
+#[cfg(feature = "selfplay")]
+fn parse_time_control(text: &str) -> Result<chess_bot::selfplay::TimeControl, String> {
+    chess_bot::selfplay::TimeControl::parse(text)
+        .ok_or_else(|| format!("expected seconds+increment, got {text:?}"))
+}
 
-        let thinking_start_time = Instant::now();
+#[cfg(feature = "selfplay")]
+fn parse_elo_bounds(text: &str) -> Result<(f64, f64), String> {
+    text.split_once(',')
+        .and_then(|(elo0, elo1)| Some((elo0.trim().parse().ok()?, elo1.trim().parse().ok()?)))
+        .ok_or_else(|| format!("expected two Elo values like 0,5, got {text:?}"))
+}
 
-        let mut wtime: Option<u64> = None;
-        let mut btime: Option<u64> = None;
+#[cfg(feature = "selfplay")]
+fn run_match(args: MatchArgs) {
+    use chess_bot::selfplay::{
+        EngineConfig, MatchSettings, Sprt, SprtDecision, TimeControl, run_match,
+    };
 
-        let mut i = 0;
-        while i < tokens.len() {
-            match tokens[i] {
-                "wtime" => {
-                    if let Some(val_str) = tokens.get(i + 1) {
-                        if let Ok(time) = val_str.parse::<u64>() {
-                            wtime = Some(time);
-                        }
-                        i += 2;
-                    } else {
-                        i += 1;
-                    }
-                }
-                "btime" => {
-                    if let Some(val_str) = tokens.get(i + 1) {
-                        if let Ok(time) = val_str.parse::<u64>() {
-                            btime = Some(time);
-                        }
-                        i += 2;
-                    } else {
-                        i += 1;
-                    }
-                }
-                // TODO: Parse other parameters like "depth", "nodes", "movetime", "infinite"
-                _ => {
-                    // Ignore unknown or unhandled tokens
-                    i += 1;
-                }
+    let mut settings = MatchSettings::default();
+    if let Some(games) = args.games {
+        settings.games = games;
+    }
+    if let Some(millis) = args.movetime {
+        settings.time_control = TimeControl::MoveTime(std::time::Duration::from_millis(millis));
+    }
+    if let Some(time_control) = args.tc {
+        settings.time_control = time_control;
+    }
+    if let Some(book) = &args.book {
+        let text = std::fs::read_to_string(book).unwrap_or_else(|error| {
+            eprintln!("{}: {error}", book.display());
+            std::process::exit(2);
+        });
+        settings.openings = chess_bot::genfens::parse_book(&text);
+    }
+    settings.sprt = args.sprt.map(|(elo0, elo1)| Sprt {
+        elo0,
+        elo1,
+        alpha: args.alpha,
+        beta: args.beta,
+    });
+
+    let load = |path: &Path| {
+        EngineConfig::load(path).unwrap_or_else(|error| {
+            eprintln!("{}: {error}", path.display());
+            std::process::exit(2);
+        })
+    };
+    let first = load(&args.first);
+    let second = load(&args.second);
+    let score = run_match(&first, &second, &settings, |game, result, score| {
+        let llr = match settings.sprt {
+            Some(sprt) => {
+                let (lower, upper) = sprt.bounds();
+                format!(", LLR {:.2} ({lower:.2}, {upper:.2})", sprt.llr(score))
             }
-        }
-
-        // Clone necessary state for the thinking thread
-        let position_to_search = self.pos.clone();
-        let is_thinking_clone = Arc::clone(&self.is_thinking);
-        let is_thinking_clone_b = Arc::clone(&self.is_thinking);
-
-        let time = if position_to_search.turn() == Color::White {
-            wtime
-        } else {
-            btime
+            None => String::new(),
         };
-
-        let target_think_time = Duration::from_millis(match time {
-            Some(available_time) => available_time / 20,
-            None => 100,
-        });
-
-        let handle = thread::spawn(move || {
-            let mut transposition_table: HashMap<Zobrist64, TranspositionInformation> =
-                HashMap::new();
-            let mut searcher = Searcher::new(
-                &position_to_search,
-                1,
-                &is_thinking_clone_b,
-                None,
-                &mut transposition_table,
-            );
-            let mut best_move = searcher.next_move();
-            let mut depth: u64 = 2;
-            loop {
-                if !is_thinking_clone_b.load(Ordering::SeqCst) {
-                    break;
-                }
-                let mut searcher = Searcher::new(
-                    &position_to_search,
-                    depth,
-                    &is_thinking_clone_b,
-                    Some(&best_move),
-                    &mut transposition_table,
-                );
-                best_move = searcher.next_move();
-                depth += 1;
+        eprintln!(
+            "Game {game}/{}: {result:?}, {} vs {}: +{} ={} -{}{llr}",
+            settings.games, first.name, second.name, score.wins, score.draws, score.losses
+        );
+    })
+    .unwrap_or_else(|error| {
+        eprintln!("{error}");
+        std::process::exit(2);
+    });
+
+    println!(
+        "{} vs {}: +{} ={} -{}, score {:.1}%, Elo difference {:+.1} +/- {:.1}",
+        first.name,
+        second.name,
+        score.wins,
+        score.draws,
+        score.losses,
+        score.score() * 100.0,
+        score.elo_difference(),
+        score.elo_margin()
+    );
+    if let Some(sprt) = settings.sprt {
+        println!(
+            "SPRT [{}, {}]: {}",
+            sprt.elo0,
+            sprt.elo1,
+            match sprt.decision(&score) {
+                SprtDecision::AcceptH0 => "H0 accepted",
+                SprtDecision::AcceptH1 => "H1 accepted",
+                SprtDecision::Continue => "undecided",
             }
+        );
+    }
+}
 
-            let time_taken = thinking_start_time.elapsed();
-            println!("info time {}", time_taken.as_millis());
-
-            // A real engine might also send a ponder move.
-            let best_move_response = format!(
-                "bestmove {}",
-                best_move.to_uci(shakmaty::CastlingMode::Standard)
-            );
-            println!("{best_move_response}");
-        });
+#[cfg(feature = "play")]
+fn run_play(black: bool, movetime: Option<u64>) {
+    use chess_bot::play::{PlaySettings, run};
 
-        let _timer_handle = thread::spawn(move || {
-            thread::sleep(target_think_time);
-            is_thinking_clone.store(false, Ordering::SeqCst);
-        });
-
-        self.thinking_thread = Some(handle);
+    let mut settings = PlaySettings::default();
+    if black {
+        settings.human = shakmaty::Color::Black;
     }
-
-    /// Prepares the engine for a new game.
-    fn handle_ucinewgame(&mut self) {
-        self.pos = Chess::default();
+    if let Some(millis) = movetime {
+        settings.movetime = std::time::Duration::from_millis(millis);
     }
 
-    /// Handles the "stop" command.
-    fn handle_stop(&self) {
-        self.is_thinking.store(false, Ordering::SeqCst);
-    }
-
-    /// Handles the "quit" command.
-    fn handle_quit(&self) {
-        std::process::exit(0);
+    if let Err(error) = run(io::stdin().lock(), io::stdout(), settings) {
+        eprintln!("{error}");
+        std::process::exit(2);
     }
 }
 
-fn main() {
-    let mut engine_state = EngineState::new();
-    let stdin = io::stdin();
-
-    for line in stdin.lock().lines() {
-        let trimed_line = line
-            .expect("Failed to read line from stdin")
-            .trim()
-            .to_owned();
-        if trimed_line.is_empty() {
-            continue;
+fn run_serve(args: ServeArgs) {
+    #[cfg(feature = "server")]
+    if let Some(address) = &args.http {
+        eprintln!("Listening on http://{address}");
+        if let Err(error) = chess_bot::server::run(address, args.workers) {
+            eprintln!("{address}: {error}");
+            std::process::exit(2);
+        }
+        return;
+    }
+    if let Some(address) = &args.tcp {
+        let listener = std::net::TcpListener::bind(address).unwrap_or_else(|error| {
+            eprintln!("{address}: {error}");
+            std::process::exit(2);
+        });
+        eprintln!("Speaking UCI on {address}");
+        if let Err(error) = protocol::serve_tcp(listener, EngineOptions::default()) {
+            eprintln!("{error}");
+            std::process::exit(2);
         }
-
-        engine_state.handle_command(&trimed_line);
-
-        // Ensure every command response is sent immediately.
-        io::stdout().flush().expect("Failed to flush stdout");
     }
 }
@@ -0,0 +1,301 @@
+use std::mem;
+
+use shakmaty::{Move, Position, variant::VariantPosition};
+use web_time::Instant;
+
+use crate::controller::SearchController;
+use crate::engine::{
+    IterationInfo, ScoreBound, SearchAlgorithm, SearchFeatures, SearchLimits, SearchObserver,
+    SearchResult,
+};
+use crate::error::Error;
+use crate::eval::Evaluator;
+use crate::transposition::TranspositionTable;
+
+/// Weight of exploration against exploitation in the UCT formula.
+const EXPLORATION: f64 = 1.4;
+
+/// Scores are mapped to win probabilities on this scale, like Elo differences.
+const SCORE_SCALE: f64 = 400.0;
+
+struct Node {
+    /// Move leading here from the parent, `None` for the root.
+    mv: Option<Move>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    expanded: bool,
+    visits: u32,
+    /// Sum of the results for the side that played `mv`, each between 0 and 1.
+    value_sum: f64,
+}
+
+impl Node {
+    fn new(mv: Option<Move>, parent: Option<usize>) -> Self {
+        Self {
+            mv,
+            parent,
+            children: Vec::new(),
+            expanded: false,
+            visits: 0,
+            value_sum: 0.0,
+        }
+    }
+
+    fn mean_value(&self) -> f64 {
+        if self.visits == 0 {
+            return 0.5;
+        }
+        self.value_sum / f64::from(self.visits)
+    }
+}
+
+/// Monte-Carlo tree search without playouts: leaves are scored by `evaluator`
+/// and turned into win probabilities. Runs until `controller` stops it or the
+/// tree reaches the depth limit, then plays the most visited move. Once the
+/// tree holds `max_nodes` nodes, leaves are still visited but no longer expanded.
+pub fn search<E: Evaluator + ?Sized>(
+    position: &VariantPosition,
+    limits: &SearchLimits,
+    evaluator: &E,
+    max_nodes: usize,
+    controller: &SearchController,
+    observer: &mut impl SearchObserver,
+) -> Result<SearchResult, Error> {
+    let start_time = Instant::now();
+    let max_depth = limits.max_depth();
+
+    let mut tree = vec![Node::new(None, None)];
+    expand(&mut tree, 0, position, &limits.search_moves);
+    if tree[0].children.is_empty() {
+        return Err(Error::NoLegalMoves);
+    }
+
+    let mut reported_depth = 0;
+    let mut last_best_move = None;
+    let mut last_info = None;
+
+    loop {
+        // Selection: walk down the tree by UCT until a leaf is reached
+        let mut node = 0;
+        let mut leaf_position = position.clone();
+        let mut depth = 0;
+        while tree[node].expanded && !tree[node].children.is_empty() {
+            node = select_child(&tree, node);
+            leaf_position.play_unchecked(tree[node].mv.expect("Only the root has no move"));
+            depth += 1;
+        }
+
+        // Expansion and evaluation from the point of view of the side to move at the leaf
+        if tree.len() < max_nodes && !leaf_position.is_game_over() {
+            expand(&mut tree, node, &leaf_position, &[]);
+        }
+        let leaf_value = win_probability(evaluator.evaluate(&leaf_position));
+
+        // Backpropagation: each node is scored for the side that moved into it
+        let mut value = 1.0 - leaf_value;
+        let mut current = Some(node);
+        while let Some(index) = current {
+            tree[index].visits += 1;
+            tree[index].value_sum += value;
+            value = 1.0 - value;
+            current = tree[index].parent;
+        }
+
+        let must_stop = controller.count_node();
+        let depth_reached = max_depth.is_some_and(|max| depth >= max);
+        if depth > reported_depth || must_stop || depth_reached {
+            reported_depth = reported_depth.max(depth);
+            let info = iteration_info(&tree, reported_depth, controller.nodes());
+            observer.on_iteration(&info);
+            if last_best_move != Some(info.best_move) {
+                observer.on_new_best_move(&info);
+                last_best_move = Some(info.best_move);
+            }
+            last_info = Some(info);
+        }
+        if must_stop || depth_reached || controller.is_stopped() {
+            break;
+        }
+    }
+
+    let info = last_info.expect("Reported at least once before stopping");
+    let result = SearchResult {
+        best_move: info.best_move,
+        ponder: info.pv.get(1).copied(),
+        score: info.score,
+        bound: info.bound,
+        pv: info.pv,
+        depth: info.depth,
+        seldepth: info.seldepth,
+        nodes: info.nodes,
+        time: start_time.elapsed(),
+    };
+    observer.on_search_finished(&result);
+    Ok(result)
+}
+
+/// Monte-Carlo tree search as a [`SearchAlgorithm`]. It keeps its own tree,
+/// allowed as much memory as the transposition table, so search features and
+/// the table itself go unused.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Mcts;
+
+impl SearchAlgorithm for Mcts {
+    fn search(
+        &self,
+        position: &VariantPosition,
+        limits: &SearchLimits,
+        evaluator: &dyn Evaluator,
+        _features: SearchFeatures,
+        controller: &SearchController,
+        transposition_table: &mut TranspositionTable,
+        mut observer: &mut dyn SearchObserver,
+    ) -> Result<SearchResult, Error> {
+        // Each node is also listed among its parent's children
+        let max_nodes =
+            transposition_table.size_bytes() / (mem::size_of::<Node>() + mem::size_of::<usize>());
+        search(
+            position,
+            limits,
+            evaluator,
+            max_nodes,
+            controller,
+            &mut observer,
+        )
+    }
+}
+
+/// Adds a child for every legal move of `position`, or only `allowed` ones if given.
+fn expand(tree: &mut Vec<Node>, node: usize, position: &VariantPosition, allowed: &[Move]) {
+    for m in position.legal_moves() {
+        if allowed.is_empty() || allowed.contains(&m) {
+            let child = tree.len();
+            tree.push(Node::new(Some(m), Some(node)));
+            tree[node].children.push(child);
+        }
+    }
+    tree[node].expanded = true;
+}
+
+fn select_child(tree: &[Node], node: usize) -> usize {
+    let parent_visits = f64::from(tree[node].visits.max(1)).ln();
+    let uct = |child: &Node| {
+        if child.visits == 0 {
+            return f64::INFINITY;
+        }
+        child.mean_value() + EXPLORATION * (parent_visits / f64::from(child.visits)).sqrt()
+    };
+
+    *tree[node]
+        .children
+        .iter()
+        .max_by(|&&a, &&b| uct(&tree[a]).total_cmp(&uct(&tree[b])))
+        .expect("Only called on nodes with children")
+}
+
+/// The most visited child, which is more robust than the highest mean.
+fn most_visited_child(tree: &[Node], node: usize) -> Option<usize> {
+    tree[node]
+        .children
+        .iter()
+        .copied()
+        .filter(|&child| tree[child].visits > 0)
+        .max_by_key(|&child| tree[child].visits)
+}
+
+fn iteration_info(tree: &[Node], depth: u64, nodes: u64) -> IterationInfo {
+    let best_child = most_visited_child(tree, 0).expect("The root has been visited");
+
+    let mut pv = Vec::new();
+    let mut current = Some(best_child);
+    while let Some(node) = current {
+        pv.push(tree[node].mv.expect("Only the root has no move"));
+        current = most_visited_child(tree, node);
+    }
+
+    IterationInfo {
+        depth,
+        // The principal variation is as deep as the tree gets along the best line
+        seldepth: depth.max(pv.len() as u64),
+        score: score_from_probability(tree[best_child].mean_value()),
+        bound: ScoreBound::Exact,
+        nodes,
+        hashfull: None,
+        best_move: pv[0],
+        pv,
+    }
+}
+
+fn win_probability(score: i64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-(score as f64) / SCORE_SCALE))
+}
+
+fn score_from_probability(probability: f64) -> i64 {
+    // Certain wins and losses would be infinite
+    let probability = probability.clamp(0.0001, 0.9999);
+    (-SCORE_SCALE * (1.0 / probability - 1.0).log10()).round() as i64
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, atomic::AtomicBool};
+
+    use shakmaty::{CastlingMode, Chess, fen::Fen, uci::UciMove};
+
+    use super::*;
+    use crate::eval::PestoEvaluator;
+
+    #[test]
+    fn test_finds_mate_in_one() {
+        let position: VariantPosition = "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1"
+            .parse::<Fen>()
+            .unwrap()
+            .into_position::<Chess>(CastlingMode::Standard)
+            .unwrap()
+            .into();
+        let controller =
+            SearchController::new(Arc::new(AtomicBool::new(true))).with_node_budget(2000);
+
+        let result = search(
+            &position,
+            &SearchLimits::default(),
+            &PestoEvaluator,
+            usize::MAX,
+            &controller,
+            &mut (),
+        )
+        .unwrap();
+
+        let mate: UciMove = "a1a8".parse().unwrap();
+        assert_eq!(result.best_move, mate.to_move(&position).unwrap());
+        assert!(result.score > 0);
+    }
+
+    #[test]
+    fn test_tree_stops_growing_at_max_nodes() {
+        let controller =
+            SearchController::new(Arc::new(AtomicBool::new(true))).with_node_budget(500);
+
+        // Room for the root and its 20 moves only
+        let result = search(
+            &VariantPosition::default(),
+            &SearchLimits::default(),
+            &PestoEvaluator,
+            21,
+            &controller,
+            &mut (),
+        )
+        .unwrap();
+
+        assert_eq!(result.nodes, 500);
+        assert_eq!(result.pv.len(), 1);
+        assert_eq!(result.seldepth, 1);
+    }
+
+    #[test]
+    fn test_score_conversion_round_trips() {
+        for score in [-300, 0, 150] {
+            assert_eq!(score_from_probability(win_probability(score)), score);
+        }
+    }
+}
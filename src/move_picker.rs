@@ -0,0 +1,70 @@
+use arrayvec::ArrayVec;
+use shakmaty::{Move, MoveList};
+
+/// Hands out moves best first. Every move is scored once up front, then each
+/// call to `next` picks the best of the rest, so nodes that cut off early
+/// never pay for ordering the moves they don't search.
+pub struct MovePicker {
+    moves: MoveList,
+    scores: ArrayVec<i64, 256>,
+    /// Moves before this index have already been handed out.
+    next: usize,
+}
+
+impl MovePicker {
+    /// Scores `moves` with `score`; higher scores are picked first.
+    pub fn new(moves: MoveList, score: impl Fn(&Move) -> i64) -> Self {
+        let scores = moves.iter().map(score).collect();
+        Self {
+            moves,
+            scores,
+            next: 0,
+        }
+    }
+}
+
+impl Iterator for MovePicker {
+    type Item = Move;
+
+    fn next(&mut self) -> Option<Move> {
+        // First of the best remaining moves, so ties keep their generation order
+        let mut best = self.next;
+        for index in self.next + 1..self.moves.len() {
+            if self.scores[index] > self.scores[best] {
+                best = index;
+            }
+        }
+        let best_move = *self.moves.get(best)?;
+
+        // Shift the skipped moves up by one instead of swapping, to keep ties ordered
+        self.moves[self.next..=best].rotate_right(1);
+        self.scores[self.next..=best].rotate_right(1);
+        self.next += 1;
+        Some(best_move)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use shakmaty::{Chess, Position};
+
+    use super::*;
+
+    #[test]
+    fn test_picks_moves_best_first() {
+        let position = Chess::default();
+        let legal_moves = position.legal_moves();
+        let count = legal_moves.len();
+
+        let picked: Vec<Move> =
+            MovePicker::new(legal_moves.clone(), |m| i64::from(m.to().rank() as u8)).collect();
+
+        assert_eq!(picked.len(), count);
+        let ranks: Vec<u8> = picked.iter().map(|m| m.to().rank() as u8).collect();
+        assert!(ranks.windows(2).all(|pair| pair[0] >= pair[1]));
+
+        // Equal scores come out in the order they were generated
+        let unsorted: Vec<Move> = MovePicker::new(legal_moves.clone(), |_| 0).collect();
+        assert_eq!(unsorted, legal_moves.to_vec());
+    }
+}
@@ -0,0 +1,100 @@
+/// The parts of a PGN game needed to set up its position.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PgnGame {
+    /// Tag pairs like `Event` or `FEN`, in the order they appear.
+    pub tags: Vec<(String, String)>,
+    /// Mainline moves in SAN, without move numbers or annotations.
+    pub moves: Vec<String>,
+}
+
+impl PgnGame {
+    /// Reads the first game of `text`. Comments, variations, NAGs and the result
+    /// are skipped; whether the moves are legal is left to the caller.
+    pub fn parse(text: &str) -> Self {
+        let mut game = Self::default();
+        let mut chars = text.chars().peekable();
+        let mut token = String::new();
+        // Nesting level of "(...)" variations
+        let mut variation_depth = 0;
+
+        while let Some(c) = chars.next() {
+            match c {
+                '[' if variation_depth == 0 => {
+                    let tag: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                    if let Some((name, value)) = tag.split_once(char::is_whitespace) {
+                        let value = value.trim().trim_matches('"');
+                        game.tags.push((name.to_owned(), value.to_owned()));
+                    }
+                }
+                '{' => {
+                    chars.by_ref().find(|&c| c == '}');
+                }
+                ';' => {
+                    chars.by_ref().find(|&c| c == '\n');
+                }
+                '(' => variation_depth += 1,
+                ')' => variation_depth -= 1,
+                c if c.is_whitespace() => {}
+                _ => {
+                    token.push(c);
+                    while let Some(&next) = chars.peek() {
+                        if next.is_whitespace() || "{}();[".contains(next) {
+                            break;
+                        }
+                        token.push(next);
+                        chars.next();
+                    }
+                    if variation_depth == 0 {
+                        if is_result(&token) {
+                            break;
+                        }
+                        if let Some(san) = move_token(&token) {
+                            game.moves.push(san.to_owned());
+                        }
+                    }
+                    token.clear();
+                }
+            }
+        }
+
+        game
+    }
+
+    /// Value of the tag `name`, e.g. `FEN` for games not starting from the initial position.
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn is_result(token: &str) -> bool {
+    matches!(token, "1-0" | "0-1" | "1/2-1/2" | "*")
+}
+
+/// Strips move numbers ("12." or "12...") and annotations ("!?") from a token.
+fn move_token(token: &str) -> Option<&str> {
+    if token.starts_with('$') {
+        return None;
+    }
+    let token = token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+    let token = token.trim_end_matches(['!', '?']);
+    (!token.is_empty()).then_some(token)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_skips_annotations() {
+        let game = PgnGame::parse(
+            "[Event \"Casual\"]\n[FEN \"8/8/8/8/8/8/8/K1k5 w - - 0 1\"]\n\n\
+             1. e4 {best by test} e5 2.Nf3 (2. f4 exf4) Nc6!? $1 3... a6 ; rest\n1-0 4. d4",
+        );
+        assert_eq!(game.tag("FEN"), Some("8/8/8/8/8/8/8/K1k5 w - - 0 1"));
+        assert_eq!(game.tag("Site"), None);
+        assert_eq!(game.moves, ["e4", "e5", "Nf3", "Nc6", "a6"]);
+    }
+}
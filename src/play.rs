@@ -0,0 +1,273 @@
+use std::{
+    io::{BufRead, Write},
+    time::Duration,
+};
+
+use shakmaty::{
+    CastlingMode, Chess, Color, Move, Position, Role, Square,
+    san::{San, SanPlus},
+    uci::UciMove,
+};
+
+use crate::error::Error;
+use crate::selfplay::{EngineConfig, Player};
+
+const HELP: &str = "Enter moves as SAN (Nf3) or UCI (g1f3). \
+    Commands: undo, hint, switch, help, quit";
+
+/// How a game against the engine is set up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlaySettings {
+    /// Side the person at the terminal plays.
+    pub human: Color,
+    pub movetime: Duration,
+}
+
+impl Default for PlaySettings {
+    fn default() -> Self {
+        Self {
+            human: Color::White,
+            movetime: Duration::from_secs(1),
+        }
+    }
+}
+
+/// A game between the person at the terminal and the engine.
+pub struct Game {
+    player: Player,
+    settings: PlaySettings,
+    position: Chess,
+    moves: Vec<Move>,
+}
+
+impl Game {
+    pub fn new(settings: PlaySettings) -> Result<Self, Error> {
+        Ok(Self {
+            player: Player::new(&EngineConfig::default())?,
+            settings,
+            position: Chess::default(),
+            moves: Vec::new(),
+        })
+    }
+
+    pub fn position(&self) -> &Chess {
+        &self.position
+    }
+
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    fn is_engine_turn(&self) -> bool {
+        self.position.turn() != self.settings.human && !self.position.is_game_over()
+    }
+
+    /// Plays a move given in SAN or UCI notation.
+    pub fn play_move(&mut self, notation: &str) -> Result<(), Error> {
+        let m = notation
+            .parse::<UciMove>()
+            .ok()
+            .and_then(|uci_move| uci_move.to_move(&self.position).ok())
+            .or_else(|| {
+                notation
+                    .parse::<SanPlus>()
+                    .ok()
+                    .and_then(|san_plus| san_plus.san.to_move(&self.position).ok())
+            })
+            .ok_or_else(|| Error::IllegalMove(notation.to_owned()))?;
+        self.position.play_unchecked(m);
+        self.moves.push(m);
+        Ok(())
+    }
+
+    /// The engine's choice for the side to move.
+    pub fn search(&mut self) -> Result<Move, Error> {
+        let uci_moves: Vec<String> = self
+            .moves
+            .iter()
+            .map(|m| m.to_uci(CastlingMode::Standard).to_string())
+            .collect();
+        let best_move = self.player.best_move(&uci_moves, self.settings.movetime)?;
+        best_move
+            .parse::<UciMove>()
+            .ok()
+            .and_then(|uci_move| uci_move.to_move(&self.position).ok())
+            .ok_or(Error::IllegalMove(best_move))
+    }
+
+    /// Lets the engine move if it is its turn; returns the move played.
+    pub fn engine_reply(&mut self) -> Result<Option<Move>, Error> {
+        if !self.is_engine_turn() {
+            return Ok(None);
+        }
+        let m = self.search()?;
+        self.position.play_unchecked(m);
+        self.moves.push(m);
+        Ok(Some(m))
+    }
+
+    /// Takes back moves until it is the human's turn again, at least one.
+    pub fn undo(&mut self) {
+        if self.moves.pop().is_none() {
+            return;
+        }
+        while !self.moves.is_empty()
+            && self.moves.len() % 2 != usize::from(self.settings.human == Color::Black)
+        {
+            self.moves.pop();
+        }
+        self.position = Chess::default();
+        for &m in &self.moves {
+            self.position.play_unchecked(m);
+        }
+    }
+
+    /// Swaps sides; the engine takes over the side to move.
+    pub fn switch(&mut self) {
+        self.settings.human = !self.settings.human;
+    }
+
+    /// The move list in SAN, numbered like "1. e4 e5 2. Nf3".
+    pub fn move_list(&self) -> String {
+        let mut position = Chess::default();
+        let mut list = Vec::new();
+        for (ply, &m) in self.moves.iter().enumerate() {
+            if ply % 2 == 0 {
+                list.push(format!("{}.", ply / 2 + 1));
+            }
+            list.push(San::from_move(&position, m).to_string());
+            position.play_unchecked(m);
+        }
+        list.join(" ")
+    }
+
+    /// The board as Unicode pieces, seen from the human's side.
+    pub fn render(&self) -> String {
+        let board = self.position.board();
+        let mut ranks: Vec<usize> = (0..8).collect();
+        let mut files: Vec<usize> = (0..8).collect();
+        match self.settings.human {
+            Color::White => ranks.reverse(),
+            Color::Black => files.reverse(),
+        }
+
+        let mut lines = Vec::new();
+        for &rank in &ranks {
+            let mut line = format!("{} ", rank + 1);
+            for &file in &files {
+                let square = Square::new((rank * 8 + file) as u32);
+                line.push(' ');
+                line.push(
+                    board
+                        .piece_at(square)
+                        .map_or('·', |piece| piece_symbol(piece.color, piece.role)),
+                );
+            }
+            lines.push(line);
+        }
+        let file_names: String = files
+            .iter()
+            .map(|&file| format!(" {}", (b'a' + file as u8) as char))
+            .collect();
+        lines.push(format!("  {file_names}"));
+        lines.join("\n")
+    }
+}
+
+fn piece_symbol(color: Color, role: Role) -> char {
+    let symbols = match color {
+        Color::White => ['♙', '♘', '♗', '♖', '♕', '♔'],
+        Color::Black => ['♟', '♞', '♝', '♜', '♛', '♚'],
+    };
+    symbols[role as usize - 1]
+}
+
+/// Plays a game on the terminal, reading moves and commands from `input`
+/// until the user quits or the input ends.
+pub fn run(
+    input: impl BufRead,
+    mut output: impl Write,
+    settings: PlaySettings,
+) -> Result<(), Error> {
+    let mut game = Game::new(settings)?;
+    writeln!(output, "{HELP}")?;
+    game.engine_reply()?;
+    show(&game, &mut output)?;
+
+    for line in input.lines() {
+        let line = line?;
+        match line.trim() {
+            "" => continue,
+            "quit" => break,
+            "help" => {
+                writeln!(output, "{HELP}")?;
+                continue;
+            }
+            "hint" => {
+                if game.position().is_game_over() {
+                    writeln!(output, "The game is over")?;
+                } else {
+                    let hint = game.search()?;
+                    writeln!(output, "Hint: {}", San::from_move(game.position(), hint))?;
+                }
+                continue;
+            }
+            "undo" => game.undo(),
+            "switch" => game.switch(),
+            notation => {
+                if game.position().is_game_over() {
+                    writeln!(output, "The game is over, try undo or quit")?;
+                    continue;
+                }
+                if let Err(error) = game.play_move(notation) {
+                    writeln!(output, "{error}")?;
+                    continue;
+                }
+            }
+        }
+
+        game.engine_reply()?;
+        show(&game, &mut output)?;
+    }
+
+    Ok(())
+}
+
+fn show(game: &Game, output: &mut impl Write) -> Result<(), Error> {
+    writeln!(output, "\n{}\n", game.render())?;
+    if !game.moves().is_empty() {
+        writeln!(output, "{}", game.move_list())?;
+    }
+    if let Some(outcome) = game.position().outcome() {
+        writeln!(output, "Game over: {outcome}")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_moves_and_takeback() {
+        let mut game = Game::new(PlaySettings {
+            human: Color::White,
+            movetime: Duration::from_millis(5),
+        })
+        .unwrap();
+
+        game.play_move("e4").unwrap();
+        assert!(game.play_move("e4").is_err());
+        assert!(game.engine_reply().unwrap().is_some());
+        game.play_move("g1f3").unwrap();
+        game.engine_reply().unwrap();
+        assert!(game.move_list().starts_with("1. e4 "));
+        assert!(game.move_list().contains(" 2. Nf3 "));
+
+        game.undo();
+        assert_eq!(game.moves().len(), 2);
+        assert_eq!(game.position().turn(), Color::White);
+        assert!(game.render().starts_with("8 "));
+        assert!(game.render().ends_with("a b c d e f g h"));
+    }
+}
@@ -0,0 +1,183 @@
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex, mpsc},
+};
+
+use crate::error::Error;
+use crate::uci::{Engine, EngineOptions};
+
+/// Source of command lines sent by the GUI.
+pub trait CommandReader {
+    /// Blocks until the next line arrives; `None` once the GUI has disconnected.
+    fn read_command(&mut self) -> io::Result<Option<String>>;
+}
+
+/// Destination of the engine's responses. Shared with the search thread.
+pub trait ResponseWriter: Send + Sync {
+    /// Sends one line to the GUI and makes sure it isn't held back in a buffer.
+    fn write_response(&self, line: &str);
+}
+
+/// Feeds commands from `reader` to `engine` until the GUI quits or disconnects.
+pub fn run(engine: &mut Engine, reader: &mut impl CommandReader) -> Result<(), Error> {
+    while let Some(line) = reader.read_command()? {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        engine.handle_command(line);
+        if engine.quit_requested() {
+            break;
+        }
+    }
+
+    engine.shutdown_handle().stop_and_wait()
+}
+
+/// The process's stdin/stdout, as used by GUIs that launch the engine.
+pub struct Stdio;
+
+impl CommandReader for Stdio {
+    fn read_command(&mut self) -> io::Result<Option<String>> {
+        read_line(&mut io::stdin().lock())
+    }
+}
+
+impl ResponseWriter for Stdio {
+    fn write_response(&self, line: &str) {
+        let mut stdout = io::stdout().lock();
+        writeln!(stdout, "{line}").expect("Failed to write to stdout");
+        stdout.flush().expect("Failed to flush stdout");
+    }
+}
+
+/// Reading half of a UCI connection over TCP.
+pub struct TcpReader(BufReader<TcpStream>);
+
+/// Writing half of a UCI connection over TCP.
+pub struct TcpWriter(Mutex<TcpStream>);
+
+/// Splits a connected socket into the two protocol halves.
+pub fn tcp(stream: TcpStream) -> io::Result<(TcpReader, TcpWriter)> {
+    let writer = stream.try_clone()?;
+    Ok((
+        TcpReader(BufReader::new(stream)),
+        TcpWriter(Mutex::new(writer)),
+    ))
+}
+
+/// Speaks UCI with the clients connecting to `listener`, one at a time. Each
+/// gets a fresh engine, as if the GUI had just launched it, and "quit" only
+/// ends that client's session. Returns only if accepting a connection fails.
+pub fn serve_tcp(listener: TcpListener, options: EngineOptions) -> Result<(), Error> {
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let peer = stream.peer_addr().ok();
+        tracing::info!(?peer, "UCI client connected");
+        let (mut reader, writer) = tcp(stream)?;
+        let mut engine = Engine::with_output(options.clone(), Arc::new(writer));
+        if let Err(error) = run(&mut engine, &mut reader) {
+            tracing::warn!(%error, "UCI session ended with an error");
+        }
+        tracing::info!(?peer, "UCI client disconnected");
+    }
+    Ok(())
+}
+
+impl CommandReader for TcpReader {
+    fn read_command(&mut self) -> io::Result<Option<String>> {
+        read_line(&mut self.0)
+    }
+}
+
+impl ResponseWriter for TcpWriter {
+    fn write_response(&self, line: &str) {
+        let mut stream = self.0.lock().expect("Socket lock poisoned");
+        if let Err(error) = writeln!(stream, "{line}").and_then(|_| stream.flush()) {
+            // The reading half notices the disconnect and ends the session
+            tracing::warn!(%error, "failed to send response");
+        }
+    }
+}
+
+/// In-memory transport, mainly for driving the engine from tests or another thread.
+impl CommandReader for mpsc::Receiver<String> {
+    fn read_command(&mut self) -> io::Result<Option<String>> {
+        Ok(self.recv().ok())
+    }
+}
+
+impl ResponseWriter for mpsc::Sender<String> {
+    fn write_response(&self, line: &str) {
+        // Nobody listening anymore is not an error for the engine
+        let _ = self.send(line.to_owned());
+    }
+}
+
+fn read_line(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line))
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn test_session_over_channel() {
+        let (command_sender, mut command_receiver) = mpsc::channel();
+        let (response_sender, response_receiver) = mpsc::channel();
+        let mut engine = Engine::with_output(EngineOptions::default(), Arc::new(response_sender));
+
+        for command in [
+            "uci",
+            "isready",
+            "position startpos moves e2e4",
+            "go wtime 100 btime 100",
+            "quit",
+        ] {
+            command_sender.send(command.to_owned()).unwrap();
+        }
+        run(&mut engine, &mut command_receiver).unwrap();
+
+        let responses: Vec<String> = response_receiver.try_iter().collect();
+        assert!(responses.iter().any(|line| line == "uciok"));
+        assert!(responses.iter().any(|line| line == "readyok"));
+        assert!(responses.last().unwrap().starts_with("bestmove "));
+    }
+
+    #[test]
+    fn test_sessions_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || serve_tcp(listener, EngineOptions::default()));
+
+        // The server keeps listening after a client quits
+        for _ in 0..2 {
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream
+                .write_all(b"isready\nposition startpos\ngo depth 1\n")
+                .unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut responses = Vec::new();
+            while let Some(line) = read_line(&mut reader).unwrap() {
+                let line = line.trim().to_owned();
+                let done = line.starts_with("bestmove ");
+                responses.push(line);
+                if done {
+                    break;
+                }
+            }
+            stream.write_all(b"quit\n").unwrap();
+            assert_eq!(responses[0], "readyok");
+            assert!(responses.last().unwrap().starts_with("bestmove "));
+        }
+    }
+}
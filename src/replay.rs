@@ -0,0 +1,107 @@
+use std::{
+    io::BufRead,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::error::Error;
+use crate::uci::Engine;
+
+/// A command the GUI sent, as recovered from a session log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LogEntry<'a> {
+    /// Milliseconds since the session started, when the log records it.
+    timestamp_ms: Option<u64>,
+    command: &'a str,
+}
+
+/// Replays the GUI side of a recorded UCI session against `engine`.
+///
+/// Understands cutechess-cli debug logs (`1234 >engine(0): go ...`, engine output
+/// marked with `<` is skipped) as well as plain files with one command per line.
+/// With `realtime` the original timestamps are honored; otherwise commands are sent
+/// as fast as possible, waiting only for running searches to finish.
+pub fn replay(log: impl BufRead, engine: &mut Engine, realtime: bool) -> Result<(), Error> {
+    let start_time = Instant::now();
+
+    for line in log.lines() {
+        let line = line?;
+        let Some(entry) = parse_log_line(&line) else {
+            continue;
+        };
+
+        if realtime {
+            if let Some(timestamp_ms) = entry.timestamp_ms {
+                let due = Duration::from_millis(timestamp_ms);
+                if let Some(wait) = due.checked_sub(start_time.elapsed()) {
+                    thread::sleep(wait);
+                }
+            }
+        } else if !matches!(
+            entry.command.split_whitespace().next(),
+            Some("stop" | "ponderhit")
+        ) {
+            // GUIs wait for bestmove before moving on, only stop/ponderhit interrupt a search
+            engine.wait_for_search()?;
+        }
+
+        engine.handle_command(entry.command);
+        if engine.quit_requested() {
+            break;
+        }
+    }
+
+    engine.wait_for_search()
+}
+
+/// Extracts the GUI command from a log line, or `None` for engine output and blank lines.
+fn parse_log_line(line: &str) -> Option<LogEntry<'_>> {
+    let line = line.trim();
+    if line.is_empty() {
+        return None;
+    }
+
+    let (timestamp_ms, rest) = match line.split_once(' ') {
+        Some((first, rest)) if first.parse::<u64>().is_ok() => (first.parse().ok(), rest.trim()),
+        _ => (None, line),
+    };
+
+    if rest.starts_with('<') {
+        return None;
+    }
+    let command = match rest.strip_prefix('>') {
+        // ">engine(0): position startpos"
+        Some(sent) => sent.split_once(':')?.1.trim(),
+        None => rest,
+    };
+
+    Some(LogEntry {
+        timestamp_ms,
+        command,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_log_line() {
+        assert_eq!(
+            parse_log_line("1503 >chess_bot(0): go wtime 1000 btime 1000"),
+            Some(LogEntry {
+                timestamp_ms: Some(1503),
+                command: "go wtime 1000 btime 1000",
+            })
+        );
+        assert_eq!(parse_log_line("1510 <chess_bot(0): bestmove e2e4"), None);
+        assert_eq!(
+            parse_log_line("position startpos moves e2e4"),
+            Some(LogEntry {
+                timestamp_ms: None,
+                command: "position startpos moves e2e4",
+            })
+        );
+        assert_eq!(parse_log_line("   "), None);
+    }
+}
@@ -0,0 +1,164 @@
+use shakmaty::{Bitboard, Color, Move, Piece, Position, Role, Square};
+
+use crate::engine::piece_capture_score;
+
+/// One capture of an exchange.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExchangeStep {
+    /// Square the capturing piece came from.
+    pub from: Square,
+    pub piece: Piece,
+    /// Material won by the side that started the exchange, if it ended here.
+    pub balance: i64,
+}
+
+/// Static exchange evaluation of a move: every capture on its target square,
+/// least valuable attacker first, until one side runs out of attackers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exchange {
+    pub target: Square,
+    /// Starts with the move itself.
+    pub steps: Vec<ExchangeStep>,
+    /// Material won when either side may stop recapturing whenever that suits it.
+    pub gain: i64,
+}
+
+/// Plays out the captures on the target square of `m`. Pins and checks are
+/// ignored, so the result is an estimate, as usual for SEE.
+pub fn static_exchange(position: &impl Position, m: &Move) -> Exchange {
+    let board = position.board();
+    let target = m.to();
+    // Drops and castling never capture anything
+    let Some(from) = m.from().filter(|_| !m.is_castle()) else {
+        return Exchange {
+            target,
+            steps: Vec::new(),
+            gain: 0,
+        };
+    };
+
+    let mover = position.turn();
+    let mut occupied = board.occupied();
+    occupied.discard(from);
+    if m.is_en_passant() {
+        occupied.discard(Square::from_coords(target.file(), from.rank()));
+    }
+
+    let mut balance = m.capture().map_or(0, piece_capture_score);
+    let mut victim = m.promotion().unwrap_or(m.role());
+    let mut steps = vec![ExchangeStep {
+        from,
+        piece: Piece {
+            color: mover,
+            role: m.role(),
+        },
+        balance,
+    }];
+
+    let mut side = mover.other();
+    while let Some((square, role)) = least_valuable_attacker(position, target, side, occupied) {
+        occupied.discard(square);
+        // A king may only recapture when nothing can take it back
+        if role == Role::King && !attackers(position, target, side.other(), occupied).is_empty() {
+            break;
+        }
+
+        let captured = piece_capture_score(victim);
+        balance += if side == mover { captured } else { -captured };
+        steps.push(ExchangeStep {
+            from: square,
+            piece: Piece { color: side, role },
+            balance,
+        });
+        victim = role;
+        side = side.other();
+    }
+
+    // Going backwards, whoever made the next capture only makes it if it helps them
+    let mut gain = steps.last().expect("Holds the move itself").balance;
+    for index in (0..steps.len() - 1).rev() {
+        let stop_here = steps[index].balance;
+        let next_capturer = if (index + 1) % 2 == 0 {
+            mover
+        } else {
+            mover.other()
+        };
+        gain = if next_capturer == mover {
+            gain.max(stop_here)
+        } else {
+            gain.min(stop_here)
+        };
+    }
+
+    Exchange {
+        target,
+        steps,
+        gain,
+    }
+}
+
+fn attackers(
+    position: &impl Position,
+    target: Square,
+    side: Color,
+    occupied: Bitboard,
+) -> Bitboard {
+    // Recomputing with the captured pieces removed reveals x-ray attackers behind them
+    position.board().attacks_to(target, side, occupied) & occupied
+}
+
+fn least_valuable_attacker(
+    position: &impl Position,
+    target: Square,
+    side: Color,
+    occupied: Bitboard,
+) -> Option<(Square, Role)> {
+    let attackers = attackers(position, target, side, occupied);
+    Role::ALL.into_iter().find_map(|role| {
+        (attackers & position.board().by_role(role))
+            .first()
+            .map(|square| (square, role))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use shakmaty::{CastlingMode, Chess, fen::Fen, uci::UciMove};
+
+    use super::*;
+
+    fn exchange(fen: &str, uci: &str) -> Exchange {
+        let position: Chess = fen
+            .parse::<Fen>()
+            .unwrap()
+            .into_position(CastlingMode::Standard)
+            .unwrap();
+        let m = uci.parse::<UciMove>().unwrap().to_move(&position).unwrap();
+        static_exchange(&position, &m)
+    }
+
+    #[test]
+    fn test_defended_pawn() {
+        // Rook takes a pawn defended by a pawn: loses the exchange
+        let result = exchange("4k3/8/2p5/3p4/8/8/8/3RK3 w - - 0 1", "d1d5");
+        assert_eq!(result.steps.len(), 2);
+        assert_eq!(result.gain, 100 - 500);
+
+        // Pawn takes a knight defended by a pawn: wins a piece for a pawn
+        let result = exchange("4k3/8/2p5/3n4/4P3/8/8/4K3 w - - 0 1", "e4d5");
+        assert_eq!(result.gain, 320 - 100);
+    }
+
+    #[test]
+    fn test_xray_and_optional_recapture() {
+        // Doubled rooks win the pawn, the second rook attacks through the first
+        let result = exchange("3rk3/8/8/3p4/8/8/3R4/3RK3 w - - 0 1", "d2d5");
+        assert_eq!(result.steps.len(), 3);
+        assert_eq!(result.gain, 100);
+
+        // Recapturing with the queen would lose it to the pawn, so black doesn't
+        let result = exchange("3qk3/8/8/3p4/4P3/2N5/8/4K3 w - - 0 1", "c3d5");
+        assert_eq!(result.steps.len(), 3);
+        assert_eq!(result.gain, 100);
+    }
+}
@@ -0,0 +1,560 @@
+use std::{
+    fs,
+    path::Path,
+    sync::{Arc, mpsc},
+    time::{Duration, Instant},
+};
+
+use shakmaty::{
+    Chess, Color, EnPassantMode, Outcome, Position,
+    fen::Fen,
+    uci::UciMove,
+    zobrist::{Zobrist64, ZobristHash},
+};
+
+use crate::error::Error;
+use crate::uci::{Engine, EngineOptions};
+
+/// Short opening lines the games start from, so paired games don't all repeat each other.
+const OPENINGS: [&str; 8] = [
+    "e2e4 e7e5",
+    "e2e4 c7c5",
+    "e2e4 e7e6",
+    "e2e4 c7c6",
+    "d2d4 d7d5",
+    "d2d4 g8f6",
+    "c2c4 e7e5",
+    "g1f3 d7d5",
+];
+
+/// Options one side of a match plays with, as "setoption" name/value pairs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EngineConfig {
+    pub name: String,
+    pub options: Vec<(String, String)>,
+}
+
+impl EngineConfig {
+    /// Reads `Name = value` lines; blank lines, `#` comments and quotes around values are ignored.
+    pub fn parse(name: &str, text: &str) -> Result<Self, Error> {
+        let mut options = Vec::new();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (option, value) = line
+                .split_once('=')
+                .ok_or_else(|| Error::InvalidConfigLine(line.to_owned()))?;
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .unwrap_or(value);
+            options.push((option.trim().to_owned(), value.to_owned()));
+        }
+
+        Ok(Self {
+            name: name.to_owned(),
+            options,
+        })
+    }
+
+    /// Loads a config file, naming it after the file.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let name = path.file_stem().map_or_else(
+            || path.display().to_string(),
+            |stem| stem.to_string_lossy().into_owned(),
+        );
+        Self::parse(&name, &fs::read_to_string(path)?)
+    }
+}
+
+/// How much time the engines get in a match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeControl {
+    /// The same time for every move.
+    MoveTime(Duration),
+    /// A clock per side starting at `base` and gaining `increment` per move;
+    /// running out of time loses.
+    Clock { base: Duration, increment: Duration },
+}
+
+impl TimeControl {
+    /// Reads "seconds+increment" as used by cutechess, e.g. "10+0.1".
+    pub fn parse(text: &str) -> Option<Self> {
+        let (base, increment) = text.split_once('+').unwrap_or((text, "0"));
+        let seconds = |text: &str| {
+            text.parse::<f64>()
+                .ok()
+                .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        };
+        Some(TimeControl::Clock {
+            base: seconds(base)?,
+            increment: seconds(increment)?,
+        })
+    }
+
+    /// The "go" command for a move, given the time left for each color
+    /// (indexed by `Color`), which a fixed move time ignores.
+    fn go_command(&self, clocks: [Duration; 2]) -> String {
+        match *self {
+            TimeControl::MoveTime(movetime) => format!("go movetime {}", movetime.as_millis()),
+            TimeControl::Clock { increment, .. } => format!(
+                "go wtime {} btime {} winc {} binc {}",
+                clocks[Color::White as usize].as_millis(),
+                clocks[Color::Black as usize].as_millis(),
+                increment.as_millis(),
+                increment.as_millis()
+            ),
+        }
+    }
+}
+
+/// How the games of a match are played.
+#[derive(Debug, Clone)]
+pub struct MatchSettings {
+    pub games: u32,
+    pub time_control: TimeControl,
+    /// Games still going after this many plies are scored as draws.
+    pub max_plies: u32,
+    /// Positions the games start from, each played once with either color.
+    /// Short built-in opening lines are used when empty.
+    pub openings: Vec<Chess>,
+    /// Ends the match as soon as the test reaches a decision.
+    pub sprt: Option<Sprt>,
+}
+
+impl Default for MatchSettings {
+    fn default() -> Self {
+        Self {
+            games: 20,
+            time_control: TimeControl::MoveTime(Duration::from_millis(100)),
+            max_plies: 400,
+            openings: Vec::new(),
+            sprt: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+/// Results of a match from the first engine's point of view.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MatchScore {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl MatchScore {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Points scored per game, between 0 and 1.
+    pub fn score(&self) -> f64 {
+        if self.games() == 0 {
+            return 0.5;
+        }
+        (f64::from(self.wins) + f64::from(self.draws) / 2.0) / f64::from(self.games())
+    }
+
+    /// Elo difference implied by the score; positive if the first engine is stronger.
+    pub fn elo_difference(&self) -> f64 {
+        elo_from_score(self.score())
+    }
+
+    /// Half-width of the 95% confidence interval of [`MatchScore::elo_difference`].
+    pub fn elo_margin(&self) -> f64 {
+        let games = f64::from(self.games());
+        if games == 0.0 {
+            return f64::INFINITY;
+        }
+        let score = self.score();
+        let variance = (f64::from(self.wins) * (1.0 - score).powi(2)
+            + f64::from(self.draws) * (0.5 - score).powi(2)
+            + f64::from(self.losses) * score.powi(2))
+            / games;
+        let deviation = (variance / games).sqrt();
+        (elo_from_score(score + 1.96 * deviation) - elo_from_score(score - 1.96 * deviation)) / 2.0
+    }
+}
+
+/// Sequential probability ratio test of whether the first engine is `elo1`
+/// rather than `elo0` Elo stronger, wrongly accepting `elo1` with probability
+/// `alpha` and wrongly accepting `elo0` with probability `beta`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SprtDecision {
+    /// The first engine is no better than `elo0`.
+    AcceptH0,
+    /// The first engine is at least `elo1` better.
+    AcceptH1,
+    Continue,
+}
+
+impl Sprt {
+    /// Log-likelihood ratio of the two hypotheses after `score`, using the
+    /// normal approximation to the game results.
+    pub fn llr(&self, score: &MatchScore) -> f64 {
+        let games = f64::from(score.games());
+        let mean = score.score();
+        let variance = (f64::from(score.wins) * (1.0 - mean).powi(2)
+            + f64::from(score.draws) * (0.5 - mean).powi(2)
+            + f64::from(score.losses) * mean.powi(2))
+            / games;
+        // Nothing to go on until both results other than a draw have happened
+        if score.wins == 0 || score.losses == 0 || variance == 0.0 {
+            return 0.0;
+        }
+        let (score0, score1) = (score_from_elo(self.elo0), score_from_elo(self.elo1));
+        games * (score1 - score0) * (2.0 * mean - score0 - score1) / (2.0 * variance)
+    }
+
+    /// LLR below which H0 is accepted and above which H1 is.
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    pub fn decision(&self, score: &MatchScore) -> SprtDecision {
+        let llr = self.llr(score);
+        let (lower, upper) = self.bounds();
+        if llr <= lower {
+            SprtDecision::AcceptH0
+        } else if llr >= upper {
+            SprtDecision::AcceptH1
+        } else {
+            SprtDecision::Continue
+        }
+    }
+}
+
+fn score_from_elo(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+fn elo_from_score(score: f64) -> f64 {
+    // Keep a perfect score finite
+    let score = score.clamp(0.001, 0.999);
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+/// An in-process engine driven through its UCI interface.
+pub struct Player {
+    engine: Engine,
+    responses: mpsc::Receiver<String>,
+}
+
+impl Player {
+    pub fn new(config: &EngineConfig) -> Result<Self, Error> {
+        let (sender, responses) = mpsc::channel();
+        let mut engine = Engine::with_output(EngineOptions::default(), Arc::new(sender));
+        for (name, value) in &config.options {
+            engine.set_option(name, value)?;
+        }
+        Ok(Self { engine, responses })
+    }
+
+    pub fn new_game(&mut self) {
+        self.engine.handle_command("ucinewgame");
+    }
+
+    /// Searches the position after `moves` and returns the engine's move.
+    pub fn best_move(&mut self, moves: &[String], movetime: Duration) -> Result<String, Error> {
+        self.search(
+            &format!("position startpos moves {}", moves.join(" ")),
+            &format!("go movetime {}", movetime.as_millis()),
+        )
+    }
+
+    /// Searches the position after `moves` from `start`, with the limits of
+    /// `go` as sent over UCI (e.g. "go wtime 1000 btime 1000").
+    pub fn best_move_from(
+        &mut self,
+        start: &Fen,
+        moves: &[String],
+        go: &str,
+    ) -> Result<String, Error> {
+        self.search(
+            &format!("position fen {start} moves {}", moves.join(" ")),
+            go,
+        )
+    }
+
+    fn search(&mut self, position: &str, go: &str) -> Result<String, Error> {
+        self.engine.handle_command(position);
+        self.engine.handle_command(go);
+        self.engine.wait_for_search()?;
+
+        self.responses
+            .try_iter()
+            .filter_map(|line| {
+                line.strip_prefix("bestmove ")
+                    .and_then(|rest| rest.split_whitespace().next())
+                    .map(str::to_owned)
+            })
+            .last()
+            .ok_or(Error::NoLegalMoves)
+    }
+}
+
+/// Plays one game from `start`.
+pub fn play_game(
+    white: &mut Player,
+    black: &mut Player,
+    start: &Chess,
+    settings: &MatchSettings,
+) -> Result<GameResult, Error> {
+    white.new_game();
+    black.new_game();
+
+    let start_fen = Fen::from_position(start, EnPassantMode::Legal);
+    let mut position = start.clone();
+    let mut moves: Vec<String> = Vec::new();
+    let mut seen_positions: Vec<Zobrist64> = Vec::new();
+    // Time left for each color, indexed by `Color`, when playing on a clock
+    let mut clocks = match settings.time_control {
+        TimeControl::MoveTime(_) => [Duration::ZERO; 2],
+        TimeControl::Clock { base, .. } => [base; 2],
+    };
+
+    loop {
+        if let Some(outcome) = position.outcome() {
+            return Ok(match outcome {
+                Outcome::Decisive {
+                    winner: Color::White,
+                } => GameResult::WhiteWins,
+                Outcome::Decisive {
+                    winner: Color::Black,
+                } => GameResult::BlackWins,
+                Outcome::Draw => GameResult::Draw,
+            });
+        }
+
+        let hash = position.zobrist_hash::<Zobrist64>(EnPassantMode::Legal);
+        seen_positions.push(hash);
+        let repetitions = seen_positions.iter().filter(|&&seen| seen == hash).count();
+        if repetitions >= 3
+            || position.halfmoves() >= 100
+            || moves.len() >= settings.max_plies as usize
+        {
+            return Ok(GameResult::Draw);
+        }
+
+        let turn = position.turn();
+        let player = match turn {
+            Color::White => &mut *white,
+            Color::Black => &mut *black,
+        };
+        let start_time = Instant::now();
+        let go = settings.time_control.go_command(clocks);
+        let best_move = player.best_move_from(&start_fen, &moves, &go)?;
+        if let TimeControl::Clock { increment, .. } = settings.time_control {
+            let clock = &mut clocks[turn as usize];
+            let Some(left) = clock.checked_sub(start_time.elapsed()) else {
+                return Ok(match turn {
+                    Color::White => GameResult::BlackWins,
+                    Color::Black => GameResult::WhiteWins,
+                });
+            };
+            *clock = left + increment;
+        }
+        play_uci_move(&mut position, &best_move)?;
+        moves.push(best_move);
+    }
+}
+
+/// Plays `settings.games` games between the two configs, alternating colors, and
+/// calls `on_game` with the running score after each one. With an SPRT the
+/// match ends early once it is decided.
+pub fn run_match(
+    first: &EngineConfig,
+    second: &EngineConfig,
+    settings: &MatchSettings,
+    mut on_game: impl FnMut(u32, GameResult, &MatchScore),
+) -> Result<MatchScore, Error> {
+    let mut first_player = Player::new(first)?;
+    let mut second_player = Player::new(second)?;
+    let mut score = MatchScore::default();
+    let openings = match settings.openings.is_empty() {
+        true => built_in_openings(),
+        false => settings.openings.clone(),
+    };
+
+    for game in 0..settings.games {
+        // Both colors play every opening once
+        let opening = &openings[(game as usize / 2) % openings.len()];
+        let first_is_white = game.is_multiple_of(2);
+        let result = if first_is_white {
+            play_game(&mut first_player, &mut second_player, opening, settings)?
+        } else {
+            play_game(&mut second_player, &mut first_player, opening, settings)?
+        };
+
+        match (result, first_is_white) {
+            (GameResult::Draw, _) => score.draws += 1,
+            (GameResult::WhiteWins, true) | (GameResult::BlackWins, false) => score.wins += 1,
+            _ => score.losses += 1,
+        }
+        on_game(game + 1, result, &score);
+        if settings
+            .sprt
+            .is_some_and(|sprt| sprt.decision(&score) != SprtDecision::Continue)
+        {
+            break;
+        }
+    }
+
+    Ok(score)
+}
+
+/// The positions after the [`OPENINGS`] lines.
+fn built_in_openings() -> Vec<Chess> {
+    OPENINGS
+        .iter()
+        .map(|line| {
+            let mut position = Chess::default();
+            for m in line.split_whitespace() {
+                play_uci_move(&mut position, m).expect("Opening lines are legal");
+            }
+            position
+        })
+        .collect()
+}
+
+fn play_uci_move(position: &mut Chess, move_str: &str) -> Result<(), Error> {
+    let m = move_str
+        .parse::<UciMove>()
+        .ok()
+        .and_then(|uci_move| uci_move.to_move(position).ok())
+        .ok_or_else(|| Error::IllegalMove(move_str.to_owned()))?;
+    position.play_unchecked(m);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config = EngineConfig::parse(
+            "a",
+            "# comment\n\nNullMove = false\nEvaluator = \"Material\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.options,
+            vec![
+                ("NullMove".to_owned(), "false".to_owned()),
+                ("Evaluator".to_owned(), "Material".to_owned())
+            ]
+        );
+        assert!(EngineConfig::parse("b", "no equals sign").is_err());
+    }
+
+    #[test]
+    fn test_parse_time_control() {
+        assert_eq!(
+            TimeControl::parse("10+0.1"),
+            Some(TimeControl::Clock {
+                base: Duration::from_secs(10),
+                increment: Duration::from_millis(100),
+            })
+        );
+        assert_eq!(
+            TimeControl::parse("60"),
+            Some(TimeControl::Clock {
+                base: Duration::from_secs(60),
+                increment: Duration::ZERO,
+            })
+        );
+        assert_eq!(TimeControl::parse("fast"), None);
+    }
+
+    #[test]
+    fn test_elo_difference() {
+        let even = MatchScore {
+            wins: 3,
+            draws: 4,
+            losses: 3,
+        };
+        assert_eq!(even.elo_difference(), 0.0);
+
+        let ahead = MatchScore {
+            wins: 3,
+            draws: 0,
+            losses: 1,
+        };
+        // 75% corresponds to about +191 Elo
+        assert!((ahead.elo_difference() - 190.8).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_sprt() {
+        let sprt = Sprt {
+            elo0: 0.0,
+            elo1: 10.0,
+            alpha: 0.05,
+            beta: 0.05,
+        };
+        let (lower, upper) = sprt.bounds();
+        assert!((upper - 2.944).abs() < 0.001);
+        assert_eq!(lower, -upper);
+
+        let score = |wins, draws, losses| MatchScore {
+            wins,
+            draws,
+            losses,
+        };
+        assert_eq!(sprt.llr(&score(0, 10, 0)), 0.0);
+        assert!(sprt.llr(&score(60, 100, 40)) > 0.0);
+        assert!(sprt.llr(&score(40, 100, 60)) < 0.0);
+        assert_eq!(sprt.decision(&score(10, 10, 9)), SprtDecision::Continue);
+        assert_eq!(
+            sprt.decision(&score(600, 1000, 400)),
+            SprtDecision::AcceptH1
+        );
+        assert_eq!(
+            sprt.decision(&score(400, 1000, 600)),
+            SprtDecision::AcceptH0
+        );
+    }
+
+    #[test]
+    fn test_short_game_is_adjudicated() {
+        let config = EngineConfig::default();
+        let mut settings = MatchSettings {
+            games: 1,
+            time_control: TimeControl::MoveTime(Duration::from_millis(5)),
+            max_plies: 4,
+            openings: Vec::new(),
+            sprt: None,
+        };
+        let mut white = Player::new(&config).unwrap();
+        let mut black = Player::new(&config).unwrap();
+        let start = &built_in_openings()[0];
+        let result = play_game(&mut white, &mut black, start, &settings).unwrap();
+        assert_eq!(result, GameResult::Draw);
+
+        settings.time_control = TimeControl::parse("0.2+0.01").unwrap();
+        let result = play_game(&mut white, &mut black, start, &settings).unwrap();
+        assert_eq!(result, GameResult::Draw);
+    }
+}
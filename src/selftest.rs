@@ -0,0 +1,207 @@
+use std::sync::{Arc, atomic::AtomicBool};
+
+use shakmaty::{
+    CastlingMode, Chess, EnPassantMode, FromSetup, Position,
+    fen::Fen,
+    perft,
+    uci::UciMove,
+    variant::VariantPosition,
+    zobrist::{Zobrist64, ZobristHash},
+};
+
+use crate::controller::SearchController;
+use crate::engine::{
+    HashProbeOption, SearchFeatures, SearchLimits, TranspositionHashType, iterative_deepening,
+    mate_in_moves, probe_hash, record_hash,
+};
+use crate::eval::{PestoEvaluator, evaluate};
+use crate::transposition::TranspositionTable;
+
+/// Positions with well-known move counts, as (FEN, depth, nodes).
+const PERFT_CASES: [(&str, u32, u64); 4] = [
+    (
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        3,
+        8_902,
+    ),
+    (
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        2,
+        2_039,
+    ),
+    ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 3, 2_812),
+    (
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        2,
+        264,
+    ),
+];
+
+/// Unbalanced middlegame and endgame positions for the symmetry checks. None
+/// has a pawn race, which is scored differently depending on who moves first.
+const EVAL_POSITIONS: [&str; 4] = [
+    "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1",
+    "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP3PPP/R2QKB1R w KQ - 0 8",
+    "8/5pk1/6p1/8/3R4/6P1/5PKP/r7 w - - 0 40",
+    "4k3/8/8/3N4/8/8/2Q5/4K3 w - - 0 1",
+];
+
+/// Mate-in-two puzzles, as (FEN, first move of the solution).
+const MATE_IN_TWO: [(&str, &str); 3] = [
+    ("kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1", "a1a6"),
+    (
+        "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 10",
+        "d5f6",
+    ),
+    ("6k1/pp4p1/2p5/2bp4/8/P5Pb/1P3rrP/2BRRN1K b - - 0 1", "g2g1"),
+];
+
+/// Outcome of one self-test check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckResult {
+    pub name: String,
+    /// Why the check failed, `None` if it passed.
+    pub failure: Option<String>,
+}
+
+impl CheckResult {
+    pub fn passed(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+/// Runs every consistency check, for validating a build on a new platform.
+pub fn run() -> Vec<CheckResult> {
+    let mut results = Vec::new();
+
+    for (fen, depth, expected) in PERFT_CASES {
+        let position = parse(fen);
+        let nodes = perft(&position, depth);
+        results.push(CheckResult {
+            name: format!("perft {depth} {fen}"),
+            failure: (nodes != expected).then(|| format!("expected {expected} nodes, got {nodes}")),
+        });
+    }
+
+    for fen in EVAL_POSITIONS {
+        results.push(CheckResult {
+            name: format!("eval symmetry {fen}"),
+            failure: check_eval_symmetry(&parse(fen)).err(),
+        });
+    }
+
+    results.push(CheckResult {
+        name: "transposition table round trip".to_owned(),
+        failure: check_transposition_table().err(),
+    });
+
+    for (fen, solution) in MATE_IN_TWO {
+        results.push(CheckResult {
+            name: format!("mate in 2 {fen}"),
+            failure: check_mate_in_two(parse(fen), solution).err(),
+        });
+    }
+
+    results
+}
+
+fn parse(fen: &str) -> Chess {
+    fen.parse::<Fen>()
+        .expect("Self-test FENs are valid")
+        .into_position(CastlingMode::Standard)
+        .expect("Self-test positions are legal")
+}
+
+/// Scores are for the side to move, so swapping colors must not change the
+/// score and passing the move to the other side must negate it.
+fn check_eval_symmetry(position: &Chess) -> Result<(), String> {
+    let score = evaluate(position);
+
+    let mut setup = position.to_setup(EnPassantMode::Legal);
+    setup.mirror();
+    let mirrored = Chess::from_setup(setup, CastlingMode::Standard)
+        .map_err(|error| format!("mirrored position is invalid: {error}"))?;
+    let mirrored_score = evaluate(&mirrored);
+    if mirrored_score != score {
+        return Err(format!("scores {score}, mirrored {mirrored_score}"));
+    }
+
+    if let Ok(flipped) = position.clone().swap_turn() {
+        let flipped_score = evaluate(&flipped);
+        if flipped_score != -score {
+            return Err(format!(
+                "scores {score}, other side to move {flipped_score}"
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn check_transposition_table() -> Result<(), String> {
+    let position = Chess::default();
+    let hash = position.zobrist_hash::<Zobrist64>(EnPassantMode::Legal);
+    let best_move = "e2e4"
+        .parse::<UciMove>()
+        .ok()
+        .and_then(|uci_move| uci_move.to_move(&position).ok())
+        .expect("e2e4 is legal in the start position");
+
+    let mut table = TranspositionTable::with_entries(1024);
+    record_hash(
+        &mut table,
+        hash,
+        4,
+        37,
+        TranspositionHashType::Exact,
+        Some(best_move),
+    );
+
+    match probe_hash(&table, hash, 4, -100, 100) {
+        HashProbeOption::Some(37) => {}
+        other => return Err(format!("probe at the stored depth returned {other:?}")),
+    }
+    // Too shallow to trust the score, but the move still helps ordering
+    match probe_hash(&table, hash, 5, -100, 100) {
+        HashProbeOption::Move(m) if m == best_move => {}
+        other => return Err(format!("deeper probe returned {other:?}")),
+    }
+    Ok(())
+}
+
+fn check_mate_in_two(position: Chess, solution: &str) -> Result<(), String> {
+    let limits = SearchLimits {
+        mate: Some(2),
+        ..SearchLimits::default()
+    };
+    let controller = SearchController::new(Arc::new(AtomicBool::new(true)));
+    let result = iterative_deepening(
+        &VariantPosition::Chess(position),
+        &limits,
+        &PestoEvaluator,
+        SearchFeatures::default(),
+        &controller,
+        &mut TranspositionTable::default(),
+        &mut (),
+    )
+    .map_err(|error| error.to_string())?;
+
+    let found = result.best_move.to_uci(CastlingMode::Standard).to_string();
+    match mate_in_moves(result.score) {
+        Some(2) if found == solution => Ok(()),
+        mate => Err(format!(
+            "expected {solution} mating in 2, got {found} with mate {mate:?}"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_all_checks_pass() {
+        for result in run() {
+            assert!(result.passed(), "{}: {:?}", result.name, result.failure);
+        }
+    }
+}
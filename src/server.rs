@@ -0,0 +1,518 @@
+use std::{
+    io,
+    sync::{Arc, atomic::AtomicBool},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use shakmaty::{
+    CastlingMode, Chess, EnPassantMode, Position, fen::Fen, uci::UciMove, variant::VariantPosition,
+};
+use tiny_http::{Header, Method, ReadWrite, Request, Response, Server};
+use tungstenite::{Message, WebSocket, handshake::derive_accept_key, protocol::Role};
+
+use crate::controller::SearchController;
+use crate::engine::{
+    IterationInfo, SearchFeatures, SearchLimits, SearchObserver, SearchResult, iterative_deepening,
+};
+use crate::error::Error;
+use crate::eval::PestoEvaluator;
+use crate::transposition::TranspositionTable;
+use metrics::{Metrics, SearchRecord};
+use sessions::{Opened, Rejected, Scheduler, Session, SessionId};
+
+mod metrics;
+mod sessions;
+
+const DEFAULT_MOVETIME_MS: u64 = 1000;
+/// Served at `/`; talks to the API like any other client.
+const PLAY_PAGE: &str = include_str!("server/play.html");
+// Keeps a single request from occupying a worker indefinitely
+const MAX_MOVETIME_MS: u64 = 60_000;
+
+#[derive(Debug, Default, Deserialize)]
+struct AnalyzeRequest {
+    /// May be left out within a session to analyze its last position again.
+    fen: Option<String>,
+    movetime: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct AnalyzeResponse {
+    bestmove: String,
+    score: i64,
+    depth: u64,
+    pv: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct SessionRequest {
+    /// Default think time for the session's searches.
+    movetime: Option<u64>,
+    /// Position to start from, the standard start position if left out.
+    fen: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct MoveRequest {
+    /// The move in UCI notation, e.g. "e2e4".
+    #[serde(rename = "move")]
+    uci: String,
+}
+
+#[derive(Debug, Serialize)]
+struct PositionResponse {
+    fen: String,
+    /// "1-0", "0-1" or "1/2-1/2" once the game is over.
+    result: Option<String>,
+}
+
+/// One message of a streamed analysis.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum StreamMessage {
+    /// Sent after every iteration.
+    Info {
+        depth: u64,
+        score: i64,
+        nodes: u64,
+        pv: Vec<String>,
+    },
+    /// Sent once the search is over, before the socket closes.
+    Bestmove(AnalyzeResponse),
+    Error(ErrorResponse),
+}
+
+#[derive(Debug, Serialize)]
+struct SessionResponse {
+    id: SessionId,
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+/// Work waiting for a session's turn, with the request to answer once it's done.
+struct Job {
+    request: Request,
+    action: Action,
+    /// `None` for a request outside of a session.
+    session: Option<SessionId>,
+    received: Instant,
+}
+
+/// Moves go through the queue like searches, so they apply in the order
+/// they were sent and never while the session's position is being searched.
+enum Action {
+    Analyze(AnalyzeRequest),
+    Move(MoveRequest),
+    /// Search the session's position, reporting every iteration over a WebSocket.
+    Stream,
+}
+
+/// Serves the analysis API on `address` with `workers` searches running at most
+/// at once. Further searches wait their turn, taken session by session.
+///
+/// - `GET /` is a page for playing against the engine in the browser.
+/// - `POST /analyze` searches a single position.
+/// - `POST /sessions` opens a session, which keeps its position and
+///   transposition table between searches, and returns its id. A session is
+///   a game that moves can be played in, from the start position unless it
+///   was opened with a FEN.
+/// - `POST /sessions/<id>/moves` plays a move in the session's position.
+/// - `POST /sessions/<id>/analyze` searches within a session.
+/// - `GET /sessions/<id>/stream` upgrades to a WebSocket that reports every
+///   iteration of a search of the session's position, then its best move.
+/// - `DELETE /sessions/<id>` closes a session. Sessions left unused for half
+///   an hour are closed once another client needs room.
+/// - `GET /metrics` reports on the searches so far, for Prometheus.
+pub fn run(address: &str, workers: usize) -> io::Result<()> {
+    let server = Server::http(address).map_err(io::Error::other)?;
+    let scheduler = Arc::new(Scheduler::<Job>::new());
+    let metrics = Arc::new(Metrics::default());
+
+    for _ in 0..workers.max(1) {
+        let scheduler = Arc::clone(&scheduler);
+        let metrics = Arc::clone(&metrics);
+        thread::spawn(move || {
+            loop {
+                let (id, mut session, job) = scheduler.next();
+                let result = match job.action {
+                    Action::Analyze(analysis) => {
+                        let result = analyze(&analysis, &mut session);
+                        let response = result.as_ref().map(AnalyzeResponse::from);
+                        respond(job.request, response.map_err(Clone::clone));
+                        result.ok()
+                    }
+                    Action::Move(request) => {
+                        respond(job.request, play_move(&request, &mut session));
+                        None
+                    }
+                    Action::Stream => stream_analysis(job.request, &mut session),
+                };
+                let table_entries = session.table_entries();
+                // A session deleted during its search has had its metrics dropped
+                let open = scheduler.finish(id, session);
+                if let (true, Some(result)) = (open, &result) {
+                    metrics.record(SearchRecord {
+                        session: job.session,
+                        nodes: result.nodes,
+                        depth: result.depth,
+                        search_time: result.time,
+                        latency: job.received.elapsed(),
+                        table_entries,
+                    });
+                }
+            }
+        });
+    }
+
+    // Requests are only read and queued here, the workers do the searching
+    for request in server.incoming_requests() {
+        handle_request(request, &scheduler, &metrics);
+    }
+    Ok(())
+}
+
+fn handle_request(mut request: Request, scheduler: &Scheduler<Job>, metrics: &Metrics) {
+    let received = Instant::now();
+    let url = request.url().to_owned();
+    let segments: Vec<&str> = url.trim_matches('/').split('/').collect();
+    let method = request.method().clone();
+
+    let response = match (&method, segments.as_slice()) {
+        (Method::Get, [""]) => Response::from_string(PLAY_PAGE).with_header(
+            "Content-Type: text/html; charset=utf-8"
+                .parse::<Header>()
+                .expect("Static header is valid"),
+        ),
+        (Method::Post, ["analyze"]) => match read_json::<AnalyzeRequest>(&mut request) {
+            Ok(analysis) if analysis.fen.is_none() => error_response(400, "Missing FEN"),
+            Ok(analysis) => {
+                let session = Session::new(Duration::from_millis(DEFAULT_MOVETIME_MS));
+                let job = Job {
+                    request,
+                    action: Action::Analyze(analysis),
+                    session: None,
+                    received,
+                };
+                match scheduler.submit_once(session, job) {
+                    Ok(()) => return,
+                    Err(job) => {
+                        let _ = job
+                            .request
+                            .respond(error_response(503, "Too many queued requests"));
+                        return;
+                    }
+                }
+            }
+            Err(error) => error_response(400, &error),
+        },
+        (Method::Post, ["sessions"]) => match read_json::<SessionRequest>(&mut request) {
+            Ok(settings) => {
+                let movetime = settings.movetime.unwrap_or(DEFAULT_MOVETIME_MS);
+                let mut session = Session::new(Duration::from_millis(movetime));
+                match settings.fen.as_deref().map(parse_position).transpose() {
+                    Ok(position) => {
+                        if let Some(position) = position {
+                            session.position = position;
+                        }
+                        match scheduler.open(session) {
+                            Some(Opened { id, expired }) => {
+                                for id in expired {
+                                    metrics.forget_session(id);
+                                }
+                                json_response(201, &SessionResponse { id })
+                            }
+                            None => error_response(503, "Too many sessions"),
+                        }
+                    }
+                    Err(error) => error_response(400, &error),
+                }
+            }
+            Err(error) => error_response(400, &error),
+        },
+        (Method::Post, ["sessions", id, "analyze"]) => {
+            match read_json::<AnalyzeRequest>(&mut request) {
+                Ok(analysis) => {
+                    return submit(scheduler, id, request, Action::Analyze(analysis), received);
+                }
+                Err(error) => error_response(400, &error),
+            }
+        }
+        (Method::Post, ["sessions", id, "moves"]) => match read_json::<MoveRequest>(&mut request) {
+            Ok(m) => return submit(scheduler, id, request, Action::Move(m), received),
+            Err(error) => error_response(400, &error),
+        },
+        (Method::Get, ["sessions", id, "stream"]) => {
+            return submit(scheduler, id, request, Action::Stream, received);
+        }
+        (Method::Delete, ["sessions", id]) => {
+            if let Some(id) = id.parse().ok().filter(|&id| scheduler.close(id)) {
+                metrics.forget_session(id);
+                Response::from_string("").with_status_code(204)
+            } else {
+                error_response(404, "No such session")
+            }
+        }
+        (Method::Get, ["metrics"]) => Response::from_string(metrics.render()).with_header(
+            "Content-Type: text/plain; version=0.0.4"
+                .parse::<Header>()
+                .expect("Static header is valid"),
+        ),
+        (_, ["" | "analyze" | "metrics"] | ["sessions", ..]) => {
+            error_response(405, "Method not allowed")
+        }
+        _ => error_response(404, "Not found"),
+    };
+
+    // The client may already have hung up, nothing left to do then
+    let _ = request.respond(response);
+}
+
+/// Parses the request body; an empty body counts as `{}`.
+fn read_json<T: Default + for<'de> Deserialize<'de>>(request: &mut Request) -> Result<T, String> {
+    let mut body = String::new();
+    request
+        .as_reader()
+        .read_to_string(&mut body)
+        .map_err(|e| e.to_string())?;
+    if body.trim().is_empty() {
+        return Ok(T::default());
+    }
+    serde_json::from_str(&body).map_err(|e| e.to_string())
+}
+
+/// Queues `action` for session `id`, answering right away if there is no
+/// such session or it has too many requests queued.
+fn submit(
+    scheduler: &Scheduler<Job>,
+    id: &str,
+    request: Request,
+    action: Action,
+    received: Instant,
+) {
+    let Ok(id) = id.parse() else {
+        let _ = request.respond(error_response(404, "No such session"));
+        return;
+    };
+    let job = Job {
+        request,
+        action,
+        session: Some(id),
+        received,
+    };
+    let (job, response) = match scheduler.submit(id, job) {
+        Ok(()) => return,
+        Err(Rejected::NoSuchSession(job)) => (job, error_response(404, "No such session")),
+        Err(Rejected::QueueFull(job)) => (job, error_response(503, "Too many queued requests")),
+    };
+    let _ = job.request.respond(response);
+}
+
+fn respond(request: Request, result: Result<impl Serialize, (u16, String)>) {
+    let response = match result {
+        Ok(body) => json_response(200, &body),
+        Err((status, error)) => error_response(status, &error),
+    };
+    let _ = request.respond(response);
+}
+
+/// Searches the requested position, or the session's last one, with the
+/// session's table.
+fn analyze(request: &AnalyzeRequest, session: &mut Session) -> Result<SearchResult, (u16, String)> {
+    if let Some(fen) = &request.fen {
+        session.position = parse_position(fen).map_err(|error| (400, error))?;
+    }
+    let position = session.position.clone();
+
+    let movetime = request
+        .movetime
+        .map_or(session.movetime, Duration::from_millis);
+    search(&position, movetime, session.table(), &mut ()).map_err(|e| (422, e.to_string()))
+}
+
+/// Searches for at most `movetime`, capped at [`MAX_MOVETIME_MS`].
+fn search(
+    position: &VariantPosition,
+    movetime: Duration,
+    table: &mut TranspositionTable,
+    observer: &mut impl SearchObserver,
+) -> Result<SearchResult, Error> {
+    let limits = SearchLimits {
+        movetime: Some(movetime.min(Duration::from_millis(MAX_MOVETIME_MS))),
+        ..SearchLimits::default()
+    };
+    let controller = SearchController::new(Arc::new(AtomicBool::new(true))).with_limits(&limits);
+    iterative_deepening(
+        position,
+        &limits,
+        &PestoEvaluator,
+        SearchFeatures::default(),
+        &controller,
+        table,
+        observer,
+    )
+}
+
+/// Plays a move in UCI notation in the session's position.
+fn play_move(
+    request: &MoveRequest,
+    session: &mut Session,
+) -> Result<PositionResponse, (u16, String)> {
+    let position = &mut session.position;
+    let m = request
+        .uci
+        .parse::<UciMove>()
+        .ok()
+        .and_then(|uci_move| uci_move.to_move(position).ok())
+        .ok_or_else(|| (422, format!("Illegal move {:?}", request.uci)))?;
+    position.play_unchecked(m);
+    Ok(PositionResponse {
+        fen: Fen::from_position(position, EnPassantMode::Legal).to_string(),
+        result: position.outcome().map(|outcome| outcome.to_string()),
+    })
+}
+
+/// Completes the WebSocket handshake of `request` and streams a search of
+/// the session's position over it, closing the socket once the best move is sent.
+fn stream_analysis(request: Request, session: &mut Session) -> Option<SearchResult> {
+    let key = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Sec-WebSocket-Key"))
+        .map(|header| derive_accept_key(header.value.as_bytes()));
+    let Some(accept) = key else {
+        let _ = request.respond(error_response(400, "Expected a WebSocket handshake"));
+        return None;
+    };
+    let position = session.position.clone();
+
+    let response = Response::empty(101).with_header(
+        format!("Sec-WebSocket-Accept: {accept}")
+            .parse::<Header>()
+            .expect("Accept keys are valid header values"),
+    );
+    let socket = request.upgrade("websocket", response);
+    let mut streamer = Streamer {
+        socket: WebSocket::from_raw_socket(socket, Role::Server, None),
+        failed: false,
+    };
+    let result = search(&position, session.movetime, session.table(), &mut streamer);
+    let last = match &result {
+        Ok(result) => StreamMessage::Bestmove(AnalyzeResponse::from(result)),
+        Err(error) => StreamMessage::Error(ErrorResponse {
+            error: error.to_string(),
+        }),
+    };
+    streamer.send(&last);
+    // The client may be gone already, the socket is dropped either way
+    let _ = streamer.socket.close(None);
+    let _ = streamer.socket.flush();
+    result.ok()
+}
+
+/// Sends the iterations of a search to a WebSocket client.
+struct Streamer {
+    socket: WebSocket<Box<dyn ReadWrite + Send>>,
+    /// Set once a send failed; the search still finishes, as the session's
+    /// table gains from it either way.
+    failed: bool,
+}
+
+impl Streamer {
+    fn send(&mut self, message: &StreamMessage) {
+        if self.failed {
+            return;
+        }
+        let json = serde_json::to_string(message).expect("Stream messages always serialize");
+        self.failed = self.socket.send(Message::Text(json)).is_err();
+    }
+}
+
+impl SearchObserver for Streamer {
+    fn on_iteration(&mut self, info: &IterationInfo) {
+        self.send(&StreamMessage::Info {
+            depth: info.depth,
+            score: info.score,
+            nodes: info.nodes,
+            pv: uci_moves(&info.pv),
+        });
+    }
+}
+
+impl From<&SearchResult> for AnalyzeResponse {
+    fn from(result: &SearchResult) -> Self {
+        Self {
+            bestmove: result.best_move.to_uci(CastlingMode::Standard).to_string(),
+            score: result.score,
+            depth: result.depth,
+            pv: uci_moves(&result.pv),
+        }
+    }
+}
+
+fn uci_moves(moves: &[shakmaty::Move]) -> Vec<String> {
+    moves
+        .iter()
+        .map(|m| m.to_uci(CastlingMode::Standard).to_string())
+        .collect()
+}
+
+fn parse_position(fen: &str) -> Result<VariantPosition, String> {
+    let fen: Fen = fen.parse().map_err(|e| format!("Invalid FEN: {e}"))?;
+    Ok(fen
+        .into_position::<Chess>(CastlingMode::Standard)
+        .map_err(|e| format!("Invalid position: {e}"))?
+        .into())
+}
+
+fn error_response(status: u16, error: &str) -> Response<io::Cursor<Vec<u8>>> {
+    json_response(
+        status,
+        &ErrorResponse {
+            error: error.to_owned(),
+        },
+    )
+}
+
+fn json_response(status: u16, body: &impl Serialize) -> Response<io::Cursor<Vec<u8>>> {
+    let json = serde_json::to_string(body).expect("Response types always serialize");
+    Response::from_string(json)
+        .with_status_code(status)
+        .with_header(
+            "Content-Type: application/json"
+                .parse::<Header>()
+                .expect("Static header is valid"),
+        )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_play_move() {
+        let mut session = Session::new(Duration::from_millis(10));
+        let request = |uci: &str| MoveRequest {
+            uci: uci.to_owned(),
+        };
+        // A new session starts from the start position
+        let position = play_move(&request("e2e4"), &mut session).unwrap();
+        assert_eq!(
+            position.fen,
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"
+        );
+
+        session.position = parse_position("7k/8/6K1/8/8/8/8/1Q6 w - - 0 1").unwrap();
+        assert_eq!(
+            play_move(&request("b1a3"), &mut session).unwrap_err().0,
+            422
+        );
+        let position = play_move(&request("b1b8"), &mut session).unwrap();
+        assert_eq!(position.fen, "1Q5k/8/6K1/8/8/8/8/8 b - - 1 1");
+        assert_eq!(position.result.as_deref(), Some("1-0"));
+    }
+}
@@ -0,0 +1,197 @@
+use std::{collections::BTreeMap, fmt::Write, sync::Mutex, time::Duration};
+
+use super::sessions::{MAX_TABLE_ENTRIES, SessionId};
+
+#[derive(Debug, Default)]
+struct Latency {
+    total: Duration,
+    count: u64,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    searches: u64,
+    nodes: u64,
+    search_time: Duration,
+    depth_sum: u64,
+    /// Table entries of every open session after its last search.
+    table_entries: BTreeMap<SessionId, usize>,
+    /// Time from receiving a request to answering it, per open session.
+    latency: BTreeMap<SessionId, Latency>,
+    /// Same for requests outside of a session.
+    one_off_latency: Latency,
+}
+
+/// Numbers about the searches done so far, served in the Prometheus text
+/// format for monitoring hosted instances.
+#[derive(Debug, Default)]
+pub(super) struct Metrics(Mutex<Counters>);
+
+/// What one finished search adds to the metrics.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct SearchRecord {
+    /// `None` for requests outside of a session.
+    pub session: Option<SessionId>,
+    pub nodes: u64,
+    pub depth: u64,
+    pub search_time: Duration,
+    /// Including the time spent waiting for a worker.
+    pub latency: Duration,
+    pub table_entries: usize,
+}
+
+impl Metrics {
+    pub fn record(&self, record: SearchRecord) {
+        let mut counters = self.0.lock().expect("Metrics lock poisoned");
+        counters.searches += 1;
+        counters.nodes += record.nodes;
+        counters.depth_sum += record.depth;
+        counters.search_time += record.search_time;
+        let latency = match record.session {
+            Some(id) => {
+                counters.table_entries.insert(id, record.table_entries);
+                counters.latency.entry(id).or_default()
+            }
+            None => &mut counters.one_off_latency,
+        };
+        latency.total += record.latency;
+        latency.count += 1;
+    }
+
+    /// Drops the per-session numbers of a closed session.
+    pub fn forget_session(&self, id: SessionId) {
+        let mut counters = self.0.lock().expect("Metrics lock poisoned");
+        counters.table_entries.remove(&id);
+        counters.latency.remove(&id);
+    }
+
+    /// All metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let counters = self.0.lock().expect("Metrics lock poisoned");
+        let mut text = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, String)]| {
+            let _ = writeln!(text, "# HELP {name} {help}\n# TYPE {name} {kind}");
+            for (labels, value) in samples {
+                let _ = writeln!(text, "{name}{labels} {value}");
+            }
+        };
+        let single = |value: String| [(String::new(), value)];
+
+        let seconds = counters.search_time.as_secs_f64();
+        let nodes_per_second = if seconds > 0.0 {
+            counters.nodes as f64 / seconds
+        } else {
+            0.0
+        };
+        let average_depth = if counters.searches > 0 {
+            counters.depth_sum as f64 / counters.searches as f64
+        } else {
+            0.0
+        };
+        let entries: usize = counters.table_entries.values().sum();
+        let capacity = counters.table_entries.len() * MAX_TABLE_ENTRIES;
+        let occupancy = if capacity > 0 {
+            entries as f64 / capacity as f64
+        } else {
+            0.0
+        };
+
+        metric(
+            "chess_bot_searches_total",
+            "counter",
+            "Searches completed.",
+            &single(counters.searches.to_string()),
+        );
+        metric(
+            "chess_bot_nodes_total",
+            "counter",
+            "Nodes searched.",
+            &single(counters.nodes.to_string()),
+        );
+        metric(
+            "chess_bot_nodes_per_second",
+            "gauge",
+            "Nodes searched per second of search time.",
+            &single(nodes_per_second.to_string()),
+        );
+        metric(
+            "chess_bot_average_depth",
+            "gauge",
+            "Average depth reached per search.",
+            &single(average_depth.to_string()),
+        );
+        metric(
+            "chess_bot_tt_entries",
+            "gauge",
+            "Transposition table entries over all sessions.",
+            &single(entries.to_string()),
+        );
+        metric(
+            "chess_bot_tt_occupancy",
+            "gauge",
+            "Share of the sessions' transposition table slices in use.",
+            &single(occupancy.to_string()),
+        );
+
+        // A summary without quantiles: only the sum and count per session
+        let _ = writeln!(
+            text,
+            "# HELP chess_bot_request_latency_seconds Time from receiving an analysis \
+             request to answering it, by session.\n\
+             # TYPE chess_bot_request_latency_seconds summary"
+        );
+        let sessions = counters
+            .latency
+            .iter()
+            .map(|(id, latency)| (id.to_string(), latency))
+            .chain([("none".to_owned(), &counters.one_off_latency)]);
+        for (session, latency) in sessions {
+            let _ = writeln!(
+                text,
+                "chess_bot_request_latency_seconds_sum{{session=\"{session}\"}} {}\n\
+                 chess_bot_request_latency_seconds_count{{session=\"{session}\"}} {}",
+                latency.total.as_secs_f64(),
+                latency.count
+            );
+        }
+
+        text
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::default();
+        let record = SearchRecord {
+            session: Some(3),
+            nodes: 2000,
+            depth: 6,
+            search_time: Duration::from_secs(2),
+            latency: Duration::from_millis(2500),
+            table_entries: MAX_TABLE_ENTRIES / 4,
+        };
+        metrics.record(record);
+        metrics.record(SearchRecord {
+            session: None,
+            depth: 4,
+            ..record
+        });
+
+        let text = metrics.render();
+        assert!(
+            text.contains("# TYPE chess_bot_searches_total counter\nchess_bot_searches_total 2\n")
+        );
+        assert!(text.contains("chess_bot_nodes_per_second 1000\n"));
+        assert!(text.contains("chess_bot_average_depth 5\n"));
+        assert!(text.contains("chess_bot_tt_occupancy 0.25\n"));
+        assert!(text.contains("chess_bot_request_latency_seconds_sum{session=\"3\"} 2.5\n"));
+        assert!(text.contains("chess_bot_request_latency_seconds_count{session=\"none\"} 1\n"));
+
+        metrics.forget_session(3);
+        assert!(!metrics.render().contains("session=\"3\""));
+    }
+}
@@ -0,0 +1,344 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+use shakmaty::variant::VariantPosition;
+
+use crate::transposition::TranspositionTable;
+
+/// Most sessions open at once, to bound memory use.
+pub(super) const MAX_SESSIONS: usize = 64;
+/// Most one-off requests queued or running at once; each gets a table of
+/// its own while it runs.
+pub(super) const MAX_ONE_OFF_JOBS: usize = 64;
+/// Most jobs queued for one session, so a client can't queue work without end.
+pub(super) const MAX_SESSION_JOBS: usize = 16;
+/// How long a session may go unused before another client may take its
+/// place, for clients that leave without closing their sessions.
+pub(super) const SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+/// Positions each session's transposition table holds, so every session
+/// gets a slice of the same size.
+pub(super) const MAX_TABLE_ENTRIES: usize = 1 << 18;
+
+pub(super) type SessionId = u64;
+
+/// A session opened by [`Scheduler::open`].
+#[derive(Debug, PartialEq, Eq)]
+pub(super) struct Opened {
+    pub id: SessionId,
+    /// Idle sessions closed to make room for this one.
+    pub expired: Vec<SessionId>,
+}
+
+/// Why [`Scheduler::submit`] gave a job back.
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum Rejected<J> {
+    NoSuchSession(J),
+    /// [`MAX_SESSION_JOBS`] are queued for the session already.
+    QueueFull(J),
+}
+
+/// One board of a client: the position last analyzed, its default limits
+/// and its own transposition table.
+pub(super) struct Session {
+    /// The start position until the client sends another.
+    pub position: VariantPosition,
+    pub movetime: Duration,
+    /// `None` until the first search, so queued sessions cost little memory.
+    table: Option<TranspositionTable>,
+}
+
+impl Session {
+    pub fn new(movetime: Duration) -> Self {
+        Self {
+            position: VariantPosition::default(),
+            movetime,
+            table: None,
+        }
+    }
+
+    /// The session's table, created on first use.
+    pub fn table(&mut self) -> &mut TranspositionTable {
+        self.table
+            .get_or_insert_with(|| TranspositionTable::with_entries(MAX_TABLE_ENTRIES))
+    }
+
+    /// Positions stored in the table so far.
+    pub fn table_entries(&self) -> usize {
+        self.table.as_ref().map_or(0, TranspositionTable::len)
+    }
+}
+
+struct Slot<J> {
+    /// `None` while a worker is searching with it.
+    session: Option<Session>,
+    jobs: VecDeque<J>,
+    /// Created for a single request and closed after it.
+    ephemeral: bool,
+    closed: bool,
+    /// When the session was opened or last had a job queued or finished.
+    last_used: Instant,
+}
+
+struct State<J> {
+    slots: HashMap<SessionId, Slot<J>>,
+    next_id: SessionId,
+    /// Idle sessions with queued jobs, in the order they will be served.
+    ready: VecDeque<SessionId>,
+}
+
+/// Hands queued jobs to the search workers, one session at a time and in
+/// turns, so a client queueing many searches can't starve the others.
+/// A session never runs two searches at once, as they share its table.
+pub(super) struct Scheduler<J> {
+    state: Mutex<State<J>>,
+    job_ready: Condvar,
+    idle_timeout: Duration,
+}
+
+impl<J> Scheduler<J> {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(State {
+                slots: HashMap::new(),
+                next_id: 1,
+                ready: VecDeque::new(),
+            }),
+            job_ready: Condvar::new(),
+            idle_timeout: SESSION_IDLE_TIMEOUT,
+        }
+    }
+
+    /// Lets sessions expire after `timeout` instead of [`SESSION_IDLE_TIMEOUT`].
+    #[cfg(test)]
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State<J>> {
+        self.state.lock().expect("Scheduler lock poisoned")
+    }
+
+    /// Opens a session. With [`MAX_SESSIONS`] open already, sessions idle for
+    /// longer than the timeout are closed first; `None` if none are.
+    pub fn open(&self, session: Session) -> Option<Opened> {
+        let mut state = self.lock();
+        let open = state.slots.values().filter(|slot| !slot.ephemeral).count();
+        let mut expired = Vec::new();
+        if open >= MAX_SESSIONS {
+            // Only sessions with nothing queued or running can be idle
+            expired = state
+                .slots
+                .iter()
+                .filter(|(_, slot)| {
+                    !slot.ephemeral
+                        && slot.session.is_some()
+                        && slot.jobs.is_empty()
+                        && slot.last_used.elapsed() >= self.idle_timeout
+                })
+                .map(|(&id, _)| id)
+                .collect();
+            if expired.is_empty() {
+                return None;
+            }
+            for id in &expired {
+                state.slots.remove(id);
+            }
+        }
+        let id = insert(&mut state, session, false);
+        Some(Opened { id, expired })
+    }
+
+    /// Closes a session. A search still running for it finishes first.
+    pub fn close(&self, id: SessionId) -> bool {
+        let mut state = self.lock();
+        let Some(slot) = state.slots.get_mut(&id) else {
+            return false;
+        };
+        if slot.session.is_some() {
+            state.slots.remove(&id);
+            state.ready.retain(|&ready| ready != id);
+        } else {
+            slot.closed = true;
+            slot.jobs.clear();
+        }
+        true
+    }
+
+    /// Queues `job` for session `id`; gives it back if there is no such
+    /// session or its queue is full.
+    pub fn submit(&self, id: SessionId, job: J) -> Result<(), Rejected<J>> {
+        let mut state = self.lock();
+        match state.slots.get_mut(&id) {
+            Some(slot) if slot.closed => Err(Rejected::NoSuchSession(job)),
+            Some(slot) if slot.jobs.len() >= MAX_SESSION_JOBS => Err(Rejected::QueueFull(job)),
+            Some(slot) => {
+                slot.jobs.push_back(job);
+                slot.last_used = Instant::now();
+                // A busy session is queued again once its search is done
+                if slot.session.is_some() && slot.jobs.len() == 1 {
+                    state.ready.push_back(id);
+                    self.job_ready.notify_one();
+                }
+                Ok(())
+            }
+            None => Err(Rejected::NoSuchSession(job)),
+        }
+    }
+
+    /// Queues `job` in a session of its own that closes after it; gives it
+    /// back if [`MAX_ONE_OFF_JOBS`] are queued or running already.
+    pub fn submit_once(&self, session: Session, job: J) -> Result<(), J> {
+        let mut state = self.lock();
+        let queued = state.slots.values().filter(|slot| slot.ephemeral).count();
+        if queued >= MAX_ONE_OFF_JOBS {
+            return Err(job);
+        }
+        let id = insert(&mut state, session, true);
+        let slot = state.slots.get_mut(&id).expect("Just inserted");
+        slot.jobs.push_back(job);
+        state.ready.push_back(id);
+        self.job_ready.notify_one();
+        Ok(())
+    }
+
+    /// Waits for the next job, taking its session until [`Scheduler::finish`].
+    pub fn next(&self) -> (SessionId, Session, J) {
+        let mut state = self
+            .job_ready
+            .wait_while(self.lock(), |state| state.ready.is_empty())
+            .expect("Scheduler lock poisoned");
+        let id = state.ready.pop_front().expect("Waited for a ready session");
+        let slot = state.slots.get_mut(&id).expect("Ready sessions exist");
+        let session = slot.session.take().expect("Ready sessions are idle");
+        let job = slot.jobs.pop_front().expect("Ready sessions have jobs");
+        (id, session, job)
+    }
+
+    /// Returns a session after its search; it goes to the back of the line
+    /// if it has more jobs. `false` if the session was closed meanwhile.
+    pub fn finish(&self, id: SessionId, session: Session) -> bool {
+        let mut state = self.lock();
+        let Some(slot) = state.slots.get_mut(&id) else {
+            return false;
+        };
+        if slot.closed {
+            state.slots.remove(&id);
+            return false;
+        }
+        if slot.ephemeral && slot.jobs.is_empty() {
+            state.slots.remove(&id);
+            return true;
+        }
+        slot.session = Some(session);
+        slot.last_used = Instant::now();
+        if !slot.jobs.is_empty() {
+            state.ready.push_back(id);
+            self.job_ready.notify_one();
+        }
+        true
+    }
+}
+
+fn insert<J>(state: &mut State<J>, session: Session, ephemeral: bool) -> SessionId {
+    let id = state.next_id;
+    state.next_id += 1;
+    state.slots.insert(
+        id,
+        Slot {
+            session: Some(session),
+            jobs: VecDeque::new(),
+            ephemeral,
+            closed: false,
+            last_used: Instant::now(),
+        },
+    );
+    id
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sessions_take_turns() {
+        let scheduler = Scheduler::new();
+        let session = || Session::new(Duration::from_millis(10));
+        let busy = scheduler.open(session()).unwrap().id;
+        let quiet = scheduler.open(session()).unwrap().id;
+
+        for job in ["busy 1", "busy 2", "busy 3"] {
+            scheduler.submit(busy, job).unwrap();
+        }
+        scheduler.submit(quiet, "quiet 1").unwrap();
+        scheduler.submit_once(session(), "once").unwrap();
+
+        let mut order = Vec::new();
+        for _ in 0..5 {
+            let (id, session, job) = scheduler.next();
+            order.push(job);
+            assert!(scheduler.finish(id, session));
+        }
+        assert_eq!(order, ["busy 1", "quiet 1", "once", "busy 2", "busy 3"]);
+
+        // A session closed during its search doesn't come back
+        scheduler.submit(busy, "busy 4").unwrap();
+        let (id, taken, _) = scheduler.next();
+        assert!(scheduler.close(busy));
+        assert!(!scheduler.finish(id, taken));
+        assert_eq!(
+            scheduler.submit(busy, "late"),
+            Err(Rejected::NoSuchSession("late"))
+        );
+
+        for _ in 0..MAX_SESSION_JOBS {
+            scheduler.submit(quiet, "queued").unwrap();
+        }
+        assert_eq!(
+            scheduler.submit(quiet, "extra"),
+            Err(Rejected::QueueFull("extra"))
+        );
+
+        for _ in 0..MAX_ONE_OFF_JOBS {
+            scheduler.submit_once(session(), "once").unwrap();
+        }
+        assert_eq!(scheduler.submit_once(session(), "extra"), Err("extra"));
+
+        assert!(scheduler.close(quiet));
+        assert!(!scheduler.close(quiet));
+        assert_eq!(
+            scheduler.submit(quiet, "late"),
+            Err(Rejected::NoSuchSession("late"))
+        );
+    }
+
+    #[test]
+    fn test_idle_sessions_expire() {
+        let session = || Session::new(Duration::from_millis(10));
+        let scheduler = Scheduler::<&str>::new();
+        for _ in 0..MAX_SESSIONS {
+            scheduler.open(session()).unwrap();
+        }
+        assert!(scheduler.open(session()).is_none());
+
+        let scheduler = Scheduler::new().with_idle_timeout(Duration::ZERO);
+        let ids: Vec<SessionId> = (0..MAX_SESSIONS)
+            .map(|_| scheduler.open(session()).unwrap().id)
+            .collect();
+        // Sessions with a search queued or running are never idle
+        scheduler.submit(ids[0], "queued").unwrap();
+        scheduler.submit(ids[1], "running").unwrap();
+        let (running, taken, _) = scheduler.next();
+        let opened = scheduler.open(session()).unwrap();
+        assert_eq!(opened.expired.len(), MAX_SESSIONS - 2);
+        assert!(!opened.expired.contains(&ids[0]) && !opened.expired.contains(&running));
+        assert!(scheduler.finish(running, taken));
+        assert_eq!(
+            scheduler.submit(ids[2], "late"),
+            Err(Rejected::NoSuchSession("late"))
+        );
+    }
+}
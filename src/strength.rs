@@ -0,0 +1,86 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
+
+use shakmaty::{Position, variant::VariantPosition};
+
+use crate::eval::{Evaluator, MATE_SCORE};
+
+/// Range offered through "UCI_Elo".
+pub const MIN_ELO: i64 = 800;
+pub const MAX_ELO: i64 = 2800;
+
+/// Nodes a search may use at [`MIN_ELO`]; every 100 Elo more doubles it.
+const NODES_AT_MIN_ELO: u64 = 64;
+/// Largest eval noise in centipawns, used at [`MIN_ELO`] and shrinking to none
+/// at [`MAX_ELO`].
+const MAX_NOISE: i64 = 200;
+
+/// Nodes a search may use to play at about `elo`.
+pub fn node_limit(elo: i64) -> u64 {
+    let steps = (elo.clamp(MIN_ELO, MAX_ELO) - MIN_ELO) / 100;
+    NODES_AT_MIN_ELO << steps
+}
+
+/// How far the evaluation may be off in centipawns when playing at about `elo`.
+pub fn noise_amplitude(elo: i64) -> i64 {
+    MAX_NOISE * (MAX_ELO - elo.clamp(MIN_ELO, MAX_ELO)) / (MAX_ELO - MIN_ELO)
+}
+
+/// Adds noise to another evaluator so that it misjudges positions like a
+/// weaker player. The noise depends only on the position and the seed, so the
+/// search sees the same score every time it reaches a position.
+pub struct NoisyEvaluator {
+    inner: Arc<dyn Evaluator>,
+    amplitude: i64,
+    seed: u64,
+}
+
+impl NoisyEvaluator {
+    pub fn new(inner: Arc<dyn Evaluator>, amplitude: i64, seed: u64) -> Self {
+        Self {
+            inner,
+            amplitude: amplitude.max(0),
+            seed,
+        }
+    }
+}
+
+impl Evaluator for NoisyEvaluator {
+    fn evaluate(&self, position: &VariantPosition) -> i64 {
+        let score = self.inner.evaluate(position);
+        if self.amplitude == 0 || score.abs() >= MATE_SCORE {
+            return score;
+        }
+        let mut hasher = DefaultHasher::new();
+        self.seed.hash(&mut hasher);
+        position.board().hash(&mut hasher);
+        position.turn().hash(&mut hasher);
+        let noise = (hasher.finish() % (2 * self.amplitude as u64 + 1)) as i64;
+        score + noise - self.amplitude
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::eval::PestoEvaluator;
+
+    #[test]
+    fn test_strength_scales_with_elo() {
+        assert_eq!(node_limit(MIN_ELO), 64);
+        assert_eq!(node_limit(1500), 64 << 7);
+        assert_eq!(node_limit(0), node_limit(MIN_ELO));
+        assert_eq!(noise_amplitude(MAX_ELO), 0);
+        assert_eq!(noise_amplitude(MIN_ELO), MAX_NOISE);
+
+        let position = VariantPosition::default();
+        let noisy = NoisyEvaluator::new(Arc::new(PestoEvaluator), 50, 7);
+        let exact = PestoEvaluator.evaluate(&position);
+        let score = noisy.evaluate(&position);
+        assert!((score - exact).abs() <= 50);
+        assert_eq!(noisy.evaluate(&position), score);
+    }
+}
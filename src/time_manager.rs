@@ -0,0 +1,711 @@
+use std::{sync::Arc, time::Duration};
+
+use shakmaty::{Color, Move, Position};
+use web_time::Instant;
+
+use crate::controller::SearchController;
+use crate::engine::{IterationInfo, SearchLimits, SearchObserver, SearchResult};
+use crate::eval::{MAX_GAME_PHASE, game_phase};
+
+/// Moves the remaining time is spread over when "go" has no "movestogo".
+const DEFAULT_MOVES_TO_GO: u64 = 20;
+
+/// How many times the soft limit a search on the clock may run to finish an iteration.
+const HARD_LIMIT_FACTOR: u32 = 3;
+
+/// A score falling by this much in one iteration counts as a sharp drop.
+const SHARP_SCORE_DROP: i64 = 50;
+
+/// Share of the usual think time in percent spent in a bare endgame, growing
+/// to [`MIDDLEGAME_TIME_PERCENT`] as pieces are added.
+const ENDGAME_TIME_PERCENT: i64 = 60;
+const MIDDLEGAME_TIME_PERCENT: i64 = 120;
+/// Share of the usual think time in percent when the position allows only
+/// this many moves or fewer, e.g. out of check.
+const FORCED_TIME_PERCENT: u32 = 60;
+const FORCED_ROOT_MOVES: usize = 3;
+
+/// With less than this on the clock there is no time to deepen; the engine
+/// plays whatever a single ply finds.
+pub const EMERGENCY_TIME: Duration = Duration::from_millis(200);
+
+/// What the time manager knows about the game when a search starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockState {
+    /// Time left on the engine's clock.
+    pub time_left: Option<Duration>,
+    pub increment: Duration,
+    pub moves_to_go: Option<u64>,
+    /// Full move number of the position being searched.
+    pub move_number: u32,
+    /// Fixed time per move, overriding the clock.
+    pub movetime: Option<Duration>,
+    /// Moves to choose from at the root, after "searchmoves".
+    pub root_moves: usize,
+    /// How much of the middlegame is left, see [`game_phase`].
+    pub game_phase: i64,
+}
+
+impl ClockState {
+    /// Picks the clock of the side to move from the limits of a "go" command.
+    pub fn new(limits: &SearchLimits, position: &impl Position) -> Self {
+        let (time_left, increment) = match position.turn() {
+            Color::White => (limits.wtime, limits.winc),
+            Color::Black => (limits.btime, limits.binc),
+        };
+        Self {
+            time_left,
+            increment: increment.unwrap_or_default(),
+            moves_to_go: limits.movestogo,
+            move_number: position.fullmoves().get(),
+            movetime: limits.movetime,
+            root_moves: if limits.search_moves.is_empty() {
+                position.legal_moves().len()
+            } else {
+                limits.search_moves.len()
+            },
+            game_phase: game_phase(position.board()),
+        }
+    }
+}
+
+impl ClockState {
+    /// Whether the clock is so low that any real search risks losing on time.
+    pub fn is_critical(&self) -> bool {
+        self.movetime.is_none() && self.time_left.is_some_and(|time| time < EMERGENCY_TIME)
+    }
+}
+
+/// How long a search may take. Past `soft` no new iteration should start,
+/// at `hard` the search is cut off wherever it is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeBudget {
+    pub soft: Duration,
+    pub hard: Duration,
+}
+
+impl TimeBudget {
+    /// No time limit, for searches that end at a depth or on "stop".
+    pub const UNLIMITED: TimeBudget = TimeBudget {
+        soft: Duration::MAX,
+        hard: Duration::MAX,
+    };
+
+    /// The same time for both limits, when no iteration may run over.
+    pub const fn fixed(think_time: Duration) -> TimeBudget {
+        TimeBudget {
+            soft: think_time,
+            hard: think_time,
+        }
+    }
+
+    /// Leaves `overhead` of both limits for getting the move to the clock,
+    /// e.g. over a network.
+    pub fn minus_overhead(self, overhead: Duration) -> TimeBudget {
+        TimeBudget {
+            soft: self.soft.saturating_sub(overhead),
+            hard: self.hard.saturating_sub(overhead),
+        }
+    }
+
+    /// Shortens the soft limit to `percent` of itself; the hard limit stays
+    /// as a safety net.
+    pub fn scale_soft(self, percent: u32) -> TimeBudget {
+        TimeBudget {
+            soft: self.soft / 100 * percent.min(100),
+            hard: self.hard,
+        }
+    }
+}
+
+/// How much the search has been changing its mind, tracked across iterations.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stability {
+    /// Iterations in a row that kept the same best move.
+    pub stable_iterations: u32,
+    /// Times the best move changed during this search.
+    pub best_move_changes: u32,
+    /// How far the score fell in the last iteration, zero if it rose.
+    pub score_drop: i64,
+    /// Score of the last iteration.
+    pub score: i64,
+}
+
+/// Decides how much time to spend on a move.
+pub trait TimeManager: Send + Sync {
+    fn allocate(&self, clock: &ClockState) -> TimeBudget;
+
+    /// Called after every iteration; returning `true` ends the search.
+    fn should_stop(&self, budget: &TimeBudget, elapsed: Duration, _stability: &Stability) -> bool {
+        elapsed >= budget.soft
+    }
+
+    /// How much of the budget `nodes` searched in `wall_time` have used up.
+    fn elapsed(&self, wall_time: Duration, _nodes: u64) -> Duration {
+        wall_time
+    }
+
+    /// Nodes that take the place of the hard limit as a wall-clock deadline,
+    /// for time managers that count nodes instead of time.
+    fn node_limit(&self, _budget: &TimeBudget) -> Option<u64> {
+        None
+    }
+}
+
+/// Picks the time policy that fits the limits of a "go" command: a clock is
+/// measured in nodes with "nodestime", or in wall time otherwise. Without
+/// `shortcuts` the full time is used even when the move is obvious.
+pub fn select(limits: &SearchLimits, shortcuts: bool) -> Arc<dyn TimeManager> {
+    let has_clock = limits.wtime.is_some() || limits.btime.is_some();
+    if !has_clock || limits.movetime.is_some() {
+        return Arc::new(FixedTime);
+    }
+    let tournament = TournamentTime {
+        early_exit: shortcuts.then(EarlyExit::default),
+    };
+    match limits.nodestime {
+        Some(nodes_per_millisecond) => Arc::new(NodesAsTime {
+            inner: tournament,
+            ..NodesAsTime::new(nodes_per_millisecond)
+        }),
+        None => Arc::new(tournament),
+    }
+}
+
+/// Thinks for exactly the "movetime". Without one, other limits such as a
+/// depth or a node count end the search.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FixedTime;
+
+impl TimeManager for FixedTime {
+    fn allocate(&self, clock: &ClockState) -> TimeBudget {
+        clock
+            .movetime
+            .map_or(TimeBudget::UNLIMITED, TimeBudget::fixed)
+    }
+}
+
+/// When a search may end well before its soft limit because the move is clear.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EarlyExit {
+    /// Score from which the side to move is far enough ahead.
+    pub winning_score: i64,
+    /// Iterations in a row the best move must have stayed the same.
+    pub stable_iterations: u32,
+    /// Share of the soft limit in percent that is always used.
+    pub min_time_percent: u32,
+}
+
+impl Default for EarlyExit {
+    fn default() -> Self {
+        Self {
+            winning_score: 400,
+            stable_iterations: 6,
+            min_time_percent: 25,
+        }
+    }
+}
+
+/// Aims to spread the remaining time evenly over the moves to the next time
+/// control, plus most of the increment. An iteration that is already running
+/// may take a few times as long, but never more than half of what is left.
+/// More time goes to middlegames than to simple endgames and forcing lines.
+/// Moves that are forced or clearly winning are played early, and the soft
+/// limit grows towards the hard one while the search keeps changing its mind.
+#[derive(Debug, Clone, Copy)]
+pub struct TournamentTime {
+    /// `None` uses the full time even for forced or clearly winning moves.
+    pub early_exit: Option<EarlyExit>,
+}
+
+impl Default for TournamentTime {
+    fn default() -> Self {
+        Self {
+            early_exit: Some(EarlyExit::default()),
+        }
+    }
+}
+
+impl TimeManager for TournamentTime {
+    fn allocate(&self, clock: &ClockState) -> TimeBudget {
+        let budget = self.budget(clock);
+        if clock.root_moves == 1 && self.early_exit.is_some() {
+            // Nothing to think about, the first iteration is enough to move
+            return TimeBudget {
+                soft: Duration::ZERO,
+                ..budget
+            };
+        }
+        budget
+    }
+
+    fn should_stop(&self, budget: &TimeBudget, elapsed: Duration, stability: &Stability) -> bool {
+        let early_exit = self.early_exit.is_some_and(|early_exit| {
+            stability.score >= early_exit.winning_score
+                && stability.stable_iterations >= early_exit.stable_iterations
+                && elapsed >= budget.soft * early_exit.min_time_percent / 100
+        });
+        early_exit || elapsed >= extended_soft_limit(budget, stability)
+    }
+}
+
+/// The soft limit, doubled if the best move just changed and again if the
+/// score dropped sharply, but never past the hard limit.
+fn extended_soft_limit(budget: &TimeBudget, stability: &Stability) -> Duration {
+    let mut soft = budget.soft;
+    if stability.best_move_changes > 0 && stability.stable_iterations == 0 {
+        soft = soft.saturating_mul(2);
+    }
+    if stability.score_drop >= SHARP_SCORE_DROP {
+        soft = soft.saturating_mul(2);
+    }
+    soft.min(budget.hard)
+}
+
+impl TournamentTime {
+    fn budget(&self, clock: &ClockState) -> TimeBudget {
+        match (clock.movetime, clock.time_left) {
+            (Some(movetime), _) => TimeBudget::fixed(movetime),
+            (None, Some(time_left)) => {
+                let moves_to_go = clock.moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+                let max_time = time_left / 2;
+                let usual = time_left / moves_to_go as u32 + clock.increment * 4 / 5;
+                let soft = (usual * time_percent(clock) / 100).min(max_time);
+                TimeBudget {
+                    soft,
+                    hard: (soft * HARD_LIMIT_FACTOR).min(max_time),
+                }
+            }
+            (None, None) => TimeBudget::UNLIMITED,
+        }
+    }
+}
+
+/// Plays like [`TournamentTime`], but counts nodes instead of measuring time,
+/// so that tests and fixed-work matches give the same result on any machine.
+#[derive(Debug, Clone, Copy)]
+pub struct NodesAsTime {
+    /// Nodes that count as one millisecond.
+    pub nodes_per_millisecond: u64,
+    pub inner: TournamentTime,
+}
+
+impl NodesAsTime {
+    pub fn new(nodes_per_millisecond: u64) -> Self {
+        Self {
+            nodes_per_millisecond: nodes_per_millisecond.max(1),
+            inner: TournamentTime::default(),
+        }
+    }
+}
+
+impl TimeManager for NodesAsTime {
+    fn allocate(&self, clock: &ClockState) -> TimeBudget {
+        self.inner.allocate(clock)
+    }
+
+    fn should_stop(&self, budget: &TimeBudget, elapsed: Duration, stability: &Stability) -> bool {
+        self.inner.should_stop(budget, elapsed, stability)
+    }
+
+    fn elapsed(&self, _wall_time: Duration, nodes: u64) -> Duration {
+        Duration::from_millis(nodes / self.nodes_per_millisecond)
+    }
+
+    fn node_limit(&self, budget: &TimeBudget) -> Option<u64> {
+        (budget.hard != Duration::MAX).then(|| {
+            let hard_ms = u64::try_from(budget.hard.as_millis()).unwrap_or(u64::MAX);
+            hard_ms.saturating_mul(self.nodes_per_millisecond)
+        })
+    }
+}
+
+/// Share of the usual think time in percent that suits the position.
+fn time_percent(clock: &ClockState) -> u32 {
+    let phase = clock.game_phase.clamp(0, MAX_GAME_PHASE);
+    let percent = ENDGAME_TIME_PERCENT
+        + (MIDDLEGAME_TIME_PERCENT - ENDGAME_TIME_PERCENT) * phase / MAX_GAME_PHASE;
+    let percent = percent as u32;
+    if clock.root_moves <= FORCED_ROOT_MOVES {
+        percent * FORCED_TIME_PERCENT / 100
+    } else {
+        percent
+    }
+}
+
+/// Asks a [`TimeManager`] after every iteration whether the search should go on.
+pub struct TimeKeeper<'a, O: SearchObserver> {
+    time_manager: &'a dyn TimeManager,
+    budget: TimeBudget,
+    start_time: Instant,
+    controller: SearchController,
+    stability: Stability,
+    last_iteration: Option<(Move, i64)>,
+    /// When the time manager ended the search, if it did.
+    stopped_after: Option<Duration>,
+    /// Observer that receives all events after the time keeper.
+    inner: O,
+}
+
+impl<'a, O: SearchObserver> TimeKeeper<'a, O> {
+    pub fn new(
+        time_manager: &'a dyn TimeManager,
+        budget: TimeBudget,
+        controller: SearchController,
+        inner: O,
+    ) -> Self {
+        Self {
+            time_manager,
+            budget,
+            start_time: Instant::now(),
+            controller,
+            stability: Stability::default(),
+            last_iteration: None,
+            stopped_after: None,
+            inner,
+        }
+    }
+
+    /// Time the search had taken when the time manager ended it, `None` if
+    /// something else ended it.
+    pub fn stopped_after(&self) -> Option<Duration> {
+        self.stopped_after
+    }
+}
+
+impl<O: SearchObserver> SearchObserver for TimeKeeper<'_, O> {
+    fn on_iteration(&mut self, info: &IterationInfo) {
+        if let Some((last_best_move, last_score)) = self.last_iteration {
+            if last_best_move == info.best_move {
+                self.stability.stable_iterations += 1;
+            } else {
+                self.stability.stable_iterations = 0;
+                self.stability.best_move_changes += 1;
+            }
+            self.stability.score_drop = (last_score - info.score).max(0);
+        }
+        self.stability.score = info.score;
+        self.last_iteration = Some((info.best_move, info.score));
+
+        self.inner.on_iteration(info);
+
+        // The clock only runs once the opponent has played the expected move
+        if self.controller.is_pondering() {
+            return;
+        }
+        let (start_time, start_nodes) = self
+            .controller
+            .ponderhit_at()
+            .unwrap_or((self.start_time, 0));
+        let elapsed = self
+            .time_manager
+            .elapsed(start_time.elapsed(), self.controller.nodes() - start_nodes);
+        if self
+            .time_manager
+            .should_stop(&self.budget, elapsed, &self.stability)
+        {
+            tracing::debug!(
+                elapsed_ms = elapsed.as_millis() as u64,
+                "time manager ends search"
+            );
+            self.stopped_after = Some(elapsed);
+            self.controller.stop();
+        }
+    }
+
+    fn on_new_best_move(&mut self, info: &IterationInfo) {
+        self.inner.on_new_best_move(info);
+    }
+
+    fn on_root_move(&mut self, depth: u64, root_move: Move, number: usize) {
+        self.inner.on_root_move(depth, root_move, number);
+    }
+
+    fn on_search_finished(&mut self, result: &SearchResult) {
+        self.inner.on_search_finished(result);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use shakmaty::Chess;
+
+    use super::*;
+
+    #[test]
+    fn test_default_allocation() {
+        let limits = SearchLimits {
+            wtime: Some(Duration::from_secs(20)),
+            btime: Some(Duration::from_secs(40)),
+            ..SearchLimits::default()
+        };
+        let clock = ClockState::new(&limits, &Chess::default());
+        assert_eq!(clock.time_left, Some(Duration::from_secs(20)));
+        assert_eq!(clock.move_number, 1);
+        assert!(!clock.is_critical());
+
+        let budget = TournamentTime::default().allocate(&clock);
+        assert_eq!(budget.soft, Duration::from_millis(1200));
+        assert_eq!(budget.hard, Duration::from_millis(3600));
+        assert!(TournamentTime::default().should_stop(&budget, budget.soft, &Stability::default()));
+    }
+
+    #[test]
+    fn test_movetime_overrides_clock() {
+        let limits = SearchLimits {
+            movetime: Some(Duration::from_millis(700)),
+            wtime: Some(Duration::from_secs(20)),
+            ..SearchLimits::default()
+        };
+        let clock = ClockState::new(&limits, &Chess::default());
+        let budget = TournamentTime::default().allocate(&clock);
+        assert_eq!(budget.soft, Duration::from_millis(700));
+        assert_eq!(budget.hard, Duration::from_millis(700));
+        let stability = Stability::default();
+        assert!(!TournamentTime::default().should_stop(
+            &budget,
+            Duration::from_millis(699),
+            &stability
+        ));
+
+        let budget = budget.minus_overhead(Duration::from_millis(100));
+        assert_eq!(budget.hard, Duration::from_millis(600));
+        let budget = budget.minus_overhead(Duration::from_secs(1));
+        assert_eq!(budget.soft, Duration::ZERO);
+    }
+
+    #[test]
+    fn test_increment_allocation() {
+        let limits = SearchLimits {
+            wtime: Some(Duration::from_secs(20)),
+            winc: Some(Duration::from_secs(2)),
+            ..SearchLimits::default()
+        };
+        let clock = ClockState::new(&limits, &Chess::default());
+        let budget = TournamentTime::default().allocate(&clock);
+        assert_eq!(budget.soft, Duration::from_millis(3120));
+        assert_eq!(budget.hard, Duration::from_millis(9360));
+
+        // Low on time, the increment must not make the engine flag
+        let limits = SearchLimits {
+            wtime: Some(Duration::from_secs(1)),
+            ..limits
+        };
+        let clock = ClockState::new(&limits, &Chess::default());
+        let budget = TournamentTime::default().allocate(&clock);
+        assert_eq!(budget.soft, Duration::from_millis(500));
+        assert_eq!(budget.hard, Duration::from_millis(500));
+        assert!(!clock.is_critical());
+        let clock = ClockState {
+            time_left: Some(Duration::from_millis(150)),
+            ..clock
+        };
+        assert!(clock.is_critical());
+    }
+
+    #[test]
+    fn test_moves_to_go_allocation() {
+        let limits = SearchLimits {
+            btime: Some(Duration::from_secs(30)),
+            movestogo: Some(5),
+            ..SearchLimits::default()
+        };
+        let position = Chess::default().swap_turn().unwrap();
+        let budget = TournamentTime::default().allocate(&ClockState::new(&limits, &position));
+        assert_eq!(budget.soft, Duration::from_millis(7200));
+        assert_eq!(budget.hard, Duration::from_secs(15));
+
+        let limits = SearchLimits {
+            movestogo: Some(1),
+            ..limits
+        };
+        let budget = TournamentTime::default().allocate(&ClockState::new(&limits, &position));
+        assert_eq!(budget.hard, Duration::from_secs(15));
+    }
+
+    #[test]
+    fn test_early_exit() {
+        let limits = SearchLimits {
+            wtime: Some(Duration::from_secs(20)),
+            ..SearchLimits::default()
+        };
+        // The rook leaves the king on a1 a single move
+        let forced: Chess = "1r6/8/8/8/8/8/8/K6k w - - 0 1"
+            .parse::<shakmaty::fen::Fen>()
+            .unwrap()
+            .into_position(shakmaty::CastlingMode::Standard)
+            .unwrap();
+        let time_manager = TournamentTime::default();
+        let budget = time_manager.allocate(&ClockState::new(&limits, &forced));
+        assert_eq!(budget.soft, Duration::ZERO);
+        assert_eq!(budget.hard, Duration::from_millis(1170));
+
+        let budget = time_manager.allocate(&ClockState::new(&limits, &Chess::default()));
+        let elapsed = Duration::from_millis(300);
+        let mut stability = Stability {
+            stable_iterations: 8,
+            score: 150,
+            ..Stability::default()
+        };
+        assert!(!time_manager.should_stop(&budget, elapsed, &stability));
+        stability.score = 900;
+        assert!(time_manager.should_stop(&budget, elapsed, &stability));
+        assert!(!time_manager.should_stop(&budget, Duration::from_millis(200), &stability));
+
+        // Analysis takes its time even when the move is obvious
+        let analysis = TournamentTime { early_exit: None };
+        assert!(!analysis.should_stop(&budget, elapsed, &stability));
+        let budget = analysis.allocate(&ClockState::new(&limits, &forced));
+        assert!(budget.soft > Duration::ZERO);
+    }
+
+    #[test]
+    fn test_instability_extends_soft_limit() {
+        let time_manager = TournamentTime::default();
+        let budget = TimeBudget {
+            soft: Duration::from_secs(1),
+            hard: Duration::from_secs(3),
+        };
+        let elapsed = Duration::from_millis(1500);
+        assert!(time_manager.should_stop(&budget, elapsed, &Stability::default()));
+
+        let changed_mind = Stability {
+            best_move_changes: 1,
+            ..Stability::default()
+        };
+        assert!(!time_manager.should_stop(&budget, elapsed, &changed_mind));
+
+        let collapsing = Stability {
+            score_drop: 120,
+            ..changed_mind
+        };
+        let elapsed = Duration::from_millis(2900);
+        assert!(!time_manager.should_stop(&budget, elapsed, &collapsing));
+        assert!(time_manager.should_stop(&budget, budget.hard, &collapsing));
+    }
+
+    #[test]
+    fn test_game_phase_allocation() {
+        let limits = SearchLimits {
+            wtime: Some(Duration::from_secs(60)),
+            ..SearchLimits::default()
+        };
+        let pawn_ending: Chess = "4k3/pp3ppp/8/8/8/8/PP3PPP/4K3 w - - 0 30"
+            .parse::<shakmaty::fen::Fen>()
+            .unwrap()
+            .into_position(shakmaty::CastlingMode::Standard)
+            .unwrap();
+        let time_manager = TournamentTime::default();
+        let middlegame = time_manager.allocate(&ClockState::new(&limits, &Chess::default()));
+        let endgame = time_manager.allocate(&ClockState::new(&limits, &pawn_ending));
+        assert_eq!(middlegame.soft, Duration::from_millis(3600));
+        assert_eq!(endgame.soft, Duration::from_millis(1800));
+    }
+
+    #[test]
+    fn test_select_by_go_parameters() {
+        let clock_limits = SearchLimits {
+            wtime: Some(Duration::from_secs(20)),
+            ..SearchLimits::default()
+        };
+        let clock = ClockState::new(&clock_limits, &Chess::default());
+        assert_eq!(
+            select(&clock_limits, true).allocate(&clock).hard,
+            Duration::from_millis(3600)
+        );
+
+        let movetime_limits = SearchLimits {
+            movetime: Some(Duration::from_millis(250)),
+            ..clock_limits.clone()
+        };
+        let clock = ClockState::new(&movetime_limits, &Chess::default());
+        assert_eq!(
+            select(&movetime_limits, true).allocate(&clock),
+            TimeBudget::fixed(Duration::from_millis(250))
+        );
+        assert_eq!(
+            select(&SearchLimits::default(), true).allocate(&clock),
+            TimeBudget::fixed(Duration::from_millis(250))
+        );
+        let nodes_limits = SearchLimits {
+            nodes: Some(1000),
+            ..SearchLimits::default()
+        };
+        let clock = ClockState::new(&nodes_limits, &Chess::default());
+        assert_eq!(
+            select(&nodes_limits, true).allocate(&clock),
+            TimeBudget::UNLIMITED
+        );
+        assert_eq!(
+            TournamentTime::default().allocate(&clock),
+            TimeBudget::UNLIMITED
+        );
+
+        let nodestime_limits = SearchLimits {
+            nodestime: Some(1000),
+            ..clock_limits
+        };
+        let clock = ClockState::new(&nodestime_limits, &Chess::default());
+        let time_manager = select(&nodestime_limits, true);
+        let budget = time_manager.allocate(&clock);
+        assert_eq!(budget.hard, Duration::from_millis(3600));
+        assert_eq!(time_manager.node_limit(&budget), Some(3_600_000));
+        assert_eq!(
+            time_manager.elapsed(Duration::ZERO, 5000),
+            Duration::from_millis(5)
+        );
+    }
+
+    #[test]
+    fn test_clock_starts_at_ponderhit() {
+        use std::sync::atomic::AtomicBool;
+
+        use crate::engine::ScoreBound;
+
+        let controller =
+            SearchController::new(Arc::new(AtomicBool::new(true))).with_pondering(true);
+        let budget = TimeBudget::fixed(Duration::from_millis(50));
+        let mut time_keeper = TimeKeeper::new(&FixedTime, budget, controller.clone(), ());
+        let best_move = Chess::default().legal_moves()[0];
+        let info = IterationInfo {
+            depth: 1,
+            seldepth: 1,
+            score: 0,
+            bound: ScoreBound::Exact,
+            nodes: 1,
+            hashfull: None,
+            best_move,
+            pv: vec![best_move],
+        };
+
+        // Longer than the whole budget, but spent on the opponent's time
+        std::thread::sleep(Duration::from_millis(100));
+        controller.ponderhit(None, None);
+        time_keeper.on_iteration(&info);
+        assert!(!controller.is_stopped());
+
+        std::thread::sleep(Duration::from_millis(60));
+        time_keeper.on_iteration(&info);
+        assert!(controller.is_stopped());
+    }
+
+    #[test]
+    fn test_nodes_as_time() {
+        let time_manager = NodesAsTime::new(1000);
+        let limits = SearchLimits {
+            wtime: Some(Duration::from_secs(20)),
+            ..SearchLimits::default()
+        };
+        let budget = time_manager.allocate(&ClockState::new(&limits, &Chess::default()));
+        assert_eq!(budget.soft, Duration::from_millis(1200));
+        assert_eq!(time_manager.node_limit(&budget), Some(3_600_000));
+        assert_eq!(time_manager.node_limit(&TimeBudget::UNLIMITED), None);
+
+        let stability = Stability::default();
+        let elapsed = time_manager.elapsed(Duration::from_secs(60), 1_100_000);
+        assert_eq!(elapsed, Duration::from_millis(1100));
+        assert!(!time_manager.should_stop(&budget, elapsed, &stability));
+        let elapsed = time_manager.elapsed(Duration::ZERO, 1_200_000);
+        assert!(time_manager.should_stop(&budget, elapsed, &stability));
+    }
+}
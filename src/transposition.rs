@@ -0,0 +1,168 @@
+use std::{cell::Cell, mem};
+
+use shakmaty::zobrist::Zobrist64;
+
+use crate::engine::TranspositionInformation;
+
+/// Table size used until the GUI sets "Hash".
+pub const DEFAULT_HASH_MB: usize = 16;
+/// Largest table the "Hash" option allows. A 32-bit target can't address
+/// more than 2 GB in a single allocation.
+#[cfg(target_pointer_width = "64")]
+pub const MAX_HASH_MB: usize = 4096;
+#[cfg(not(target_pointer_width = "64"))]
+pub const MAX_HASH_MB: usize = 1024;
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    key: Zobrist64,
+    info: TranspositionInformation,
+}
+
+/// What the search learned about positions, in a fixed number of slots picked
+/// by Zobrist hash. A new entry replaces whatever was in its slot, so memory
+/// use never grows past the size given up front.
+#[derive(Debug, Clone)]
+pub struct TranspositionTable {
+    entries: Vec<Option<Entry>>,
+    /// Slots in use.
+    len: usize,
+    /// Lookups and how many of them found their position, since the last
+    /// [`TranspositionTable::reset_stats`].
+    probes: Cell<u64>,
+    hits: Cell<u64>,
+}
+
+impl TranspositionTable {
+    /// A table taking up about `size_mb` megabytes.
+    pub fn new(size_mb: usize) -> Self {
+        let size = size_mb.clamp(1, MAX_HASH_MB) * 1024 * 1024;
+        Self::with_entries(size / mem::size_of::<Option<Entry>>())
+    }
+
+    /// A table with room for exactly `entries` positions.
+    pub fn with_entries(entries: usize) -> Self {
+        Self {
+            entries: vec![None; entries.max(1)],
+            len: 0,
+            probes: Cell::new(0),
+            hits: Cell::new(0),
+        }
+    }
+
+    fn index(&self, key: Zobrist64) -> usize {
+        (key.0 % self.entries.len() as u64) as usize
+    }
+
+    pub fn get(&self, key: &Zobrist64) -> Option<&TranspositionInformation> {
+        let info = self.entries[self.index(*key)]
+            .as_ref()
+            .filter(|entry| entry.key == *key)
+            .map(|entry| &entry.info);
+        self.probes.set(self.probes.get() + 1);
+        self.hits.set(self.hits.get() + info.is_some() as u64);
+        info
+    }
+
+    pub fn insert(&mut self, key: Zobrist64, info: TranspositionInformation) {
+        let index = self.index(key);
+        let slot = &mut self.entries[index];
+        if slot.is_none() {
+            self.len += 1;
+        }
+        *slot = Some(Entry { key, info });
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.fill(None);
+        self.len = 0;
+    }
+
+    /// Positions stored.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Permille of the slots in use, as reported in "hashfull".
+    pub fn hashfull(&self) -> u64 {
+        (self.len * 1000 / self.entries.len()) as u64
+    }
+
+    pub fn probes(&self) -> u64 {
+        self.probes.get()
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits.get()
+    }
+
+    pub fn reset_stats(&self) {
+        self.probes.set(0);
+        self.hits.set(0);
+    }
+
+    /// Positions the table can hold at most.
+    pub fn capacity(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Memory taken up by the slots, in bytes.
+    pub fn size_bytes(&self) -> usize {
+        self.entries.len() * mem::size_of::<Option<Entry>>()
+    }
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        Self::new(DEFAULT_HASH_MB)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use shakmaty::zobrist::ZobristHash;
+    use shakmaty::{Chess, EnPassantMode, Position};
+
+    use super::*;
+    use crate::engine::{TranspositionHashType, record_hash};
+
+    #[test]
+    fn test_fixed_size() {
+        let mut table = TranspositionTable::new(1);
+        let capacity = table.capacity();
+        assert!(capacity * mem::size_of::<Option<Entry>>() <= 1024 * 1024);
+
+        let position = Chess::default();
+        for m in position.legal_moves() {
+            let child = position.clone().play(m).unwrap();
+            let key = child.zobrist_hash::<Zobrist64>(EnPassantMode::Legal);
+            record_hash(&mut table, key, 1, 0, TranspositionHashType::Exact, None);
+        }
+        assert_eq!(table.len(), 20);
+
+        // Keys sharing a slot replace each other instead of growing the table
+        let mut small = TranspositionTable::with_entries(4);
+        for key in 0..100 {
+            record_hash(
+                &mut small,
+                Zobrist64(key),
+                1,
+                0,
+                TranspositionHashType::Exact,
+                None,
+            );
+        }
+        assert_eq!(small.len(), 4);
+        assert_eq!(small.hashfull(), 1000);
+        assert!(small.get(&Zobrist64(99)).is_some());
+        assert!(small.get(&Zobrist64(95)).is_none());
+        assert_eq!((small.hits(), small.probes()), (1, 2));
+
+        small.clear();
+        assert!(small.is_empty());
+    }
+}
@@ -0,0 +1,1722 @@
+use shakmaty::fen::Fen;
+use shakmaty::san::SanPlus;
+use shakmaty::uci::UciMove;
+use shakmaty::variant::{Variant, VariantPosition};
+use shakmaty::zobrist::Zobrist64;
+use shakmaty::zobrist::ZobristHash;
+use shakmaty::{CastlingMode, Color, EnPassantMode, File, Move, Position, Rank, Square, perft};
+use std::fs;
+use std::sync::{Arc, Mutex, atomic::AtomicBool};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::bench;
+use crate::controller::SearchController;
+use crate::engine::{
+    IterationInfo, ScoreBound, SearchFeatures, SearchLimits, SearchMode, SearchObserver,
+    mate_in_moves,
+};
+use crate::error::Error;
+use crate::eval::{
+    self, EVAL_TERMS, EvalTrace, EvalWeights, Evaluator, EvaluatorKind, MAX_GAME_PHASE,
+};
+use crate::genfens;
+use crate::pgn::PgnGame;
+use crate::protocol::{ResponseWriter, Stdio};
+use crate::see;
+use crate::selftest;
+use crate::strength::{self, NoisyEvaluator};
+use crate::time_manager::{self, ClockState, TimeBudget, TimeManager};
+use crate::transposition::DEFAULT_HASH_MB;
+use crate::wdl::wdl;
+pub use builder::EngineBuilder;
+use crash::CrashContext;
+use json::JsonOutput;
+use opponent::Opponent;
+use worker::{SearchJob, SearchWaiter, SearchWorker};
+
+mod builder;
+mod crash;
+mod json;
+pub mod opponent;
+pub mod options;
+mod worker;
+
+/// Every command word defined by the UCI protocol (GUI to engine).
+const UCI_COMMANDS: [&str; 11] = [
+    "uci",
+    "debug",
+    "isready",
+    "setoption",
+    "register",
+    "ucinewgame",
+    "position",
+    "go",
+    "stop",
+    "ponderhit",
+    "quit",
+];
+
+/// Commands of our own, outside the UCI spec, for debugging from a terminal.
+const EXTRA_COMMANDS: [&str; 14] = [
+    "see", "state", "loadpgn", "fen", "getfen", "flip", "mirror", "swapturn", "selftest",
+    "genfens", "d", "eval", "perft", "bench",
+];
+
+/// Variants offered through the "UCI_Variant" option.
+const VARIANTS: [Variant; 8] = [
+    Variant::Chess,
+    Variant::Atomic,
+    Variant::Antichess,
+    Variant::KingOfTheHill,
+    Variant::ThreeCheck,
+    Variant::Crazyhouse,
+    Variant::RacingKings,
+    Variant::Horde,
+];
+
+/// Values of the options the GUI can change via "setoption".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EngineOptions {
+    pub nickname: String,
+    pub uci_chess960: bool,
+    pub uci_variant: Variant,
+    /// Tracing filter directives, e.g. "debug" or "chess_bot::engine=trace".
+    pub log_level: String,
+    /// File to append logs to; stderr is used when empty.
+    pub log_file: String,
+    pub evaluator: EvaluatorKind,
+    pub search_features: SearchFeatures,
+    pub search_mode: SearchMode,
+    /// Centipawns to give up to avoid a draw, before adjusting for the opponent.
+    pub contempt: i64,
+    /// As announced by the GUI in "UCI_Opponent".
+    pub opponent: Option<Opponent>,
+    /// Shows search progress as a table for people at a terminal, instead of info lines.
+    pub pretty_output: bool,
+    /// Sends every response as a JSON object on a line of its own.
+    pub json_output: bool,
+    /// Explains each chosen move by comparing it with the alternatives, after the bestmove.
+    pub explain: bool,
+    /// Whether the GUI may send "go ponder"; searching works the same either way.
+    pub ponder: bool,
+    /// Time kept back from every budget for lag between engine and clock.
+    pub move_overhead: Duration,
+    /// What a "go" without any limits searches for; `None` searches until "stop".
+    pub default_movetime: Option<Duration>,
+    /// Size of the transposition table in megabytes.
+    pub hash_mb: usize,
+    /// Set by the GUI while analysing rather than playing: no contempt and no
+    /// cutting thinking time short. There is no opening book to skip.
+    pub analyse_mode: bool,
+    /// Play at about `elo` instead of full strength.
+    pub limit_strength: bool,
+    pub elo: i64,
+    /// Adds win/draw/loss chances to info lines.
+    pub show_wdl: bool,
+    /// Where `eval_weights` came from, empty for the built-in weights.
+    pub eval_file: String,
+    /// Replaces the PeSTO evaluator's built-in weights.
+    pub eval_weights: Option<Arc<EvalWeights>>,
+}
+
+impl Default for EngineOptions {
+    fn default() -> Self {
+        Self {
+            nickname: "AllRustBot".to_owned(),
+            uci_chess960: false,
+            uci_variant: Variant::Chess,
+            log_level: "off".to_owned(),
+            log_file: String::new(),
+            evaluator: EvaluatorKind::default(),
+            search_features: SearchFeatures::default(),
+            search_mode: SearchMode::default(),
+            contempt: 0,
+            opponent: None,
+            pretty_output: false,
+            json_output: false,
+            explain: false,
+            ponder: false,
+            move_overhead: Duration::from_millis(10),
+            default_movetime: Some(Duration::from_millis(100)),
+            hash_mb: DEFAULT_HASH_MB,
+            analyse_mode: false,
+            limit_strength: false,
+            elo: 1500,
+            show_wdl: false,
+            eval_file: String::new(),
+            eval_weights: None,
+        }
+    }
+}
+
+/// The controller of the latest search. Every search gets its own stop flag,
+/// so a late stop meant for one search can't end the next.
+#[derive(Clone, Default)]
+struct CurrentSearch(Arc<Mutex<Option<SearchController>>>);
+
+impl CurrentSearch {
+    fn replace(&self, controller: SearchController) {
+        *self.0.lock().expect("Current search lock poisoned") = Some(controller);
+    }
+
+    fn stop(&self) {
+        if let Some(controller) = &*self.0.lock().expect("Current search lock poisoned") {
+            controller.stop();
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        self.0
+            .lock()
+            .expect("Current search lock poisoned")
+            .as_ref()
+            .is_some_and(|controller| !controller.is_stopped())
+    }
+}
+
+/// Stops a running search and waits for its bestmove from another thread.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    current_search: CurrentSearch,
+    search: SearchWaiter,
+}
+
+impl ShutdownHandle {
+    /// Aborts the running search, if any, and blocks until it has printed its bestmove.
+    pub fn stop_and_wait(&self) -> Result<(), Error> {
+        self.current_search.stop();
+        self.wait()
+    }
+
+    fn wait(&self) -> Result<(), Error> {
+        self.search.wait()
+    }
+}
+
+/// Holds the engine's state, primarily the current board position.
+pub struct Engine {
+    pos: VariantPosition,
+    /// Moves played since the start position of the last "position" command.
+    history: Vec<Move>,
+    current_search: CurrentSearch,
+    /// A "go ponder" search waiting for "ponderhit", with the time it may
+    /// take from then on and the nodes that stand for that time, if any.
+    pending_ponder: Option<(SearchController, TimeBudget, Option<u64>)>,
+    /// Runs every search, so its tables outlive a single "go".
+    worker: SearchWorker,
+    options: EngineOptions,
+    output: Arc<dyn ResponseWriter>,
+    /// Shared with `output`, which switches to JSON while it is set.
+    json_output: Arc<AtomicBool>,
+    /// Overrides the time policy that would be picked from the "go" parameters.
+    time_manager: Option<Arc<dyn TimeManager>>,
+    /// Set by "debug on": searches add diagnostics as info strings.
+    debug: bool,
+    quit_requested: bool,
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Self::with_options(EngineOptions::default())
+    }
+
+    /// Starts configuring an engine, see [`EngineBuilder`].
+    pub fn builder() -> EngineBuilder {
+        EngineBuilder::default()
+    }
+
+    pub fn with_options(options: EngineOptions) -> Self {
+        Self::with_output(options, Arc::new(Stdio))
+    }
+
+    /// Creates an engine that sends its responses to `output` instead of stdout.
+    pub fn with_output(options: EngineOptions, output: Arc<dyn ResponseWriter>) -> Self {
+        let json_output = Arc::new(AtomicBool::new(options.json_output));
+        let output: Arc<dyn ResponseWriter> =
+            Arc::new(JsonOutput::new(output, Arc::clone(&json_output)));
+        Self {
+            pos: VariantPosition::default(),
+            history: Vec::new(),
+            current_search: CurrentSearch::default(),
+            pending_ponder: None,
+            worker: SearchWorker::spawn(Arc::clone(&output), options.hash_mb),
+            options,
+            output,
+            json_output,
+            time_manager: None,
+            debug: false,
+            quit_requested: false,
+        }
+    }
+
+    /// The position the next "go" will search.
+    pub fn position(&self) -> &VariantPosition {
+        &self.pos
+    }
+
+    /// FEN of the current position.
+    pub fn fen(&self) -> String {
+        Fen::from_position(&self.pos, EnPassantMode::Legal).to_string()
+    }
+
+    pub fn options(&self) -> &EngineOptions {
+        &self.options
+    }
+
+    /// Uses `time_manager` for every search instead of picking one from the "go" parameters.
+    pub fn set_time_manager(&mut self, time_manager: Arc<dyn TimeManager>) {
+        self.time_manager = Some(time_manager);
+    }
+
+    /// Whether a search is currently running.
+    pub fn is_thinking(&self) -> bool {
+        self.current_search.is_running()
+    }
+
+    /// Whether the GUI has sent "quit"; the caller should stop feeding commands.
+    pub fn quit_requested(&self) -> bool {
+        self.quit_requested
+    }
+
+    /// Blocks until the running search (if any) has printed its bestmove.
+    pub fn wait_for_search(&mut self) -> Result<(), Error> {
+        self.shutdown_handle().wait()
+    }
+
+    /// A handle other threads (e.g. a signal handler) can use to end the search cleanly.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle {
+            current_search: self.current_search.clone(),
+            search: self.worker.waiter(),
+        }
+    }
+
+    /// Parses a line from the GUI and acts on it.
+    pub fn handle_command(&mut self, line: &str) {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some((command, arguments)) = split_command(&tokens) else {
+            // The spec says to ignore unknown commands.
+            return;
+        };
+        match command {
+            "position" => self.handle_position(arguments),
+            "go" => self.handle_go(arguments),
+            "isready" => self.handle_isready(),
+            "uci" => self.handle_uci(),
+            "quit" => self.handle_quit(),
+            "stop" => self.handle_stop(),
+            "ponderhit" => self.handle_ponderhit(),
+            "ucinewgame" => self.handle_ucinewgame(),
+            "debug" => self.handle_debug(arguments),
+            "setoption" => self.handle_setoption(arguments),
+            "see" => self.handle_see(arguments),
+            "state" => self.handle_state(),
+            "loadpgn" => self.handle_loadpgn(arguments),
+            "fen" | "getfen" => self.handle_fen(),
+            "flip" | "mirror" => self.handle_flip(),
+            "swapturn" => self.handle_swap_turn(),
+            "selftest" => self.handle_selftest(),
+            "genfens" => self.handle_genfens(arguments),
+            "d" => self.handle_display(),
+            "eval" => self.eval(),
+            "perft" => self.handle_perft(arguments),
+            "bench" => self.handle_bench(arguments),
+            // Known to the protocol but not supported yet.
+            _ => {}
+        }
+    }
+
+    /// Changes an option, as the GUI would with "setoption name <name> value <value>".
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        let option = options::find(name).ok_or_else(|| Error::UnknownOption(name.to_owned()))?;
+        option
+            .set(self, value)
+            .map_err(|reason| Error::InvalidOptionValue {
+                name: name.to_owned(),
+                value: value.to_owned(),
+                reason,
+            })
+    }
+
+    /// Sets up the board from a FEN (or the start position if `None`) and a series of UCI moves.
+    /// The current position is kept if anything is invalid.
+    pub fn set_position(&mut self, fen: Option<&str>, moves: &[&str]) -> Result<(), Error> {
+        let mut position = self.start_position(fen)?;
+        let mut history = Vec::new();
+        play_moves(&mut position, moves, &mut history)?;
+        self.pos = position;
+        self.history = history;
+        Ok(())
+    }
+
+    /// Sets up the position of a PGN game, taking its FEN tag into account.
+    /// With `until_move`, stops before that move number, white to move.
+    /// The current position is kept if a move is illegal.
+    pub fn set_position_from_pgn(
+        &mut self,
+        pgn: &str,
+        until_move: Option<u32>,
+    ) -> Result<(), Error> {
+        let game = PgnGame::parse(pgn);
+        let mut position = self.start_position(game.tag("FEN"))?;
+        let mut history = Vec::new();
+        for san in &game.moves {
+            if until_move.is_some_and(|until| position.fullmoves().get() >= until) {
+                break;
+            }
+            let m = san
+                .parse::<SanPlus>()
+                .ok()
+                .and_then(|san_plus| san_plus.san.to_move(&position).ok())
+                .ok_or_else(|| Error::IllegalMove(san.clone()))?;
+            position.play_unchecked(m);
+            history.push(m);
+        }
+        self.pos = position;
+        self.history = history;
+        Ok(())
+    }
+
+    /// Handles the "setoption" command to change engine parameters.
+    fn handle_setoption(&mut self, tokens: &[&str]) {
+        // tokens slice starts after "setoption", e.g., ["name", "nick", "value", "new_name"]
+        // Anything before "name" is junk and skipped.
+        let Some(name_idx) = tokens.iter().position(|&s| s == "name") else {
+            return; // Invalid format
+        };
+        let tokens = &tokens[name_idx..];
+
+        // Button options come without a value
+        let value_idx = tokens
+            .iter()
+            .position(|&s| s == "value")
+            .unwrap_or(tokens.len());
+        if value_idx <= 1 {
+            return; // No name between "name" and "value"
+        }
+        let option_name = tokens[1..value_idx].join(" ");
+        let option_value = tokens.get(value_idx + 1..).unwrap_or_default().join(" ");
+
+        if let Err(error) = self.set_option(&option_name, &option_value) {
+            self.report_error(&error);
+        }
+    }
+
+    /// Responds to the "uci" command by identifying the engine and sending supported options.
+    fn handle_uci(&self) {
+        let output = &self.output;
+        output.write_response(&format!("id name {}", self.options.nickname));
+        output.write_response("id author All");
+        for option in options::registry() {
+            output.write_response(&option.declaration(&self.options));
+        }
+        output.write_response("uciok");
+    }
+
+    /// Responds to "isready" to synchronize with the GUI.
+    fn handle_isready(&mut self) {
+        // Report a search that crashed since the last check; a running one doesn't block
+        if self.worker.waiter().take_aborted() {
+            self.report_error(&Error::SearchAborted);
+        }
+        self.output.write_response("readyok");
+    }
+
+    /// Sets up the board based on a FEN string or startpos, and a series of moves.
+    fn handle_position(&mut self, tokens: &[&str]) {
+        // Skip junk in front of the position type
+        let Some(start_idx) = tokens
+            .iter()
+            .position(|&t| t == "startpos" || t == "fen" || t == "pgn")
+        else {
+            return;
+        };
+        let tokens = &tokens[start_idx..];
+        if tokens[0] == "pgn" {
+            if let Err(error) = self.set_position_from_pgn(&tokens[1..].join(" "), None) {
+                self.report_error(&error);
+            }
+            return;
+        }
+        let moves_start_index = tokens.iter().position(|&r| r == "moves");
+
+        let fen = if tokens[0] == "fen" {
+            let fen_end = moves_start_index.unwrap_or(tokens.len());
+            Some(tokens[1..fen_end].join(" "))
+        } else {
+            None
+        };
+        let mut current_pos = match self.start_position(fen.as_deref()) {
+            Ok(position) => position,
+            Err(error) => {
+                self.report_error(&error);
+                return;
+            }
+        };
+
+        let mut history = Vec::new();
+        if let Some(msi) = moves_start_index {
+            // Keep the moves up to the first bad one rather than guessing
+            if let Err(error) = play_moves(&mut current_pos, &tokens[msi + 1..], &mut history) {
+                self.report_error(&error);
+            }
+        }
+
+        self.pos = current_pos;
+        self.history = history;
+    }
+
+    fn start_position(&self, fen: Option<&str>) -> Result<VariantPosition, Error> {
+        let Some(fen) = fen else {
+            return Ok(VariantPosition::new(self.options.uci_variant));
+        };
+        let fen: Fen = fen.parse()?;
+
+        let castle_type = if self.options.uci_chess960 {
+            CastlingMode::Chess960
+        } else {
+            CastlingMode::Standard
+        };
+
+        Ok(VariantPosition::from_setup(
+            self.options.uci_variant,
+            fen.into_setup(),
+            castle_type,
+        )?)
+    }
+
+    /// Tells the GUI about a problem without breaking the protocol.
+    fn report_error(&self, error: &Error) {
+        self.output.write_response(&format!("info string {error}"));
+    }
+
+    /// The evaluator selected through the options, before any noise from
+    /// "UCI_LimitStrength". Weights from "EvalFile" only replace PeSTO's.
+    fn evaluator(&self) -> Arc<dyn Evaluator> {
+        match (&self.options.eval_weights, self.options.evaluator) {
+            (Some(weights), EvaluatorKind::Pesto) => Arc::clone(weights) as Arc<dyn Evaluator>,
+            (_, kind) => kind.evaluator(),
+        }
+    }
+
+    /// Starts calculating the best move for the current position.
+    fn handle_go(&mut self, tokens: &[&str]) {
+        if self.is_thinking() {
+            // Ignore 'go' if already thinking, as per UCI spec.
+            return;
+        }
+
+        let mut limits = parse_go(tokens, &self.pos);
+        if limits.is_unbounded() {
+            match self.options.default_movetime {
+                Some(movetime) => limits.movetime = Some(movetime),
+                None => limits.infinite = true,
+            }
+        }
+
+        // Clone necessary state for the thinking thread
+        let position_to_search = self.pos.clone();
+        let mut evaluator = self.evaluator();
+        if self.options.limit_strength {
+            let node_limit = strength::node_limit(self.options.elo);
+            limits.nodes = Some(
+                limits
+                    .nodes
+                    .map_or(node_limit, |nodes| nodes.min(node_limit)),
+            );
+            let seed = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_nanos() as u64);
+            evaluator = Arc::new(NoisyEvaluator::new(
+                evaluator,
+                strength::noise_amplitude(self.options.elo),
+                seed,
+            ));
+        }
+        let analysing = self.options.analyse_mode;
+        // An analysis should show the objective score, not the engine's taste for draws
+        let contempt = if analysing {
+            0
+        } else {
+            self.options.contempt + self.options.opponent.as_ref().map_or(0, Opponent::contempt)
+        };
+        let features = SearchFeatures {
+            contempt,
+            ..self.options.search_features
+        };
+        let search_mode = self.options.search_mode;
+
+        let time_manager = self
+            .time_manager
+            .clone()
+            .unwrap_or_else(|| time_manager::select(&limits, !analysing));
+        let clock = ClockState::new(&limits, &position_to_search);
+        if clock.is_critical() && !limits.ponder && !limits.infinite && !analysing {
+            tracing::warn!(
+                remaining_ms = clock.time_left.map(|time| time.as_millis() as u64),
+                "clock critically low, playing the first move found"
+            );
+            limits.depth = Some(1);
+        }
+        // Without a clock, a depth, node or mate limit searches as long as reaching it takes
+        let budget = if limits.infinite || !limits.is_timed() {
+            TimeBudget::UNLIMITED
+        } else {
+            let time_percent = self
+                .options
+                .opponent
+                .as_ref()
+                .map_or(100, Opponent::time_percent);
+            time_manager
+                .allocate(&clock)
+                .scale_soft(time_percent)
+                .minus_overhead(self.options.move_overhead)
+        };
+        tracing::info!(
+            remaining_ms = clock.time_left.map(|time| time.as_millis() as u64),
+            soft_ms = budget.soft.as_millis() as u64,
+            hard_ms = budget.hard.as_millis() as u64,
+            "allocated think time"
+        );
+
+        let mut controller = SearchController::new(Arc::new(AtomicBool::new(true)))
+            .with_limits(&limits)
+            .with_pondering(limits.ponder);
+        // A ponder search starts its clock at "ponderhit"
+        self.pending_ponder = None;
+        if limits.ponder {
+            let node_limit = time_manager.node_limit(&budget);
+            self.pending_ponder = Some((controller.clone(), budget, node_limit));
+        } else if let Some(nodes) = time_manager.node_limit(&budget) {
+            // The wall clock must not cut the search short, only node counts do
+            let nodes = limits.nodes.map_or(nodes, |limit| limit.min(nodes));
+            controller = controller.with_node_budget(nodes);
+        } else if let Some(deadline) = deadline(budget) {
+            controller = controller.with_deadline(deadline);
+        }
+        self.current_search.replace(controller.clone());
+
+        self.worker.search(SearchJob {
+            position: position_to_search,
+            limits,
+            evaluator,
+            features,
+            mode: search_mode,
+            controller,
+            time_manager,
+            budget,
+            pretty_output: self.options.pretty_output,
+            show_wdl: self.options.show_wdl,
+            debug: self.debug,
+            explain: self.options.explain,
+            crash_context: CrashContext {
+                fen: self.fen(),
+                moves: self
+                    .history
+                    .iter()
+                    .map(|m| m.to_uci(CastlingMode::Standard).to_string())
+                    .collect(),
+                options: options::registry()
+                    .iter()
+                    .map(|option| (option.name, option.value(&self.options)))
+                    .collect(),
+            },
+        });
+    }
+
+    /// Prints the static exchange evaluation of a move in the current position.
+    fn handle_see(&self, tokens: &[&str]) {
+        let Some(&move_str) = tokens.first() else {
+            self.output.write_response("info string usage: see <move>");
+            return;
+        };
+        let Some(m) = move_str
+            .parse::<UciMove>()
+            .ok()
+            .and_then(|uci_move| uci_move.to_move(&self.pos).ok())
+        else {
+            self.report_error(&Error::IllegalMove(move_str.to_owned()));
+            return;
+        };
+
+        let exchange = see::static_exchange(&self.pos, &m);
+        self.output
+            .write_response(&format!("SEE of {move_str} on {}", exchange.target));
+        for (index, step) in exchange.steps.iter().enumerate() {
+            self.output.write_response(&format!(
+                "  {}. {} from {}  balance {:+}",
+                index + 1,
+                step.piece.char(),
+                step.from,
+                step.balance
+            ));
+        }
+        self.output
+            .write_response(&format!("Gain: {:+}", exchange.gain));
+    }
+
+    /// Handles "loadpgn <file> [move]", setting up the game in a PGN file.
+    fn handle_loadpgn(&mut self, tokens: &[&str]) {
+        // A trailing number is the move to stop at, the rest is the path
+        let (path, until_move) = match tokens.split_last() {
+            Some((last, rest)) if !rest.is_empty() && last.parse::<u32>().is_ok() => {
+                (rest.join(" "), last.parse().ok())
+            }
+            _ => (tokens.join(" "), None),
+        };
+        if path.is_empty() {
+            self.output
+                .write_response("info string usage: loadpgn <file> [move]");
+            return;
+        }
+
+        let result = fs::read_to_string(&path)
+            .map_err(Error::from)
+            .and_then(|pgn| self.set_position_from_pgn(&pgn, until_move));
+        if let Err(error) = result {
+            self.report_error(&error);
+        }
+    }
+
+    /// Prints the FEN of the current position.
+    fn handle_fen(&self) {
+        self.output.write_response(&self.fen());
+    }
+
+    /// Draws the board, followed by the FEN, Zobrist key and side to move.
+    fn handle_display(&self) {
+        for line in board_diagram(&self.pos) {
+            self.output.write_response(&line);
+        }
+        self.output.write_response(&format!("Fen: {}", self.fen()));
+        let key = self.pos.zobrist_hash::<Zobrist64>(EnPassantMode::Legal);
+        self.output.write_response(&format!("Key: {:016X}", key.0));
+        self.output
+            .write_response(&format!("Side to move: {}", self.pos.turn()));
+    }
+
+    /// Prints the static evaluation of the current position term by term.
+    pub fn eval(&self) {
+        let trace = match (&self.options.eval_weights, self.options.evaluator) {
+            (Some(weights), EvaluatorKind::Pesto) => weights.trace(&self.pos),
+            _ => eval::eval_trace(&self.pos),
+        };
+        let score = self.evaluator().evaluate(&self.pos);
+        for line in eval_trace_lines(&self.pos, &trace, self.options.evaluator, score) {
+            self.output.write_response(&line);
+        }
+    }
+
+    /// Mirrors the board vertically with the colors swapped. The evaluation
+    /// should not change, which makes asymmetries easy to spot.
+    fn handle_flip(&mut self) {
+        let mut setup = self.pos.to_setup(EnPassantMode::Legal);
+        setup.mirror();
+        let mode = self.pos.castles().mode();
+        match VariantPosition::from_setup(self.options.uci_variant, setup, mode) {
+            Ok(position) => {
+                self.pos = position;
+                // The moves no longer lead to this position
+                self.history.clear();
+            }
+            Err(error) => self.report_error(&error.into()),
+        }
+    }
+
+    /// Passes the move to the other side, e.g. to see what the opponent threatens.
+    fn handle_swap_turn(&mut self) {
+        match self.pos.clone().swap_turn() {
+            Ok(position) => {
+                self.pos = position;
+                self.history.clear();
+            }
+            Err(error) => self.report_error(&error.into()),
+        }
+    }
+
+    /// Runs the built-in consistency checks and prints one line per check.
+    fn handle_selftest(&self) {
+        let results = selftest::run();
+        for result in &results {
+            match &result.failure {
+                None => self.output.write_response(&format!("PASS {}", result.name)),
+                Some(failure) => self
+                    .output
+                    .write_response(&format!("FAIL {}: {failure}", result.name)),
+            }
+        }
+        let passed = results.iter().filter(|result| result.passed()).count();
+        self.output
+            .write_response(&format!("{passed}/{} checks passed", results.len()));
+    }
+
+    /// Prints random balanced openings for distributed testing, as
+    /// "genfens <count> seed <seed> book <path|none>".
+    fn handle_genfens(&self, tokens: &[&str]) {
+        let count = tokens.first().and_then(|count| count.parse::<usize>().ok());
+        let seed = value_after(tokens, "seed").and_then(|seed| seed.parse::<u64>().ok());
+        let (Some(count), Some(seed)) = (count, seed) else {
+            self.output.write_response(
+                "info string usage: genfens <count> seed <seed> [book <path|none>]",
+            );
+            return;
+        };
+
+        let book = match value_after(tokens, "book") {
+            Some(path) if !path.eq_ignore_ascii_case("none") => match fs::read_to_string(path) {
+                Ok(text) => genfens::parse_book(&text),
+                Err(error) => return self.report_error(&error.into()),
+            },
+            _ => Vec::new(),
+        };
+        for position in genfens::generate(count, seed, &book) {
+            let fen = Fen::from_position(&position, EnPassantMode::Legal);
+            self.output
+                .write_response(&format!("info string genfens {fen}"));
+        }
+    }
+
+    /// Handles "perft <depth>".
+    fn handle_perft(&self, tokens: &[&str]) {
+        match tokens.first().and_then(|depth| depth.parse::<u32>().ok()) {
+            Some(depth) => self.perft(depth),
+            None => self
+                .output
+                .write_response("info string usage: perft <depth>"),
+        }
+    }
+
+    /// Counts the leaves `depth` plies below the current position, per root move
+    /// and in total, for comparing move generation with other engines.
+    pub fn perft(&self, depth: u32) {
+        let start = Instant::now();
+        let divide = perft_divide(&self.pos, depth);
+        for (m, nodes) in &divide {
+            self.output
+                .write_response(&format!("{}: {nodes}", m.to_uci(CastlingMode::Standard)));
+        }
+        let nodes = match depth {
+            0 => 1,
+            _ => divide.iter().map(|(_, nodes)| nodes).sum(),
+        };
+        let elapsed = start.elapsed();
+        self.output.write_response("");
+        self.output
+            .write_response(&format!("Nodes searched: {nodes}"));
+        self.output.write_response(&format!(
+            "Time: {}ms, {} nodes per second",
+            elapsed.as_millis(),
+            nodes as u128 * 1000 / elapsed.as_millis().max(1)
+        ));
+    }
+
+    /// Searches the bench positions and prints the totals the way OpenBench reads them.
+    fn handle_bench(&self, tokens: &[&str]) {
+        let depth = tokens
+            .first()
+            .and_then(|depth| depth.parse::<u64>().ok())
+            .unwrap_or(bench::BENCH_DEPTH);
+        match bench::run(depth) {
+            Ok(result) => {
+                self.output
+                    .write_response(&format!("{} nodes {} nps", result.nodes, result.nps()))
+            }
+            Err(error) => self.report_error(&error),
+        }
+    }
+
+    /// Prints a snapshot of the engine's state, meant to be pasted into bug reports.
+    fn handle_state(&self) {
+        let output = &self.output;
+        output.write_response(&format!("fen: {}", self.fen()));
+        output.write_response(&format!("side to move: {}", self.pos.turn()));
+        output.write_response(&format!("moves played: {}", self.history.len()));
+        output.write_response(&format!(
+            "searching: {}",
+            if self.is_thinking() { "yes" } else { "no" }
+        ));
+        output.write_response(&format!("hash entries: {}", self.worker.table_entries()));
+        for option in options::registry() {
+            output.write_response(&format!(
+                "option {}: {}",
+                option.name,
+                option.value(&self.options)
+            ));
+        }
+    }
+
+    /// Handles "debug on" and "debug off".
+    fn handle_debug(&mut self, tokens: &[&str]) {
+        match tokens.first() {
+            Some(&"on") => self.debug = true,
+            Some(&"off") => self.debug = false,
+            _ => {}
+        }
+    }
+
+    /// Prepares the engine for a new game.
+    fn handle_ucinewgame(&mut self) {
+        self.pos = VariantPosition::new(self.options.uci_variant);
+        self.history.clear();
+        self.worker.new_game();
+        match &self.options.opponent {
+            Some(opponent) => tracing::info!(%opponent, "new game"),
+            None => tracing::info!("new game"),
+        }
+    }
+
+    /// Handles the "stop" command.
+    fn handle_stop(&self) {
+        self.current_search.stop();
+    }
+
+    /// Handles the "ponderhit" command: the opponent played the expected move,
+    /// so the pondering search goes on as a normal one on our own clock.
+    fn handle_ponderhit(&mut self) {
+        match self.pending_ponder.take() {
+            // Like in "go", only node counts may cut the search short
+            Some((controller, _, Some(nodes))) => controller.ponderhit(None, Some(nodes)),
+            Some((controller, budget, None)) => controller.ponderhit(deadline(budget), None),
+            None => {}
+        }
+    }
+
+    /// Handles the "quit" command.
+    fn handle_quit(&mut self) {
+        self.current_search.stop();
+        self.quit_requested = true;
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How long a search runs before root moves are reported as "currmove".
+const CURRMOVE_DELAY: Duration = Duration::from_secs(1);
+/// Least time between two info lines, except that every finished iteration is
+/// reported straight away.
+const INFO_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Reports every iteration, as info lines or as a table for people to read.
+struct InfoPrinter {
+    output: Arc<dyn ResponseWriter>,
+    /// Set when printing the table instead of info lines.
+    table: Option<PrettyTable>,
+    show_wdl: bool,
+    start: Instant,
+    /// When the last line was written, for keeping to [`INFO_INTERVAL`].
+    last_line: Instant,
+}
+
+/// State of the table printed by [`InfoPrinter`] in pretty mode.
+struct PrettyTable {
+    /// Root of the search, for writing the principal variation in SAN.
+    root: VariantPosition,
+    header_printed: bool,
+}
+
+impl InfoPrinter {
+    fn new(
+        output: Arc<dyn ResponseWriter>,
+        pretty_root: Option<VariantPosition>,
+        show_wdl: bool,
+    ) -> Self {
+        let start = Instant::now();
+        Self {
+            output,
+            show_wdl,
+            start,
+            last_line: start,
+            table: pretty_root.map(|root| PrettyTable {
+                root,
+                header_printed: false,
+            }),
+        }
+    }
+}
+
+impl SearchObserver for InfoPrinter {
+    fn on_iteration(&mut self, info: &IterationInfo) {
+        self.last_line = Instant::now();
+        match &mut self.table {
+            None => {
+                self.output
+                    .write_response(&info_line(info, self.start.elapsed(), self.show_wdl))
+            }
+            Some(table) => {
+                if !std::mem::replace(&mut table.header_printed, true) {
+                    self.output.write_response(&format!(
+                        "{:>5} {:>8} {:>9} {:>12}  pv",
+                        "depth", "score", "time", "nodes"
+                    ));
+                }
+                self.output
+                    .write_response(&pretty_row(&table.root, info, self.start.elapsed()));
+            }
+        }
+    }
+
+    fn on_root_move(&mut self, depth: u64, root_move: Move, number: usize) {
+        // Short searches finish too quickly for anyone to wonder what they are doing
+        if self.table.is_none()
+            && self.start.elapsed() >= CURRMOVE_DELAY
+            && self.last_line.elapsed() >= INFO_INTERVAL
+        {
+            self.last_line = Instant::now();
+            self.output.write_response(&format!(
+                "info depth {depth} currmove {} currmovenumber {number}",
+                root_move.to_uci(CastlingMode::Standard)
+            ));
+        }
+    }
+}
+
+/// The info line sent over UCI for an iteration finished `elapsed` into the
+/// search, ending with the principal variation.
+fn info_line(info: &IterationInfo, elapsed: Duration, show_wdl: bool) -> String {
+    let wdl = if show_wdl {
+        let (win, draw, loss) = wdl(info.score);
+        format!(" wdl {win} {draw} {loss}")
+    } else {
+        String::new()
+    };
+    let pv: Vec<String> = info
+        .pv
+        .iter()
+        .map(|m| m.to_uci(CastlingMode::Standard).to_string())
+        .collect();
+    let bound = match info.bound {
+        ScoreBound::Exact => "",
+        ScoreBound::Lower => " lowerbound",
+        ScoreBound::Upper => " upperbound",
+    };
+    let nps = info.nodes as u128 * 1000 / elapsed.as_millis().max(1);
+    let hashfull = info
+        .hashfull
+        .map(|permille| format!(" hashfull {permille}"))
+        .unwrap_or_default();
+    format!(
+        "info depth {} seldepth {} score {}{bound}{wdl} nodes {} nps {nps}{hashfull} time {} pv {}",
+        info.depth,
+        info.seldepth,
+        uci_score(info.score),
+        info.nodes,
+        elapsed.as_millis(),
+        pv.join(" ")
+    )
+}
+
+/// The static evaluation as a table: every term for both colors in the
+/// middlegame and endgame, blended by game phase into a total for White.
+fn eval_trace_lines(
+    position: &VariantPosition,
+    trace: &EvalTrace,
+    evaluator: EvaluatorKind,
+    score: i64,
+) -> Vec<String> {
+    let pawns = |centipawns: i64| format!("{:+.2}", centipawns as f64 / 100.0);
+    let mut lines = vec![
+        format!(
+            "{:>22} | {:>6} {:>6} | {:>6} {:>6} | {:>6}",
+            "term", "W mg", "W eg", "B mg", "B eg", "total"
+        ),
+        "-".repeat(56),
+    ];
+    let mut total = 0;
+    for (name, term) in EVAL_TERMS.iter().zip(&trace.terms) {
+        let blended = term.blend(Color::White, trace.phase);
+        total += blended;
+        lines.push(format!(
+            "{name:>22} | {:>6} {:>6} | {:>6} {:>6} | {:>6}",
+            pawns(term.mg[Color::White as usize]),
+            pawns(term.eg[Color::White as usize]),
+            pawns(term.mg[Color::Black as usize]),
+            pawns(term.eg[Color::Black as usize]),
+            pawns(blended),
+        ));
+    }
+    lines.push("-".repeat(56));
+    lines.push(format!(
+        "game phase {}/{MAX_GAME_PHASE} (middlegame share)",
+        trace.phase
+    ));
+    lines.push(format!("sum of terms: {} (White)", pawns(total)));
+    let white_score = if position.turn() == Color::White {
+        score
+    } else {
+        -score
+    };
+    let white_score = match mate_in_moves(score) {
+        Some(_) => uci_score(white_score),
+        None => pawns(white_score),
+    };
+    lines.push(match evaluator {
+        EvaluatorKind::Pesto => {
+            format!("final evaluation: {white_score} (White), after scaling drawish positions")
+        }
+        // The terms only break down PeSTO's score
+        _ => format!(
+            "final evaluation: {white_score} (White) by the {} evaluator, not from the terms above",
+            evaluator.name()
+        ),
+    });
+    lines
+}
+
+/// Leaves `depth` plies below `position` for every legal move, none at depth 0.
+fn perft_divide(position: &VariantPosition, depth: u32) -> Vec<(Move, u64)> {
+    if depth == 0 {
+        return Vec::new();
+    }
+    position
+        .legal_moves()
+        .into_iter()
+        .map(|m| {
+            let mut child = position.clone();
+            child.play_unchecked(m);
+            (m, perft(&child, depth - 1))
+        })
+        .collect()
+}
+
+/// The board as ASCII art, White at the bottom, uppercase for White's pieces.
+fn board_diagram(position: &VariantPosition) -> Vec<String> {
+    let separator = " +---+---+---+---+---+---+---+---+".to_owned();
+    let mut lines = vec![separator.clone()];
+    for rank in Rank::ALL.into_iter().rev() {
+        let squares: String = File::ALL
+            .into_iter()
+            .map(|file| {
+                let piece = position.board().piece_at(Square::from_coords(file, rank));
+                format!(" {} |", piece.map_or(' ', |piece| piece.char()))
+            })
+            .collect();
+        lines.push(format!(" |{squares} {}", rank.char()));
+        lines.push(separator.clone());
+    }
+    lines.push("   a   b   c   d   e   f   g   h".to_owned());
+    lines
+}
+
+/// When a search with `budget` starting now has to end, `None` if never.
+fn deadline(budget: TimeBudget) -> Option<Instant> {
+    Instant::now().checked_add(budget.hard)
+}
+
+/// A score as sent over UCI: "mate N" in moves for forced mates, "cp N" otherwise.
+fn uci_score(score: i64) -> String {
+    match mate_in_moves(score) {
+        Some(moves) => format!("mate {moves}"),
+        None => format!("cp {score}"),
+    }
+}
+
+/// One line of the pretty table: depth, score in pawns, time, nodes and the PV in SAN.
+fn pretty_row(root: &VariantPosition, info: &IterationInfo, elapsed: Duration) -> String {
+    let score = match mate_in_moves(info.score) {
+        Some(moves) => format!("#{moves}"),
+        None => format!("{:+.2}", info.score as f64 / 100.0),
+    };
+    let mut position = root.clone();
+    let pv: Vec<String> = info
+        .pv
+        .iter()
+        .map(|&m| SanPlus::from_move_and_play_unchecked(&mut position, m).to_string())
+        .collect();
+    format!(
+        "{:>5} {score:>8} {:>8.2}s {:>12}  {}",
+        info.depth,
+        elapsed.as_secs_f64(),
+        info.nodes,
+        pv.join(" ")
+    )
+}
+
+/// The token following `key`, for "key value" style arguments.
+fn value_after<'a>(tokens: &[&'a str], key: &str) -> Option<&'a str> {
+    let position = tokens.iter().position(|&token| token == key)?;
+    tokens.get(position + 1).copied()
+}
+
+/// Reads the limits of a "go" command. Unknown tokens and unparsable values are skipped.
+fn parse_go(tokens: &[&str], position: &VariantPosition) -> SearchLimits {
+    let mut limits = SearchLimits::default();
+    let number = |i: usize| {
+        tokens
+            .get(i + 1)
+            .and_then(|value| value.parse::<u64>().ok())
+    };
+    let millis = |i: usize| number(i).map(Duration::from_millis);
+
+    let mut i = 0;
+    while i < tokens.len() {
+        match tokens[i] {
+            "depth" => limits.depth = number(i),
+            "nodes" => limits.nodes = number(i),
+            "mate" => limits.mate = number(i),
+            "movestogo" => limits.movestogo = number(i),
+            "nodestime" => limits.nodestime = number(i),
+            "movetime" => limits.movetime = millis(i),
+            "wtime" => limits.wtime = millis(i),
+            "btime" => limits.btime = millis(i),
+            "winc" => limits.winc = millis(i),
+            "binc" => limits.binc = millis(i),
+            // Flags without a value
+            "infinite" | "ponder" => {
+                if tokens[i] == "infinite" {
+                    limits.infinite = true;
+                } else {
+                    limits.ponder = true;
+                }
+                i += 1;
+                continue;
+            }
+            "searchmoves" => {
+                // The move list runs until the first token that isn't a legal move
+                while let Some(m) = tokens
+                    .get(i + 1)
+                    .and_then(|token| token.parse::<UciMove>().ok())
+                    .and_then(|uci_move| uci_move.to_move(position).ok())
+                {
+                    limits.search_moves.push(m);
+                    i += 1;
+                }
+                i += 1;
+                continue;
+            }
+            _ => {
+                i += 1;
+                continue;
+            }
+        }
+        // Skip the value, or just the keyword if the value is missing
+        i += if tokens.get(i + 1).is_some() { 2 } else { 1 };
+    }
+
+    limits
+}
+
+/// Plays `moves` on `position`, stopping at the first one that isn't legal.
+/// Every move played is appended to `history`.
+fn play_moves(
+    position: &mut VariantPosition,
+    moves: &[&str],
+    history: &mut Vec<Move>,
+) -> Result<(), Error> {
+    for move_str in moves {
+        let m = move_str
+            .parse::<UciMove>()
+            .ok()
+            .and_then(|uci_move| uci_move.to_move(position).ok())
+            .ok_or_else(|| Error::IllegalMove((*move_str).to_owned()))?;
+        position.play_unchecked(m);
+        history.push(m);
+    }
+    Ok(())
+}
+
+/// Parses the value of a UCI check option.
+fn parse_check(value: &str) -> Option<bool> {
+    if value.eq_ignore_ascii_case("true") {
+        Some(true)
+    } else if value.eq_ignore_ascii_case("false") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Finds the first known command word and returns it with the tokens following it.
+/// Leading unknown tokens are skipped, so "joho debug on" is read as "debug on".
+fn split_command<'a, 'b>(tokens: &'a [&'b str]) -> Option<(&'b str, &'a [&'b str])> {
+    let command_idx = tokens
+        .iter()
+        .position(|token| UCI_COMMANDS.contains(token) || EXTRA_COMMANDS.contains(token))?;
+    Some((tokens[command_idx], &tokens[command_idx + 1..]))
+}
+
+#[cfg(test)]
+mod test {
+    use shakmaty::Chess;
+
+    use super::*;
+    use crate::eval::MATE_SCORE;
+
+    #[test]
+    fn test_split_command_skips_junk() {
+        let tokens = ["joho", "debug", "on"];
+        assert_eq!(split_command(&tokens), Some(("debug", &tokens[2..])));
+        assert_eq!(split_command(&["hello", "world"]), None);
+    }
+
+    #[test]
+    fn test_position_stops_at_invalid_move() {
+        let mut engine = Engine::new();
+        engine.handle_command("junk position xyz startpos moves e2e4 e7e5 nonsense d2d4");
+        let expected: Chess = "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2"
+            .parse::<Fen>()
+            .unwrap()
+            .into_position(CastlingMode::Standard)
+            .unwrap();
+        assert_eq!(engine.position().board(), expected.board());
+        assert_eq!(engine.position().turn(), expected.turn());
+        assert_eq!(engine.history.len(), 2);
+    }
+
+    #[test]
+    fn test_swap_turn() {
+        let mut engine = Engine::new();
+        engine.handle_command("position startpos moves e2e4");
+        engine.handle_command("swapturn");
+        assert_eq!(
+            engine.fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1"
+        );
+        assert!(engine.history.is_empty());
+
+        // Passing would leave the side that just moved in check
+        engine.handle_command("position fen 4k3/8/8/8/8/8/8/4RK2 b - - 0 1");
+        engine.handle_command("swapturn");
+        assert_eq!(engine.position().turn(), shakmaty::Color::Black);
+    }
+
+    #[test]
+    fn test_flip() {
+        let mut engine = Engine::new();
+        engine.handle_command("position startpos moves e2e4");
+        let score = eval::evaluate(engine.position());
+        engine.handle_command("flip");
+        assert_eq!(
+            engine.fen(),
+            "rnbqkbnr/pppp1ppp/8/4p3/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+        );
+        assert!(engine.history.is_empty());
+        assert_eq!(eval::evaluate(engine.position()), score);
+
+        engine.handle_command("position fen r3k2r/8/8/8/8/8/8/4K2R w Kkq - 3 20");
+        engine.handle_command("flip");
+        assert_eq!(engine.fen(), "4k2r/8/8/8/8/8/8/R3K2R b KQk - 3 20");
+        engine.handle_command("mirror");
+        engine.handle_command("getfen");
+        assert_eq!(engine.fen(), "r3k2r/8/8/8/8/8/8/4K2R w Kkq - 3 20");
+    }
+
+    #[test]
+    fn test_set_option_errors() {
+        let mut engine = Engine::new();
+        assert!(matches!(
+            engine.set_option("UCI_Chess960", "maybe"),
+            Err(Error::InvalidOptionValue { .. })
+        ));
+        assert!(matches!(
+            engine.set_option("Evaluator", "NNUE"),
+            Err(Error::InvalidOptionValue { .. })
+        ));
+        engine.set_option("Evaluator", "material").unwrap();
+        assert_eq!(engine.options().evaluator, EvaluatorKind::Material);
+        engine.set_option("nullmove", "false").unwrap();
+        engine.set_option("SearchMode", "mcts").unwrap();
+        assert_eq!(engine.options().search_mode, SearchMode::Mcts);
+        engine.set_option("UCI_Variant", "kingofthehill").unwrap();
+        assert_eq!(engine.position().variant(), Variant::KingOfTheHill);
+        engine.set_option("UCI_Variant", "chess").unwrap();
+        assert!(!engine.options().search_features.null_move);
+        assert!(matches!(
+            engine.set_option("EvalFile", "/nonexistent/eval.txt"),
+            Err(Error::InvalidOptionValue { .. })
+        ));
+        assert!(engine.options().eval_weights.is_none());
+        assert_eq!(engine.options().eval_file, "");
+        assert!(matches!(
+            engine.set_option("NoSuchOption", "1"),
+            Err(Error::UnknownOption(_))
+        ));
+        assert!(matches!(
+            engine.set_position(None, &["e2e5"]),
+            Err(Error::IllegalMove(_))
+        ));
+        assert_eq!(engine.position().board(), Chess::default().board());
+    }
+
+    #[test]
+    fn test_position_from_pgn() {
+        let mut engine = Engine::new();
+        let pgn = "[Event \"?\"]\n1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 1-0";
+        engine.set_position_from_pgn(pgn, Some(3)).unwrap();
+        assert_eq!(engine.history.len(), 4);
+        assert_eq!(engine.position().fullmoves().get(), 3);
+
+        engine.handle_command(&format!("position pgn {pgn}"));
+        assert_eq!(engine.history.len(), 6);
+        assert!(matches!(
+            engine.set_position_from_pgn("1. e4 e4", None),
+            Err(Error::IllegalMove(_))
+        ));
+        assert_eq!(engine.history.len(), 6);
+    }
+
+    #[test]
+    fn test_parse_go() {
+        let position = VariantPosition::default();
+        let tokens: Vec<&str> = "wtime 1000 btime x depth 5 searchmoves e2e4 d2d5 nodes 100"
+            .split_whitespace()
+            .collect();
+        let limits = parse_go(&tokens, &position);
+        assert!(!limits.infinite);
+        assert_eq!(limits.wtime, Some(Duration::from_millis(1000)));
+        assert_eq!(limits.btime, None);
+        assert_eq!(limits.depth, Some(5));
+        assert_eq!(limits.search_moves.len(), 1);
+        assert_eq!(limits.nodes, Some(100));
+        assert!(limits.is_timed());
+        assert!(!parse_go(&["depth", "12"], &position).is_timed());
+        assert!(parse_go(&["infinite"], &position).infinite);
+
+        let limits = parse_go(&["ponder", "wtime", "500", "infinite"], &position);
+        assert!(limits.ponder && limits.infinite);
+        assert!(!limits.is_unbounded());
+        assert!(parse_go(&[], &position).is_unbounded());
+        assert!(!parse_go(&["nodes", "10"], &position).is_unbounded());
+        assert_eq!(limits.wtime, Some(Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn test_ponderhit_releases_bestmove() {
+        let (sender, responses) = std::sync::mpsc::channel();
+        let mut engine = Engine::with_output(EngineOptions::default(), Arc::new(sender));
+        engine.handle_command("go ponder depth 1");
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(engine.is_thinking());
+
+        engine.handle_command("ponderhit");
+        engine.wait_for_search().unwrap();
+        assert!(!engine.is_thinking());
+        assert!(
+            responses
+                .try_iter()
+                .any(|line| line.starts_with("bestmove "))
+        );
+    }
+
+    #[test]
+    fn test_bare_go_until_stop() {
+        let (sender, responses) = std::sync::mpsc::channel();
+        let mut engine = Engine::with_output(EngineOptions::default(), Arc::new(sender));
+        engine.set_option("DefaultMovetime", "0").unwrap();
+        engine.handle_command("go");
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(engine.is_thinking());
+
+        engine.handle_command("stop");
+        engine.wait_for_search().unwrap();
+        assert!(
+            responses
+                .try_iter()
+                .any(|line| line.starts_with("bestmove "))
+        );
+    }
+
+    #[test]
+    fn test_nodestime_hard_limit() {
+        let (sender, responses) = std::sync::mpsc::channel();
+        let mut engine = Engine::with_output(EngineOptions::default(), Arc::new(sender));
+        // 3.6 seconds of hard limit at one node per millisecond
+        engine.handle_command("go wtime 20000 btime 20000 nodestime 1");
+        engine.wait_for_search().unwrap();
+        let nodes = responses
+            .try_iter()
+            .filter_map(|line| {
+                let tokens: Vec<&str> = line.split_whitespace().collect();
+                let index = tokens.iter().position(|&token| token == "nodes")?;
+                tokens.get(index + 1)?.parse::<u64>().ok()
+            })
+            .max()
+            .unwrap();
+        assert!(nodes <= 3600);
+    }
+
+    #[test]
+    fn test_mate_search_without_clock() {
+        let (sender, responses) = std::sync::mpsc::channel();
+        let mut engine = Engine::with_output(EngineOptions::default(), Arc::new(sender));
+        // The queen needs eleven plies to mate, far more than a moment's thought
+        engine.handle_command("position fen 8/8/8/8/8/2k5/8/K6Q w - - 0 1");
+        engine.handle_command("go mate 6");
+        engine.wait_for_search().unwrap();
+        let mate_depth = responses.try_iter().find_map(|line| {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            let index = tokens.iter().position(|&token| token == "mate")?;
+            let moves = tokens.get(index + 1)?.parse::<i64>().ok()?;
+            (moves > 0).then(|| value_after(&tokens, "depth")?.parse::<u64>().ok())?
+        });
+        assert!(mate_depth.is_some_and(|depth| depth > 7));
+    }
+
+    #[test]
+    fn test_node_limit_without_clock() {
+        let (sender, responses) = std::sync::mpsc::channel();
+        let mut engine = Engine::with_output(EngineOptions::default(), Arc::new(sender));
+        engine.handle_command("go nodes 100000");
+        engine.wait_for_search().unwrap();
+        let nodes = responses
+            .try_iter()
+            .filter_map(|line| {
+                let tokens: Vec<&str> = line.split_whitespace().collect();
+                value_after(&tokens, "nodes")?.parse::<u64>().ok()
+            })
+            .max()
+            .unwrap();
+        // Nodes already under way when the budget runs out still finish
+        assert!((100_000..101_000).contains(&nodes));
+    }
+
+    #[test]
+    fn test_ponderhit_with_nodestime() {
+        let (sender, _responses) = std::sync::mpsc::channel();
+        let mut engine = Engine::with_output(EngineOptions::default(), Arc::new(sender));
+        // As in "go", 360 milliseconds of hard limit at one node per millisecond
+        engine.handle_command("go ponder wtime 2000 btime 2000 nodestime 1");
+        let controller = engine.current_search.0.lock().unwrap().clone().unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        engine.handle_command("ponderhit");
+        engine.wait_for_search().unwrap();
+        let (_, nodes_at_ponderhit) = controller.ponderhit_at().unwrap();
+        assert!(controller.nodes() - nodes_at_ponderhit <= 1000);
+    }
+
+    #[test]
+    fn test_debug_diagnostics() {
+        let (sender, responses) = std::sync::mpsc::channel();
+        let mut engine = Engine::with_output(EngineOptions::default(), Arc::new(sender));
+        let debug_lines = |engine: &mut Engine| {
+            engine.handle_command("go depth 2");
+            engine.wait_for_search().unwrap();
+            responses
+                .try_iter()
+                .filter(|line| line.starts_with("info string debug "))
+                .count()
+        };
+        assert_eq!(debug_lines(&mut engine), 0);
+        engine.handle_command("debug on");
+        assert_eq!(debug_lines(&mut engine), 2);
+        engine.handle_command("debug off");
+        assert_eq!(debug_lines(&mut engine), 0);
+    }
+
+    #[test]
+    fn test_stale_stop_spares_next_search() {
+        let (sender, responses) = std::sync::mpsc::channel();
+        let mut engine = Engine::with_output(EngineOptions::default(), Arc::new(sender));
+        engine.handle_command("go infinite");
+        let old_search = engine.current_search.0.lock().unwrap().clone().unwrap();
+        engine.handle_command("stop");
+        engine.wait_for_search().unwrap();
+
+        engine.handle_command("go infinite");
+        // Whatever is left of the old search stopping itself again
+        old_search.stop();
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(engine.is_thinking());
+
+        engine.handle_command("stop");
+        engine.wait_for_search().unwrap();
+        let best_moves = responses
+            .try_iter()
+            .filter(|line| line.starts_with("bestmove "))
+            .count();
+        assert_eq!(best_moves, 2);
+    }
+
+    #[test]
+    fn test_go_after_crashed_search() {
+        use crate::time_manager::Stability;
+        use std::sync::atomic::Ordering;
+
+        /// Panics the first time it is asked, like a bug in a time policy.
+        struct PanicsOnce(AtomicBool);
+
+        impl TimeManager for PanicsOnce {
+            fn allocate(&self, _clock: &ClockState) -> TimeBudget {
+                TimeBudget::fixed(Duration::from_millis(50))
+            }
+
+            fn should_stop(&self, budget: &TimeBudget, elapsed: Duration, _: &Stability) -> bool {
+                assert!(!self.0.swap(false, Ordering::Relaxed), "injected crash");
+                elapsed >= budget.soft
+            }
+        }
+
+        let (sender, responses) = std::sync::mpsc::channel();
+        let mut engine = Engine::with_output(EngineOptions::default(), Arc::new(sender));
+        engine.set_time_manager(Arc::new(PanicsOnce(AtomicBool::new(true))));
+        engine.handle_command("go movetime 50");
+        assert!(matches!(
+            engine.wait_for_search(),
+            Err(Error::SearchAborted)
+        ));
+        assert!(!engine.is_thinking());
+
+        engine.handle_command("go movetime 50");
+        engine.wait_for_search().unwrap();
+        let best_moves: Vec<String> = responses
+            .try_iter()
+            .filter(|line| line.starts_with("bestmove "))
+            .collect();
+        assert_eq!(best_moves.len(), 2);
+        assert_eq!(best_moves[0], "bestmove 0000");
+    }
+
+    #[test]
+    fn test_pretty_row() {
+        let root = VariantPosition::default();
+        let mut position = root.clone();
+        let mut history = Vec::new();
+        play_moves(&mut position, &["e2e4", "e7e5"], &mut history).unwrap();
+        let pv = history;
+        let info = IterationInfo {
+            depth: 4,
+            seldepth: 6,
+            score: 35,
+            bound: ScoreBound::Exact,
+            nodes: 1234,
+            hashfull: None,
+            best_move: pv[0],
+            pv,
+        };
+        assert_eq!(
+            pretty_row(&root, &info, Duration::from_millis(1500)),
+            "    4    +0.35     1.50s         1234  e4 e5"
+        );
+    }
+
+    #[test]
+    fn test_info_line() {
+        let mut position = VariantPosition::default();
+        let mut history = Vec::new();
+        play_moves(&mut position, &["e2e4", "e7e5", "g1f3"], &mut history).unwrap();
+        let pv = history;
+        let info = IterationInfo {
+            depth: 5,
+            seldepth: 9,
+            score: 200,
+            bound: ScoreBound::Exact,
+            nodes: 4321,
+            hashfull: Some(12),
+            best_move: pv[0],
+            pv,
+        };
+        let elapsed = Duration::from_millis(2);
+        assert_eq!(
+            info_line(&info, elapsed, false),
+            "info depth 5 seldepth 9 score cp 200 nodes 4321 nps 2160500 hashfull 12 time 2 pv e2e4 e7e5 g1f3"
+        );
+        assert_eq!(
+            info_line(&info, elapsed, true),
+            "info depth 5 seldepth 9 score cp 200 wdl 500 495 5 nodes 4321 nps 2160500 hashfull 12 time 2 pv e2e4 e7e5 g1f3"
+        );
+
+        let cut_short = IterationInfo {
+            bound: ScoreBound::Lower,
+            hashfull: None,
+            ..info
+        };
+        assert_eq!(
+            info_line(&cut_short, elapsed, false),
+            "info depth 5 seldepth 9 score cp 200 lowerbound nodes 4321 nps 2160500 time 2 pv e2e4 e7e5 g1f3"
+        );
+    }
+
+    #[test]
+    fn test_currmove_rate_limit() {
+        let (sender, responses) = std::sync::mpsc::channel();
+        let mut printer = InfoPrinter::new(Arc::new(sender), None, false);
+        let root_move = VariantPosition::default().legal_moves()[0];
+        printer.on_root_move(1, root_move, 1);
+        assert_eq!(responses.try_iter().count(), 0);
+
+        // Long into the search, only one of a burst of root moves gets through
+        printer.start -= CURRMOVE_DELAY;
+        printer.last_line -= INFO_INTERVAL;
+        for number in 1..=20 {
+            printer.on_root_move(9, root_move, number);
+        }
+        let lines: Vec<String> = responses.try_iter().collect();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].ends_with("currmovenumber 1"));
+    }
+
+    #[test]
+    fn test_display() {
+        let diagram = board_diagram(&VariantPosition::default());
+        assert_eq!(diagram.len(), 18);
+        assert_eq!(diagram[1], " | r | n | b | q | k | b | n | r | 8");
+        assert_eq!(diagram[9], " |   |   |   |   |   |   |   |   | 4");
+        assert_eq!(diagram[15], " | R | N | B | Q | K | B | N | R | 1");
+
+        let (sender, responses) = std::sync::mpsc::channel();
+        let mut engine = Engine::with_output(EngineOptions::default(), Arc::new(sender));
+        engine.handle_command("d");
+        let lines: Vec<String> = responses.try_iter().collect();
+        // The PolyGlot key of the start position
+        assert!(lines.contains(&"Key: 463B96181691FC9C".to_owned()));
+        assert!(lines.contains(&"Side to move: white".to_owned()));
+    }
+
+    #[test]
+    fn test_eval_trace() {
+        let position = VariantPosition::default();
+        let lines = eval_trace_lines(
+            &position,
+            &eval::eval_trace(&position),
+            EvaluatorKind::Pesto,
+            eval::evaluate(&position),
+        );
+        assert_eq!(lines.len(), EVAL_TERMS.len() + 6);
+        assert_eq!(
+            lines[2],
+            "material and placement | +38.92 +36.75 | +38.92 +36.75 |  +0.00"
+        );
+        assert_eq!(lines[7], "game phase 24/24 (middlegame share)");
+        assert_eq!(
+            lines[9],
+            "final evaluation: +0.00 (White), after scaling drawish positions"
+        );
+
+        // The total comes from the evaluator the search would use
+        let (sender, responses) = std::sync::mpsc::channel();
+        let mut engine = Engine::with_output(EngineOptions::default(), Arc::new(sender));
+        engine.handle_command("setoption name Evaluator value Material");
+        engine.handle_command("position startpos moves e2e4 d7d5 e4d5");
+        engine.handle_command("eval");
+        assert_eq!(
+            responses.try_iter().last().unwrap(),
+            "final evaluation: +0.82 (White) by the Material evaluator, not from the terms above"
+        );
+    }
+
+    #[test]
+    fn test_perft_divide() {
+        let mut engine = Engine::new();
+        engine
+            .set_position(
+                Some("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"),
+                &[],
+            )
+            .unwrap();
+        let divide = perft_divide(engine.position(), 2);
+        assert_eq!(divide.len(), 48);
+        assert_eq!(divide.iter().map(|(_, nodes)| nodes).sum::<u64>(), 2039);
+        assert!(perft_divide(engine.position(), 0).is_empty());
+    }
+
+    #[test]
+    fn test_uci_score() {
+        assert_eq!(uci_score(-35), "cp -35");
+        assert_eq!(uci_score(MATE_SCORE - 1), "mate 1");
+        assert_eq!(uci_score(MATE_SCORE - 5), "mate 3");
+        assert_eq!(uci_score(-(MATE_SCORE - 4)), "mate -2");
+    }
+}
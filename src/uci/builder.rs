@@ -0,0 +1,127 @@
+use std::sync::Arc;
+
+use super::{Engine, EngineOptions};
+use crate::engine::SearchMode;
+use crate::error::Error;
+use crate::eval::{EvalWeights, EvaluatorKind};
+use crate::logging;
+use crate::protocol::{ResponseWriter, Stdio};
+
+/// Sets an engine up in code rather than through "setoption" commands.
+pub struct EngineBuilder {
+    options: EngineOptions,
+    output: Arc<dyn ResponseWriter>,
+}
+
+impl Default for EngineBuilder {
+    fn default() -> Self {
+        Self::new(EngineOptions::default())
+    }
+}
+
+impl EngineBuilder {
+    /// Starts from `options`, answering on stdout.
+    pub fn new(options: EngineOptions) -> Self {
+        Self {
+            options,
+            output: Arc::new(Stdio),
+        }
+    }
+
+    /// Size of the transposition table in megabytes.
+    pub fn with_hash_mb(mut self, hash_mb: usize) -> Self {
+        self.options.hash_mb = hash_mb;
+        self
+    }
+
+    pub fn with_evaluator(mut self, evaluator: EvaluatorKind) -> Self {
+        self.options.evaluator = evaluator;
+        self
+    }
+
+    /// Replaces the built-in PeSTO weights with those in `path`, loaded by [`Self::build`].
+    pub fn with_eval_file(mut self, path: &str) -> Self {
+        self.options.eval_file = path.to_owned();
+        self
+    }
+
+    pub fn with_search_mode(mut self, search_mode: SearchMode) -> Self {
+        self.options.search_mode = search_mode;
+        self
+    }
+
+    /// Tracing filter directives, e.g. "debug" or "chess_bot::engine=trace".
+    pub fn with_log_level(mut self, log_level: &str) -> Self {
+        self.options.log_level = log_level.to_owned();
+        self
+    }
+
+    /// File to append logs to instead of stderr.
+    pub fn with_log_file(mut self, log_file: &str) -> Self {
+        self.options.log_file = log_file.to_owned();
+        self
+    }
+
+    pub fn with_pretty_output(mut self, pretty_output: bool) -> Self {
+        self.options.pretty_output = pretty_output;
+        self
+    }
+
+    pub fn with_json_output(mut self, json_output: bool) -> Self {
+        self.options.json_output = json_output;
+        self
+    }
+
+    /// Sends responses to `output` instead of stdout.
+    pub fn with_output(mut self, output: Arc<dyn ResponseWriter>) -> Self {
+        self.output = output;
+        self
+    }
+
+    /// Loads the eval file and sets up logging, then creates the engine.
+    pub fn build(mut self) -> Result<Engine, Error> {
+        self.options.eval_weights = match self.options.eval_file.as_str() {
+            "" => None,
+            path => Some(Arc::new(EvalWeights::load(path)?)),
+        };
+
+        // Logging is global, so only touch it when asked to
+        let defaults = EngineOptions::default();
+        if self.options.log_level != defaults.log_level || !self.options.log_file.is_empty() {
+            let log = logging::init();
+            log.set_filter(&self.options.log_level).map_err(|reason| {
+                Error::InvalidOptionValue {
+                    name: "LogLevel".to_owned(),
+                    value: self.options.log_level.clone(),
+                    reason,
+                }
+            })?;
+            log.set_file(&self.options.log_file)?;
+        }
+
+        Ok(Engine::with_output(self.options, self.output))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_build() {
+        let engine = Engine::builder()
+            .with_hash_mb(2)
+            .with_evaluator(EvaluatorKind::Material)
+            .with_json_output(true)
+            .build()
+            .unwrap();
+        assert_eq!(engine.options().hash_mb, 2);
+        assert_eq!(engine.options().evaluator, EvaluatorKind::Material);
+        assert!(engine.options().json_output);
+
+        let missing = Engine::builder()
+            .with_eval_file("/nonexistent/eval.txt")
+            .build();
+        assert!(matches!(missing, Err(Error::Io(_))));
+    }
+}
@@ -0,0 +1,115 @@
+use std::{
+    backtrace::Backtrace,
+    cell::{Cell, RefCell},
+    fs, io,
+    panic::{self, PanicHookInfo},
+    path::PathBuf,
+    sync::Once,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// What the search thread was working on, written to the report if it panics.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(super) struct CrashContext {
+    pub fen: String,
+    /// Moves since the start position of the last "position" command, in UCI notation.
+    pub moves: Vec<String>,
+    /// Every option as a (name, value) pair.
+    pub options: Vec<(&'static str, String)>,
+}
+
+thread_local! {
+    static CONTEXT: RefCell<Option<CrashContext>> = const { RefCell::new(None) };
+    static DEPTH: Cell<u64> = const { Cell::new(0) };
+    static LAST_REPORT: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+}
+
+/// Wraps the panic hook so that panics on a thread with a context write a report.
+/// The report is written before unwinding starts, so it also works in
+/// builds with `panic = "abort"`, where the panic can't be caught.
+pub(super) fn install_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if let Some(context) = CONTEXT.with_borrow(Clone::clone) {
+                match write_report(info, &context, DEPTH.get()) {
+                    Ok(path) => {
+                        eprintln!("Crash report written to {}", path.display());
+                        LAST_REPORT.set(Some(path));
+                    }
+                    Err(error) => eprintln!("Failed to write crash report: {error}"),
+                }
+            }
+            previous_hook(info);
+        }));
+    });
+}
+
+/// Sets what this thread is working on; `None` once it's done.
+pub(super) fn set_context(context: Option<CrashContext>) {
+    DEPTH.set(0);
+    CONTEXT.set(context);
+}
+
+/// Remembers the last completed search depth of this thread.
+pub(super) fn record_depth(depth: u64) {
+    DEPTH.set(depth);
+}
+
+/// The report written for the last panic on this thread, if any.
+pub(super) fn take_report_path() -> Option<PathBuf> {
+    LAST_REPORT.take()
+}
+
+fn write_report(info: &PanicHookInfo, context: &CrashContext, depth: u64) -> io::Result<PathBuf> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    // Crashes within the same second each get a report of their own
+    let path = std::env::temp_dir().join(format!(
+        "chess_bot-crash-{}-{:09}-{}.txt",
+        timestamp.as_secs(),
+        timestamp.subsec_nanos(),
+        std::process::id()
+    ));
+    let backtrace = Backtrace::force_capture();
+    fs::write(
+        &path,
+        report_text(&info.to_string(), context, depth, &backtrace.to_string()),
+    )?;
+    Ok(path)
+}
+
+fn report_text(panic: &str, context: &CrashContext, depth: u64, backtrace: &str) -> String {
+    let mut report = format!(
+        "chess_bot {} crash report\n\npanic: {panic}\nfen: {}\nmoves: {}\ndepth: {depth}\n\noptions:\n",
+        env!("CARGO_PKG_VERSION"),
+        context.fen,
+        context.moves.join(" "),
+    );
+    for (name, value) in &context.options {
+        report.push_str(&format!("  {name} = {value}\n"));
+    }
+    report.push_str(&format!("\nbacktrace:\n{backtrace}\n"));
+    report
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_report_text() {
+        let context = CrashContext {
+            fen: "8/8/8/8/8/8/8/K1k5 w - - 0 1".to_owned(),
+            moves: vec!["e2e4".to_owned(), "e7e5".to_owned()],
+            options: vec![("NullMove", "false".to_owned())],
+        };
+        let report = report_text("oops", &context, 7, "0: main");
+        assert!(report.contains("panic: oops\nfen: 8/8/8/8/8/8/8/K1k5 w - - 0 1\n"));
+        assert!(report.contains("moves: e2e4 e7e5\ndepth: 7\n"));
+        assert!(report.contains("  NullMove = false\n"));
+        assert!(report.ends_with("backtrace:\n0: main\n"));
+    }
+}
@@ -0,0 +1,205 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+use crate::protocol::ResponseWriter;
+
+/// Info fields followed by a single number.
+const NUMBER_FIELDS: [&str; 11] = [
+    "depth",
+    "seldepth",
+    "multipv",
+    "nodes",
+    "nps",
+    "hashfull",
+    "tbhits",
+    "sbhits",
+    "time",
+    "currmovenumber",
+    "cpuload",
+];
+/// Info fields taking the moves up to the end of the line.
+const MOVE_LIST_FIELDS: [&str; 3] = ["pv", "refutation", "currline"];
+
+/// Passes responses on unchanged, or as one JSON object per line while
+/// `enabled` is set, for tools that would rather not parse UCI.
+pub(super) struct JsonOutput {
+    inner: Arc<dyn ResponseWriter>,
+    enabled: Arc<AtomicBool>,
+}
+
+impl JsonOutput {
+    pub fn new(inner: Arc<dyn ResponseWriter>, enabled: Arc<AtomicBool>) -> Self {
+        Self { inner, enabled }
+    }
+}
+
+impl ResponseWriter for JsonOutput {
+    fn write_response(&self, line: &str) {
+        if self.enabled.load(Ordering::Relaxed) {
+            self.inner.write_response(&to_json(line));
+        } else {
+            self.inner.write_response(line);
+        }
+    }
+}
+
+/// Turns a response into a JSON object whose `type` is the UCI keyword, e.g.
+/// `{"type":"bestmove","bestmove":"e2e4","ponder":"e7e5"}`. Lines that aren't
+/// UCI, like the board diagram, become `{"type":"text","text":...}`.
+pub(super) fn to_json(line: &str) -> String {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    let mut fields = Vec::new();
+    match tokens.as_slice() {
+        ["info", rest @ ..] => {
+            fields.push(("type", string("info")));
+            info_fields(rest, &mut fields);
+        }
+        ["bestmove", best_move, rest @ ..] => {
+            fields.push(("type", string("bestmove")));
+            fields.push(("bestmove", string(best_move)));
+            if let ["ponder", ponder] = rest {
+                fields.push(("ponder", string(ponder)));
+            }
+        }
+        ["id", key, rest @ ..] => {
+            fields.push(("type", string("id")));
+            fields.push((key, string(&rest.join(" "))));
+        }
+        [
+            keyword @ ("uciok" | "readyok" | "option" | "copyprotection" | "registration"),
+            rest @ ..,
+        ] => {
+            fields.push(("type", string(keyword)));
+            if !rest.is_empty() {
+                fields.push(("text", string(&rest.join(" "))));
+            }
+        }
+        _ => {
+            fields.push(("type", string("text")));
+            fields.push(("text", string(line)));
+        }
+    }
+    object(&fields)
+}
+
+fn info_fields<'a>(mut tokens: &[&'a str], fields: &mut Vec<(&'a str, String)>) {
+    while let [key, rest @ ..] = tokens {
+        tokens = rest;
+        match *key {
+            "string" => {
+                fields.push(("string", string(&tokens.join(" "))));
+                return;
+            }
+            key if MOVE_LIST_FIELDS.contains(&key) => {
+                let moves: Vec<String> = tokens.iter().map(|m| string(m)).collect();
+                fields.push((key, format!("[{}]", moves.join(","))));
+                return;
+            }
+            "score" => {
+                let mut score = Vec::new();
+                while let [kind @ ("cp" | "mate"), value, rest @ ..] = tokens {
+                    score.push((*kind, number(value)));
+                    tokens = rest;
+                }
+                while let [bound @ ("lowerbound" | "upperbound"), rest @ ..] = tokens {
+                    score.push(("bound", string(&bound[..5])));
+                    tokens = rest;
+                }
+                fields.push(("score", object(&score)));
+            }
+            "wdl" => {
+                let chances: Vec<String> =
+                    tokens.iter().take(3).map(|value| number(value)).collect();
+                tokens = &tokens[chances.len()..];
+                fields.push(("wdl", format!("[{}]", chances.join(","))));
+            }
+            key => {
+                let Some((value, rest)) = tokens.split_first() else {
+                    return;
+                };
+                tokens = rest;
+                let value = if NUMBER_FIELDS.contains(&key) {
+                    number(value)
+                } else {
+                    string(value)
+                };
+                fields.push((key, value));
+            }
+        }
+    }
+}
+
+fn object(fields: &[(&str, String)]) -> String {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|(key, value)| format!("{}:{value}", string(key)))
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+/// A number as sent, or as a string if it isn't one, so the output stays valid JSON.
+fn number(value: &str) -> String {
+    if value.parse::<i64>().is_ok() {
+        value.to_owned()
+    } else {
+        string(value)
+    }
+}
+
+fn string(text: &str) -> String {
+    let mut json = String::with_capacity(text.len() + 2);
+    json.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_to_json() {
+        assert_eq!(
+            to_json(
+                "info depth 5 seldepth 7 score cp -12 lowerbound wdl 40 900 60 nodes 1234 nps 5000 hashfull 3 time 20 pv e2e4 e7e5"
+            ),
+            concat!(
+                r#"{"type":"info","depth":5,"seldepth":7,"score":{"cp":-12,"bound":"lower"},"#,
+                r#""wdl":[40,900,60],"nodes":1234,"nps":5000,"hashfull":3,"time":20,"pv":["e2e4","e7e5"]}"#
+            )
+        );
+        assert_eq!(
+            to_json("info depth 9 currmove g1f3 currmovenumber 2"),
+            r#"{"type":"info","depth":9,"currmove":"g1f3","currmovenumber":2}"#
+        );
+        assert_eq!(
+            to_json("info string hash \"full\""),
+            r#"{"type":"info","string":"hash \"full\""}"#
+        );
+        assert_eq!(
+            to_json("bestmove e2e4 ponder e7e5"),
+            r#"{"type":"bestmove","bestmove":"e2e4","ponder":"e7e5"}"#
+        );
+        assert_eq!(
+            to_json("id name AllRustBot"),
+            r#"{"type":"id","name":"AllRustBot"}"#
+        );
+        assert_eq!(to_json("readyok"), r#"{"type":"readyok"}"#);
+        assert_eq!(
+            to_json(" 8 | r n b"),
+            r#"{"type":"text","text":" 8 | r n b"}"#
+        );
+    }
+}
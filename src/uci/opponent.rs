@@ -0,0 +1,121 @@
+use std::fmt;
+
+/// Rough rating of the engine, the reference point for the rating gap.
+const ENGINE_RATING: i64 = 2200;
+/// Rating points per centipawn of contempt.
+const RATING_PER_CENTIPAWN: i64 = 20;
+/// Contempt derived from the rating gap stays within this many centipawns.
+const MAX_RATING_CONTEMPT: i64 = 50;
+/// Rating points per percent of thinking time saved against a weaker human.
+const RATING_PER_TIME_PERCENT: i64 = 20;
+/// Thinking time saved against weaker humans stays within this many percent.
+const MAX_TIME_SAVED_PERCENT: i64 = 25;
+
+/// Who the engine plays against, as sent by the GUI in "UCI_Opponent", e.g.
+/// "GM 2800 human Garry Kasparov" or "none none computer Some Engine".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Opponent {
+    pub title: Option<String>,
+    pub rating: Option<i64>,
+    pub computer: bool,
+    pub name: String,
+}
+
+impl Opponent {
+    /// Parses "<title> <rating> <computer|human> <name>", where title and
+    /// rating may be "none". The name may contain spaces.
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.splitn(4, ' ');
+        let title = parts.next()?;
+        let rating = parts.next()?;
+        let computer = match parts.next()? {
+            "computer" => true,
+            "human" => false,
+            _ => return None,
+        };
+        let none = |part: &str| part.eq_ignore_ascii_case("none");
+        Some(Self {
+            title: (!none(title)).then(|| title.to_owned()),
+            rating: if none(rating) {
+                None
+            } else {
+                Some(rating.parse().ok()?)
+            },
+            computer,
+            name: parts.next().unwrap_or_default().trim().to_owned(),
+        })
+    }
+
+    /// Extra contempt against this opponent: avoid draws against weaker
+    /// players and accept them against stronger ones.
+    pub fn contempt(&self) -> i64 {
+        self.rating.map_or(0, |rating| {
+            ((ENGINE_RATING - rating) / RATING_PER_CENTIPAWN)
+                .clamp(-MAX_RATING_CONTEMPT, MAX_RATING_CONTEMPT)
+        })
+    }
+
+    /// Percentage of the usual thinking time to spend: weaker humans get
+    /// quicker replies, while computers and stronger players get the full time.
+    pub fn time_percent(&self) -> u32 {
+        let saved = match self.rating {
+            Some(rating) if !self.computer => ((ENGINE_RATING - rating) / RATING_PER_TIME_PERCENT)
+                .clamp(0, MAX_TIME_SAVED_PERCENT),
+            _ => 0,
+        };
+        (100 - saved) as u32
+    }
+}
+
+impl fmt::Display for Opponent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let title = self.title.as_deref().unwrap_or("none");
+        let kind = if self.computer { "computer" } else { "human" };
+        match self.rating {
+            Some(rating) => write!(f, "{title} {rating} {kind} {}", self.name),
+            None => write!(f, "{title} none {kind} {}", self.name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_contempt() {
+        let kasparov = Opponent::parse("GM 2800 human Garry Kasparov").unwrap();
+        assert_eq!(kasparov.title.as_deref(), Some("GM"));
+        assert_eq!(kasparov.name, "Garry Kasparov");
+        assert!(!kasparov.computer);
+        assert_eq!(kasparov.contempt(), -30);
+        assert_eq!(kasparov.time_percent(), 100);
+        assert_eq!(kasparov.to_string(), "GM 2800 human Garry Kasparov");
+
+        let engine = Opponent::parse("none none computer Stockfish 17").unwrap();
+        assert_eq!((engine.title.as_deref(), engine.rating), (None, None));
+        assert_eq!(engine.contempt(), 0);
+        assert_eq!(engine.time_percent(), 100);
+
+        assert_eq!(
+            Opponent::parse("none 1000 human Beginner")
+                .unwrap()
+                .contempt(),
+            50
+        );
+        assert_eq!(
+            Opponent::parse("none 1900 human Club Player")
+                .unwrap()
+                .time_percent(),
+            85
+        );
+        assert_eq!(
+            Opponent::parse("none 1000 computer Weak Engine")
+                .unwrap()
+                .time_percent(),
+            100
+        );
+        assert!(Opponent::parse("GM 2800 alien Zorg").is_none());
+        assert!(Opponent::parse("").is_none());
+    }
+}
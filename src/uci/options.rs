@@ -0,0 +1,476 @@
+use std::{
+    sync::{Arc, OnceLock, atomic::Ordering},
+    time::Duration,
+};
+
+use shakmaty::variant::{Variant, VariantPosition};
+
+use super::opponent::Opponent;
+use super::{Engine, EngineOptions, VARIANTS};
+use crate::engine::SearchMode;
+use crate::eval::{EvalWeights, EvaluatorKind};
+use crate::logging;
+use crate::strength::{MAX_ELO, MIN_ELO};
+use crate::transposition::MAX_HASH_MB;
+
+/// The UCI option types, with what a valid value looks like for each.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OptionKind {
+    Check,
+    Spin { min: i64, max: i64 },
+    Combo(Vec<&'static str>),
+    String,
+    Button,
+}
+
+/// A value that has been checked against its option's [`OptionKind`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionValue<'a> {
+    Check(bool),
+    Spin(i64),
+    /// Always one of the declared vars, spelled as declared.
+    Combo(&'static str),
+    String(&'a str),
+    Button,
+}
+
+/// One entry of the registry: everything needed to announce, validate and apply an option.
+pub struct UciOption {
+    pub name: &'static str,
+    pub kind: OptionKind,
+    /// Current value, announced as the default in "option" lines.
+    current: fn(&EngineOptions) -> String,
+    apply: fn(&mut Engine, OptionValue) -> Result<(), String>,
+}
+
+impl UciOption {
+    /// The option's value in `options`, as it would be sent in "setoption".
+    pub fn value(&self, options: &EngineOptions) -> String {
+        (self.current)(options)
+    }
+
+    /// The "option name ..." line telling the GUI about this option.
+    pub fn declaration(&self, options: &EngineOptions) -> String {
+        let current = self.value(options);
+        match &self.kind {
+            OptionKind::Check => format!("option name {} type check default {current}", self.name),
+            OptionKind::Spin { min, max } => format!(
+                "option name {} type spin default {current} min {min} max {max}",
+                self.name
+            ),
+            OptionKind::Combo(vars) => {
+                let vars: String = vars.iter().map(|var| format!(" var {var}")).collect();
+                format!(
+                    "option name {} type combo default {current}{vars}",
+                    self.name
+                )
+            }
+            OptionKind::String => {
+                // The spec's way of announcing an empty string
+                let current = if current.is_empty() {
+                    "<empty>".to_owned()
+                } else {
+                    current
+                };
+                format!("option name {} type string default {current}", self.name)
+            }
+            OptionKind::Button => format!("option name {} type button", self.name),
+        }
+    }
+
+    /// Checks `value` against the option's type.
+    pub fn parse<'a>(&self, value: &'a str) -> Result<OptionValue<'a>, String> {
+        match &self.kind {
+            OptionKind::Check => super::parse_check(value)
+                .map(OptionValue::Check)
+                .ok_or_else(|| "expected true or false".to_owned()),
+            OptionKind::Spin { min, max } => value
+                .parse::<i64>()
+                .ok()
+                .filter(|number| (min..=max).contains(&number))
+                .map(OptionValue::Spin)
+                .ok_or_else(|| format!("expected a number from {min} to {max}")),
+            OptionKind::Combo(vars) => vars
+                .iter()
+                .find(|var| var.eq_ignore_ascii_case(value))
+                .map(|&var| OptionValue::Combo(var))
+                .ok_or_else(|| format!("expected one of {}", vars.join(", "))),
+            OptionKind::String => Ok(OptionValue::String(value)),
+            OptionKind::Button => Ok(OptionValue::Button),
+        }
+    }
+
+    /// Validates `value` and applies it to `engine`.
+    pub(super) fn set(&self, engine: &mut Engine, value: &str) -> Result<(), String> {
+        let value = self.parse(value)?;
+        (self.apply)(engine, value)
+    }
+}
+
+/// Looks up an option by name, ignoring case as the spec asks.
+pub fn find(name: &str) -> Option<&'static UciOption> {
+    registry()
+        .iter()
+        .find(|option| option.name.eq_ignore_ascii_case(name))
+}
+
+/// Every option the engine supports, in the order they are announced.
+pub fn registry() -> &'static [UciOption] {
+    static REGISTRY: OnceLock<Vec<UciOption>> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        vec![
+            UciOption {
+                name: "nick",
+                kind: OptionKind::String,
+                current: |options| options.nickname.clone(),
+                apply: |engine, value| {
+                    engine.options.nickname = string(value).to_owned();
+                    Ok(())
+                },
+            },
+            UciOption {
+                name: "Hash",
+                kind: OptionKind::Spin {
+                    min: 1,
+                    max: MAX_HASH_MB as i64,
+                },
+                current: |options| options.hash_mb.to_string(),
+                apply: |engine, value| {
+                    engine.options.hash_mb = spin(value) as usize;
+                    engine.worker.resize_table(engine.options.hash_mb);
+                    Ok(())
+                },
+            },
+            UciOption {
+                name: "Clear Hash",
+                kind: OptionKind::Button,
+                current: |_| String::new(),
+                apply: |engine, _| {
+                    engine.worker.new_game();
+                    Ok(())
+                },
+            },
+            UciOption {
+                name: "UCI_Chess960",
+                kind: OptionKind::Check,
+                current: |options| options.uci_chess960.to_string(),
+                apply: |engine, value| {
+                    engine.options.uci_chess960 = check(value);
+                    Ok(())
+                },
+            },
+            UciOption {
+                name: "Ponder",
+                kind: OptionKind::Check,
+                current: |options| options.ponder.to_string(),
+                apply: |engine, value| {
+                    engine.options.ponder = check(value);
+                    Ok(())
+                },
+            },
+            UciOption {
+                name: "MoveOverhead",
+                kind: OptionKind::Spin { min: 0, max: 5000 },
+                current: |options| options.move_overhead.as_millis().to_string(),
+                apply: |engine, value| {
+                    engine.options.move_overhead = Duration::from_millis(spin(value) as u64);
+                    Ok(())
+                },
+            },
+            UciOption {
+                // 0 makes a bare "go" search until "stop"
+                name: "DefaultMovetime",
+                kind: OptionKind::Spin {
+                    min: 0,
+                    max: 3_600_000,
+                },
+                current: |options| {
+                    options
+                        .default_movetime
+                        .map_or(0, |movetime| movetime.as_millis())
+                        .to_string()
+                },
+                apply: |engine, value| {
+                    let millis = spin(value) as u64;
+                    engine.options.default_movetime =
+                        (millis > 0).then(|| Duration::from_millis(millis));
+                    Ok(())
+                },
+            },
+            UciOption {
+                name: "UCI_AnalyseMode",
+                kind: OptionKind::Check,
+                current: |options| options.analyse_mode.to_string(),
+                apply: |engine, value| {
+                    engine.options.analyse_mode = check(value);
+                    Ok(())
+                },
+            },
+            UciOption {
+                name: "UCI_LimitStrength",
+                kind: OptionKind::Check,
+                current: |options| options.limit_strength.to_string(),
+                apply: |engine, value| {
+                    engine.options.limit_strength = check(value);
+                    Ok(())
+                },
+            },
+            UciOption {
+                name: "UCI_Elo",
+                kind: OptionKind::Spin {
+                    min: MIN_ELO,
+                    max: MAX_ELO,
+                },
+                current: |options| options.elo.to_string(),
+                apply: |engine, value| {
+                    engine.options.elo = spin(value);
+                    Ok(())
+                },
+            },
+            UciOption {
+                name: "UCI_ShowWDL",
+                kind: OptionKind::Check,
+                current: |options| options.show_wdl.to_string(),
+                apply: |engine, value| {
+                    engine.options.show_wdl = check(value);
+                    Ok(())
+                },
+            },
+            UciOption {
+                name: "UCI_Opponent",
+                kind: OptionKind::String,
+                current: |options| {
+                    options
+                        .opponent
+                        .as_ref()
+                        .map(ToString::to_string)
+                        .unwrap_or_default()
+                },
+                apply: |engine, value| {
+                    let value = string(value).trim();
+                    let opponent = if value.is_empty() {
+                        None
+                    } else {
+                        let opponent = Opponent::parse(value).ok_or_else(|| {
+                            "expected \"<title> <rating> <computer|human> <name>\"".to_owned()
+                        })?;
+                        tracing::info!(%opponent, contempt = opponent.contempt(), "new opponent");
+                        Some(opponent)
+                    };
+                    engine.options.opponent = opponent;
+                    Ok(())
+                },
+            },
+            UciOption {
+                name: "Contempt",
+                kind: OptionKind::Spin {
+                    min: -100,
+                    max: 100,
+                },
+                current: |options| options.contempt.to_string(),
+                apply: |engine, value| {
+                    engine.options.contempt = spin(value);
+                    Ok(())
+                },
+            },
+            UciOption {
+                name: "UCI_Variant",
+                kind: OptionKind::Combo(VARIANTS.iter().map(|variant| variant.uci()).collect()),
+                current: |options| options.uci_variant.uci().to_owned(),
+                apply: |engine, value| {
+                    let variant = Variant::from_uci(combo(value)).map_err(|e| e.to_string())?;
+                    engine.options.uci_variant = variant;
+                    engine.pos = VariantPosition::new(variant);
+                    engine.history.clear();
+                    Ok(())
+                },
+            },
+            UciOption {
+                name: "LogLevel",
+                kind: OptionKind::String,
+                current: |options| options.log_level.clone(),
+                apply: |engine, value| {
+                    if let Some(log) = logging::handle() {
+                        log.set_filter(string(value))?;
+                    }
+                    engine.options.log_level = string(value).to_owned();
+                    Ok(())
+                },
+            },
+            UciOption {
+                name: "LogFile",
+                kind: OptionKind::String,
+                current: |options| options.log_file.clone(),
+                apply: |engine, value| {
+                    if let Some(log) = logging::handle() {
+                        log.set_file(string(value)).map_err(|e| e.to_string())?;
+                    }
+                    engine.options.log_file = string(value).to_owned();
+                    Ok(())
+                },
+            },
+            UciOption {
+                name: "Evaluator",
+                kind: OptionKind::Combo(
+                    EvaluatorKind::ALL.iter().map(|kind| kind.name()).collect(),
+                ),
+                current: |options| options.evaluator.name().to_owned(),
+                apply: |engine, value| {
+                    engine.options.evaluator =
+                        EvaluatorKind::from_name(combo(value)).expect("Declared as a var");
+                    Ok(())
+                },
+            },
+            UciOption {
+                name: "EvalFile",
+                kind: OptionKind::String,
+                current: |options| options.eval_file.clone(),
+                apply: |engine, value| {
+                    // A file that fails to load leaves the current weights in place
+                    let path = match string(value) {
+                        "<empty>" => "",
+                        path => path,
+                    };
+                    let weights = match path {
+                        "" => None,
+                        path => Some(Arc::new(
+                            EvalWeights::load(path).map_err(|e| e.to_string())?,
+                        )),
+                    };
+                    engine.options.eval_file = path.to_owned();
+                    engine.options.eval_weights = weights;
+                    Ok(())
+                },
+            },
+            UciOption {
+                name: "SearchMode",
+                kind: OptionKind::Combo(SearchMode::ALL.iter().map(|mode| mode.name()).collect()),
+                current: |options| options.search_mode.name().to_owned(),
+                apply: |engine, value| {
+                    engine.options.search_mode =
+                        SearchMode::from_name(combo(value)).expect("Declared as a var");
+                    Ok(())
+                },
+            },
+            UciOption {
+                name: "NullMove",
+                kind: OptionKind::Check,
+                current: |options| options.search_features.null_move.to_string(),
+                apply: |engine, value| {
+                    engine.options.search_features.null_move = check(value);
+                    Ok(())
+                },
+            },
+            UciOption {
+                name: "LMR",
+                kind: OptionKind::Check,
+                current: |options| options.search_features.late_move_reductions.to_string(),
+                apply: |engine, value| {
+                    engine.options.search_features.late_move_reductions = check(value);
+                    Ok(())
+                },
+            },
+            UciOption {
+                name: "Quiescence",
+                kind: OptionKind::Check,
+                current: |options| options.search_features.quiescence.to_string(),
+                apply: |engine, value| {
+                    engine.options.search_features.quiescence = check(value);
+                    Ok(())
+                },
+            },
+            UciOption {
+                name: "Explain",
+                kind: OptionKind::Check,
+                current: |options| options.explain.to_string(),
+                apply: |engine, value| {
+                    engine.options.explain = check(value);
+                    Ok(())
+                },
+            },
+            UciOption {
+                name: "PrettyOutput",
+                kind: OptionKind::Check,
+                current: |options| options.pretty_output.to_string(),
+                apply: |engine, value| {
+                    engine.options.pretty_output = check(value);
+                    Ok(())
+                },
+            },
+            UciOption {
+                name: "JsonOutput",
+                kind: OptionKind::Check,
+                current: |options| options.json_output.to_string(),
+                apply: |engine, value| {
+                    engine.options.json_output = check(value);
+                    engine
+                        .json_output
+                        .store(engine.options.json_output, Ordering::Relaxed);
+                    Ok(())
+                },
+            },
+        ]
+    })
+}
+
+// Setters only ever see values of their own option's kind, see `UciOption::parse`
+
+fn check(value: OptionValue) -> bool {
+    match value {
+        OptionValue::Check(value) => value,
+        _ => unreachable!("Check option got {value:?}"),
+    }
+}
+
+fn spin(value: OptionValue) -> i64 {
+    match value {
+        OptionValue::Spin(value) => value,
+        _ => unreachable!("Spin option got {value:?}"),
+    }
+}
+
+fn combo(value: OptionValue) -> &'static str {
+    match value {
+        OptionValue::Combo(value) => value,
+        _ => unreachable!("Combo option got {value:?}"),
+    }
+}
+
+fn string<'a>(value: OptionValue<'a>) -> &'a str {
+    match value {
+        OptionValue::String(value) => value,
+        _ => unreachable!("String option got {value:?}"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_values_are_validated_by_kind() {
+        let spin = UciOption {
+            name: "Spin",
+            kind: OptionKind::Spin { min: 1, max: 8 },
+            current: |_| "1".to_owned(),
+            apply: |_, _| Ok(()),
+        };
+        assert_eq!(spin.parse("8"), Ok(OptionValue::Spin(8)));
+        assert!(spin.parse("9").is_err());
+        assert_eq!(
+            spin.declaration(&EngineOptions::default()),
+            "option name Spin type spin default 1 min 1 max 8"
+        );
+
+        let evaluator = find("evaluator").unwrap();
+        assert_eq!(evaluator.parse("pesto"), Ok(OptionValue::Combo("PeSTO")));
+        assert!(evaluator.parse("NNUE").is_err());
+
+        let clear_hash = find("clear hash").unwrap();
+        assert_eq!(clear_hash.parse("anything"), Ok(OptionValue::Button));
+        assert_eq!(
+            clear_hash.declaration(&EngineOptions::default()),
+            "option name Clear Hash type button"
+        );
+    }
+}
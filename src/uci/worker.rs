@@ -0,0 +1,468 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Condvar, Mutex, mpsc},
+    thread,
+    time::Duration,
+};
+
+use shakmaty::{CastlingMode, Move, variant::VariantPosition};
+
+use super::crash::{self, CrashContext};
+use super::{InfoPrinter, uci_score};
+use crate::controller::SearchController;
+use crate::engine::{
+    IterationInfo, SearchFeatures, SearchLimits, SearchMode, SearchObserver, SearchResult,
+};
+use crate::error::Error;
+use crate::eval::Evaluator;
+use crate::explain::{self, Explanation};
+use crate::protocol::ResponseWriter;
+use crate::time_manager::{TimeBudget, TimeKeeper, TimeManager};
+use crate::transposition::TranspositionTable;
+
+/// Alternatives to the chosen move shown in explain mode.
+const EXPLAIN_ALTERNATIVES: usize = 4;
+/// Moves of each line shown in explain mode.
+const EXPLAIN_PV_LENGTH: usize = 6;
+
+/// Everything the worker needs to answer one "go".
+pub(super) struct SearchJob {
+    pub position: VariantPosition,
+    pub limits: SearchLimits,
+    pub evaluator: Arc<dyn Evaluator>,
+    pub features: SearchFeatures,
+    pub mode: SearchMode,
+    pub controller: SearchController,
+    pub time_manager: Arc<dyn TimeManager>,
+    pub budget: TimeBudget,
+    /// Print a table for people to read instead of info lines.
+    pub pretty_output: bool,
+    /// Add win/draw/loss chances to info lines.
+    pub show_wdl: bool,
+    /// Report hash and time diagnostics as info strings, as asked by "debug on".
+    pub debug: bool,
+    /// Compare the chosen move with the alternatives once the search is done.
+    pub explain: bool,
+    pub crash_context: CrashContext,
+}
+
+enum Job {
+    Search(Box<SearchJob>),
+    NewGame,
+    /// Replaces the table with an empty one of this many megabytes.
+    Resize(usize),
+}
+
+#[derive(Default)]
+struct WorkerState {
+    searching: bool,
+    /// The last search panicked; reported once and then cleared.
+    aborted: bool,
+    /// Size of the transposition table after the last finished job.
+    table_entries: usize,
+}
+
+type SharedState = Arc<(Mutex<WorkerState>, Condvar)>;
+
+/// The long-lived search thread. Searches are handed over one at a time, and the
+/// transposition table stays with the thread from one move to the next.
+pub(super) struct SearchWorker {
+    jobs: mpsc::Sender<Job>,
+    state: SharedState,
+}
+
+impl SearchWorker {
+    /// Starts the thread with a table of `table_size_mb` megabytes. It ends by
+    /// itself once the worker is dropped.
+    pub fn spawn(output: Arc<dyn ResponseWriter>, table_size_mb: usize) -> Self {
+        let (jobs, job_receiver) = mpsc::channel();
+        let state = SharedState::default();
+
+        crash::install_hook();
+        let thread_state = Arc::clone(&state);
+        thread::spawn(move || {
+            let mut transposition_table = TranspositionTable::new(table_size_mb);
+            for job in job_receiver {
+                match job {
+                    Job::Search(job) => {
+                        crash::set_context(Some(job.crash_context.clone()));
+                        let controller = job.controller.clone();
+                        let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+                            run_search(*job, &mut transposition_table, &output)
+                        }));
+                        crash::set_context(None);
+                        let aborted = outcome.is_err();
+                        if aborted {
+                            // A half-written table can't be trusted anymore
+                            transposition_table.clear();
+                            // Otherwise the search would still count as running
+                            // and later "go"s would be ignored
+                            controller.stop();
+                            if let Some(path) = crash::take_report_path() {
+                                output.write_response(&format!(
+                                    "info string search crashed, report written to {}",
+                                    path.display()
+                                ));
+                            }
+                            output.write_response("bestmove 0000");
+                        }
+
+                        let (lock, finished) = &*thread_state;
+                        let mut state = lock.lock().expect("Worker state lock poisoned");
+                        state.searching = false;
+                        state.aborted |= aborted;
+                        state.table_entries = transposition_table.len();
+                        finished.notify_all();
+                    }
+                    Job::NewGame => {
+                        transposition_table.clear();
+                        thread_state
+                            .0
+                            .lock()
+                            .expect("Worker state lock poisoned")
+                            .table_entries = 0;
+                    }
+                    Job::Resize(size_mb) => {
+                        // Free the old table before allocating the new one
+                        drop(std::mem::replace(
+                            &mut transposition_table,
+                            TranspositionTable::with_entries(1),
+                        ));
+                        transposition_table = TranspositionTable::new(size_mb);
+                        thread_state
+                            .0
+                            .lock()
+                            .expect("Worker state lock poisoned")
+                            .table_entries = 0;
+                    }
+                }
+            }
+        });
+
+        Self { jobs, state }
+    }
+
+    /// Hands a search to the worker; it prints info lines and the bestmove itself.
+    pub fn search(&self, job: SearchJob) {
+        self.state
+            .0
+            .lock()
+            .expect("Worker state lock poisoned")
+            .searching = true;
+        self.jobs
+            .send(Job::Search(Box::new(job)))
+            .expect("Search worker thread is gone");
+    }
+
+    /// Forgets everything learned in the previous game.
+    pub fn new_game(&self) {
+        self.jobs
+            .send(Job::NewGame)
+            .expect("Search worker thread is gone");
+    }
+
+    /// Gives the transposition table a new size, emptying it.
+    pub fn resize_table(&self, size_mb: usize) {
+        self.jobs
+            .send(Job::Resize(size_mb))
+            .expect("Search worker thread is gone");
+    }
+
+    /// Entries in the transposition table as of the last finished search.
+    pub fn table_entries(&self) -> usize {
+        self.state
+            .0
+            .lock()
+            .expect("Worker state lock poisoned")
+            .table_entries
+    }
+
+    pub fn waiter(&self) -> SearchWaiter {
+        SearchWaiter(Arc::clone(&self.state))
+    }
+}
+
+/// Lets other threads wait for the worker's current search.
+#[derive(Clone)]
+pub(super) struct SearchWaiter(SharedState);
+
+impl SearchWaiter {
+    /// Blocks until no search is running; fails if the last one crashed.
+    pub fn wait(&self) -> Result<(), Error> {
+        let (lock, finished) = &*self.0;
+        let state = lock.lock().expect("Worker state lock poisoned");
+        let mut state = finished
+            .wait_while(state, |state| state.searching)
+            .expect("Worker state lock poisoned");
+        if std::mem::take(&mut state.aborted) {
+            return Err(Error::SearchAborted);
+        }
+        Ok(())
+    }
+
+    /// Returns whether a search crashed since the last check, without waiting.
+    pub fn take_aborted(&self) -> bool {
+        let mut state = self.0.0.lock().expect("Worker state lock poisoned");
+        std::mem::take(&mut state.aborted)
+    }
+}
+
+fn run_search(
+    job: SearchJob,
+    transposition_table: &mut TranspositionTable,
+    output: &Arc<dyn ResponseWriter>,
+) {
+    let SearchJob {
+        position,
+        limits,
+        evaluator,
+        features,
+        mode,
+        controller,
+        time_manager,
+        budget,
+        pretty_output,
+        show_wdl,
+        debug,
+        explain,
+        crash_context: _,
+    } = job;
+
+    let mut observer = TimeKeeper::new(
+        &*time_manager,
+        budget,
+        controller.clone(),
+        DepthRecorder(InfoPrinter::new(
+            Arc::clone(output),
+            pretty_output.then(|| position.clone()),
+            show_wdl,
+        )),
+    );
+    let table_move = explain::table_move(&position, transposition_table);
+    transposition_table.reset_stats();
+    let search_result = mode.algorithm().search(
+        &position,
+        &limits,
+        &*evaluator,
+        features,
+        &controller,
+        transposition_table,
+        &mut observer,
+    );
+    // The GUI expects the bestmove of an infinite search only after "stop",
+    // and that of a ponder search not before "ponderhit"
+    if limits.infinite {
+        controller.wait_until_stopped();
+    } else if limits.ponder {
+        controller.wait_while_pondering();
+    }
+    // A depth or mate limit can end the search before anyone stopped it
+    controller.stop();
+
+    if debug {
+        for line in debug_lines(&budget, observer.stopped_after(), transposition_table) {
+            output.write_response(&line);
+        }
+    }
+
+    output.write_response(&best_move_response(&search_result, &**output));
+
+    // Comparing every root move takes a while, so the GUI gets its move first
+    if let (true, Ok(result)) = (explain, &search_result) {
+        let explanation = explain::explain(
+            &position,
+            result,
+            &*evaluator,
+            features,
+            transposition_table,
+            table_move,
+            EXPLAIN_ALTERNATIVES,
+        );
+        match explanation {
+            Ok(explanation) => {
+                for line in explanation_lines(&explanation) {
+                    output.write_response(&line);
+                }
+            }
+            Err(error) => output.write_response(&format!("info string explain failed: {error}")),
+        }
+    }
+}
+
+/// Diagnostics for "debug on": how well the hash table served the search and
+/// what the time manager decided.
+fn debug_lines(
+    budget: &TimeBudget,
+    stopped_after: Option<Duration>,
+    table: &TranspositionTable,
+) -> Vec<String> {
+    let millis = |limit: Duration| match limit {
+        Duration::MAX => "none".to_owned(),
+        limit => format!("{}ms", limit.as_millis()),
+    };
+    let hit_percent = table.hits() as f64 * 100.0 / table.probes().max(1) as f64;
+    let stop = match stopped_after {
+        Some(elapsed) => format!("time manager stopped at {}", millis(elapsed)),
+        None => "not stopped by time manager".to_owned(),
+    };
+    vec![
+        format!(
+            "info string debug hash hits {} of {} probes ({hit_percent:.1}%), {} of {} entries used",
+            table.hits(),
+            table.probes(),
+            table.len(),
+            table.capacity(),
+        ),
+        format!(
+            "info string debug time soft {} hard {}, {stop}",
+            millis(budget.soft),
+            millis(budget.hard),
+        ),
+    ]
+}
+
+/// The explanation as info strings: where the move came from, then the chosen
+/// move and the alternatives with the terms that tell them apart.
+fn explanation_lines(explanation: &Explanation) -> Vec<String> {
+    let yes_no = |flag: bool| if flag { "yes" } else { "no" };
+    let mut lines = vec![format!(
+        "info string explain depth {} hashmove {}",
+        explanation.depth,
+        yes_no(explanation.from_table),
+    )];
+    for (rank, root_move) in explanation.moves.iter().enumerate() {
+        let pv: Vec<String> = root_move
+            .pv
+            .iter()
+            .take(EXPLAIN_PV_LENGTH)
+            .map(|m| m.to_uci(CastlingMode::Standard).to_string())
+            .collect();
+        let mut line = format!(
+            "info string explain {} {} score {} pv {}",
+            if rank == 0 { "chosen" } else { "alternative" },
+            root_move.root_move.to_uci(CastlingMode::Standard),
+            uci_score(root_move.score),
+            pv.join(" ")
+        );
+        if !root_move.term_deltas.is_empty() {
+            let deltas: Vec<String> = root_move
+                .term_deltas
+                .iter()
+                .map(|(name, delta)| format!("{name} {delta:+}"))
+                .collect();
+            line.push_str(&format!(" chosen move better by: {}", deltas.join(", ")));
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// Keeps the crash context's depth up to date and passes everything on.
+struct DepthRecorder<O: SearchObserver>(O);
+
+impl<O: SearchObserver> SearchObserver for DepthRecorder<O> {
+    fn on_iteration(&mut self, info: &IterationInfo) {
+        crash::record_depth(info.depth);
+        self.0.on_iteration(info);
+    }
+
+    fn on_new_best_move(&mut self, info: &IterationInfo) {
+        self.0.on_new_best_move(info);
+    }
+
+    fn on_root_move(&mut self, depth: u64, root_move: Move, number: usize) {
+        self.0.on_root_move(depth, root_move, number);
+    }
+
+    fn on_search_finished(&mut self, result: &SearchResult) {
+        self.0.on_search_finished(result);
+    }
+}
+
+fn best_move_response(
+    search_result: &Result<SearchResult, Error>,
+    output: &dyn ResponseWriter,
+) -> String {
+    match search_result {
+        Ok(result) => {
+            output.write_response(&format!(
+                "info nodes {} time {}",
+                result.nodes,
+                result.time.as_millis()
+            ));
+            let best_move = result.best_move.to_uci(CastlingMode::Standard);
+            match result.ponder {
+                Some(ponder) => format!(
+                    "bestmove {best_move} ponder {}",
+                    ponder.to_uci(CastlingMode::Standard)
+                ),
+                None => format!("bestmove {best_move}"),
+            }
+        }
+        Err(error) => {
+            // The GUI still expects a bestmove, "0000" is the null move
+            output.write_response(&format!("info string {error}"));
+            "bestmove 0000".to_owned()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::atomic::AtomicBool, time::Duration};
+
+    use super::*;
+    use crate::time_manager::FixedTime;
+
+    #[test]
+    fn test_worker_answers_consecutive_searches() {
+        let (sender, responses) = mpsc::channel();
+        let worker = SearchWorker::spawn(Arc::new(sender), 1);
+        let limits = SearchLimits {
+            depth: Some(2),
+            ..SearchLimits::default()
+        };
+
+        for _ in 0..2 {
+            worker.search(SearchJob {
+                position: VariantPosition::default(),
+                limits: limits.clone(),
+                evaluator: Arc::new(crate::eval::PestoEvaluator),
+                features: SearchFeatures::default(),
+                mode: SearchMode::AlphaBeta,
+                controller: SearchController::new(Arc::new(AtomicBool::new(true))),
+                time_manager: Arc::new(FixedTime),
+                budget: TimeBudget {
+                    soft: Duration::from_secs(10),
+                    hard: Duration::from_secs(10),
+                },
+                pretty_output: false,
+                show_wdl: false,
+                debug: false,
+                explain: true,
+                crash_context: CrashContext::default(),
+            });
+            worker.waiter().wait().unwrap();
+        }
+
+        let responses: Vec<String> = responses.try_iter().collect();
+        let best_moves = responses
+            .iter()
+            .filter(|line| line.starts_with("bestmove "))
+            .count();
+        assert_eq!(best_moves, 2);
+        let explanations = responses
+            .iter()
+            .filter(|line| line.starts_with("info string explain chosen "))
+            .count();
+        assert_eq!(explanations, 2);
+        // The GUI shouldn't have to wait for the explanation
+        let first_best_move = responses
+            .iter()
+            .position(|line| line.starts_with("bestmove "));
+        let first_explanation = responses
+            .iter()
+            .position(|line| line.starts_with("info string explain "));
+        assert!(first_best_move < first_explanation);
+    }
+}
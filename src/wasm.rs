@@ -0,0 +1,138 @@
+use std::{
+    sync::{Arc, atomic::AtomicBool},
+    time::Duration,
+};
+
+use js_sys::Function;
+use shakmaty::{CastlingMode, Chess, Position, fen::Fen, uci::UciMove, variant::VariantPosition};
+use wasm_bindgen::prelude::*;
+
+use crate::controller::SearchController;
+use crate::engine::{
+    IterationInfo, SearchFeatures, SearchLimits, SearchObserver, iterative_deepening,
+};
+use crate::eval::PestoEvaluator;
+use crate::transposition::TranspositionTable;
+
+/// Engine handle exposed to JavaScript.
+///
+/// Threads are not available in the browser, so searches run synchronously.
+/// Build with `cargo rustc --crate-type cdylib` as described for the `wasm` feature in Cargo.toml.
+#[wasm_bindgen]
+pub struct WasmEngine {
+    position: Chess,
+    transposition_table: TranspositionTable,
+}
+
+#[wasm_bindgen]
+impl WasmEngine {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self {
+            position: Chess::default(),
+            transposition_table: TranspositionTable::default(),
+        }
+    }
+
+    /// Sets up the board from a FEN string (or "startpos") and space separated UCI moves.
+    #[wasm_bindgen(js_name = setPosition)]
+    pub fn set_position(&mut self, fen: &str, moves: &str) -> Result<(), JsError> {
+        let mut position = if fen == "startpos" {
+            Chess::default()
+        } else {
+            let fen: Fen = fen.parse()?;
+            fen.into_position(CastlingMode::Standard)?
+        };
+
+        for move_str in moves.split_whitespace() {
+            let uci_move: UciMove = move_str.parse()?;
+            let m = uci_move.to_move(&position)?;
+            position.play_unchecked(m);
+        }
+
+        self.position = position;
+        Ok(())
+    }
+
+    /// Clears the transposition table before starting a new game.
+    #[wasm_bindgen(js_name = newGame)]
+    pub fn new_game(&mut self) {
+        self.position = Chess::default();
+        self.transposition_table.clear();
+    }
+
+    /// Searches the current position and returns the best move in UCI notation.
+    ///
+    /// Deepening stops at `max_depth` or once `movetime_ms` has passed; an infinite
+    /// `movetime_ms` leaves only the depth. After every iteration
+    /// `on_info(depth, score, pv)` is called, with the pv as UCI moves.
+    pub fn search(
+        &mut self,
+        max_depth: u32,
+        movetime_ms: f64,
+        on_info: &Function,
+    ) -> Result<String, JsError> {
+        let limits = SearchLimits {
+            depth: Some(u64::from(max_depth.max(1))),
+            movetime: Duration::try_from_secs_f64(movetime_ms.max(0.0) / 1000.0).ok(),
+            ..SearchLimits::default()
+        };
+        let controller =
+            SearchController::new(Arc::new(AtomicBool::new(true))).with_limits(&limits);
+        let mut reporter = JsReporter {
+            on_info,
+            controller: controller.clone(),
+            callback_failed: false,
+        };
+
+        let result = iterative_deepening(
+            &VariantPosition::from(self.position.clone()),
+            &limits,
+            &PestoEvaluator,
+            SearchFeatures::default(),
+            &controller,
+            &mut self.transposition_table,
+            &mut reporter,
+        )?;
+        if reporter.callback_failed {
+            return Err(JsError::new("on_info callback threw an exception"));
+        }
+
+        Ok(result.best_move.to_uci(CastlingMode::Standard).to_string())
+    }
+}
+
+/// Forwards iterations to the JS callback and ends the search if the callback throws.
+struct JsReporter<'a> {
+    on_info: &'a Function,
+    controller: SearchController,
+    callback_failed: bool,
+}
+
+impl SearchObserver for JsReporter<'_> {
+    fn on_iteration(&mut self, info: &IterationInfo) {
+        let pv = info
+            .pv
+            .iter()
+            .map(|m| m.to_uci(CastlingMode::Standard).to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let call_result = self.on_info.call3(
+            &JsValue::NULL,
+            &JsValue::from(info.depth as f64),
+            &JsValue::from(info.score as f64),
+            &JsValue::from_str(&pv),
+        );
+
+        self.callback_failed |= call_result.is_err();
+        if self.callback_failed {
+            self.controller.stop();
+        }
+    }
+}
+
+impl Default for WasmEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,47 @@
+use crate::engine::mate_in_moves;
+
+/// Score in centipawns at which the side to move wins half of its games.
+const EVEN_WIN_SCORE: f64 = 200.0;
+/// How quickly winning chances grow with the score, in centipawns.
+const WIN_SPREAD: f64 = 75.0;
+
+/// Chances of a win, draw and loss for the side to move, in permille, at
+/// `score`. A logistic curve fit by eye: equal positions are mostly drawn and
+/// two pawns up wins about half the time.
+pub fn wdl(score: i64) -> (u32, u32, u32) {
+    match mate_in_moves(score) {
+        Some(moves) if moves > 0 => (1000, 0, 0),
+        Some(_) => (0, 0, 1000),
+        None => {
+            let win = win_permille(score as f64);
+            let loss = win_permille(-score as f64);
+            (win, 1000 - win - loss, loss)
+        }
+    }
+}
+
+fn win_permille(score: f64) -> u32 {
+    (1000.0 / (1.0 + ((EVEN_WIN_SCORE - score) / WIN_SPREAD).exp())).round() as u32
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::eval::MATE_SCORE;
+
+    #[test]
+    fn test_wdl() {
+        let (win, draw, loss) = wdl(0);
+        assert_eq!(win, loss);
+        assert!(draw > 800);
+        assert_eq!(wdl(200).0, 500);
+        assert!(wdl(800).0 > 990);
+
+        let (win, draw, loss) = wdl(-350);
+        assert_eq!(win + draw + loss, 1000);
+        assert!(loss > win);
+
+        assert_eq!(wdl(MATE_SCORE - 3), (1000, 0, 0));
+        assert_eq!(wdl(-MATE_SCORE + 2), (0, 0, 1000));
+    }
+}